@@ -4,7 +4,7 @@ use std::{
   fmt::Debug,
   fs::{read_to_string, write},
   io,
-  path::Path,
+  path::{Component, Path, PathBuf},
   sync::Arc,
 };
 
@@ -79,7 +79,7 @@ fn perform_compaction(base_dir: &Path, package_file: &Path, out_file: &Path, inc
     println!("reading from {}", base_dir.display());
   }
 
-  let new_compact_file = load_files_to_edn(package_file, base_dir, verbose)?;
+  let new_compact_file = load_files_to_edn(package_file, base_dir, out_file, verbose)?;
   let has_old_file = out_file.exists();
   let changes = if has_old_file {
     let old_compact_data = cirru_edn::parse(&read_file(out_file)?).map_err(io_err)?;
@@ -194,21 +194,22 @@ fn find_file_changes(old_file: &FileInSnapShot, new_file: &FileInSnapShot) -> Re
   })
 }
 
-fn load_files_to_edn(package_file: &Path, base_dir: &Path, verbose: bool) -> Result<Edn, io::Error> {
+fn load_files_to_edn(package_file: &Path, base_dir: &Path, out_file: &Path, verbose: bool) -> Result<Edn, io::Error> {
   let mut dict = EdnMapView::default();
 
   let content = read_file(package_file)?;
   let package_data = cirru_edn::parse(&content).map_err(io_err)?;
+  let mut configs = package_data.view_map().map_err(io_err)?;
 
-  let pkg = package_data
-    .view_map()
-    .map_err(io_err)?
-    .get_or_nil("package")
-    .read_str()
-    .map_err(io_err)?;
+  let pkg = configs.get_or_nil("package").read_str().map_err(io_err)?;
+
+  if let Some(modules_dir) = calcit::read_modules_dir(&configs).map_err(io_err)? {
+    let rebased = rebase_modules_dir(&modules_dir, package_file, out_file)?;
+    configs.insert_key("modules-dir", Edn::Str(rebased.into()));
+  }
 
   dict.insert_key("package", Edn::Str(pkg));
-  dict.insert_key("configs", package_data);
+  dict.insert_key("configs", configs.into());
 
   let mut files = EdnMapView::default();
 
@@ -292,6 +293,85 @@ pub struct TopLevelBundleCalcit {
 }
 
 // simulate an IO error with String
+/// `:modules-dir` is relative to `deps.cirru`, while `cr` reads it relative to the compact file
+fn rebase_modules_dir(modules_dir: &str, package_file: &Path, out_file: &Path) -> io::Result<String> {
+  let parent_of = |file: &Path| match file.parent() {
+    Some(p) if !p.as_os_str().is_empty() => p.canonicalize(),
+    _ => Path::new(".").canonicalize(),
+  };
+  let from = parent_of(package_file)?;
+  let to = parent_of(out_file)?;
+  if from == to {
+    return Ok(modules_dir.to_owned());
+  }
+
+  // normalize `..` in the target, since the folder may not exist yet
+  let mut target = PathBuf::new();
+  for c in from.join(modules_dir).components() {
+    match c {
+      Component::ParentDir => {
+        target.pop();
+      }
+      Component::CurDir => {}
+      _ => target.push(c),
+    }
+  }
+
+  let common = to.components().zip(target.components()).take_while(|(a, b)| a == b).count();
+  let mut relative = PathBuf::new();
+  for _ in to.components().skip(common) {
+    relative.push("..");
+  }
+  for c in target.components().skip(common) {
+    relative.push(c);
+  }
+  match relative.to_str() {
+    Some("") => Ok(String::from("./")),
+    Some(s) => Ok(format!("{s}/")),
+    None => Err(io_err(format!("invalid modules dir: {}", relative.display()))),
+  }
+}
+
 fn io_err(e: String) -> io::Error {
   io::Error::new(io::ErrorKind::InvalidData, e)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::fs;
+
+  /// folders need to exist since paths are canonicalized
+  fn demo_dirs(name: &str) -> io::Result<PathBuf> {
+    let root = std::env::temp_dir().join(format!("bundle-calcit-{}-{}", name, std::process::id()));
+    fs::create_dir_all(root.join("app/dist"))?;
+    Ok(root)
+  }
+
+  #[test]
+  fn same_folder_keeps_modules_dir() -> io::Result<()> {
+    let root = demo_dirs("same")?;
+    let app = root.join("app");
+    let rebased = rebase_modules_dir("../modules/", &app.join("deps.cirru"), &app.join("compact.cirru"))?;
+    assert_eq!(rebased, "../modules/");
+    fs::remove_dir_all(root)
+  }
+
+  #[test]
+  fn rebase_parent_dirs() -> io::Result<()> {
+    let root = demo_dirs("parent")?;
+    let app = root.join("app");
+    let out = app.join("dist/compact.cirru");
+    assert_eq!(rebase_modules_dir("../modules/", &app.join("deps.cirru"), &out)?, "../../modules/");
+    assert_eq!(
+      rebase_modules_dir("./.calcit-modules/", &app.join("deps.cirru"), &out)?,
+      "../.calcit-modules/"
+    );
+    assert_eq!(rebase_modules_dir("dist/", &app.join("deps.cirru"), &out)?, "./");
+    assert_eq!(
+      rebase_modules_dir("../app/lib/../modules/", &app.join("dist/deps.cirru"), &app.join("compact.cirru"))?,
+      "app/modules/"
+    );
+    fs::remove_dir_all(root)
+  }
+}
@@ -1,17 +1,27 @@
-//! CLI tool to download packages from github,
+//! CLI tool to download packages from github, other git servers, local folders or tarballs,
 //! packages are defined in `deps.cirru` file
 //!
-//! files are stored in `~/.config/calcit/modules/`.
-
+//! files are stored in `~/.config/calcit/modules/`, or in `:modules-dir` of `deps.cirru` for a project-local folder.
+//! with `:versioned-modules true`, each dependency is cloned into `{folder}@{version}/`
+//! so that different versions of a library coexist.
+//! packages with their own `deps.cirru` bring in their dependencies too, different versions of one package are reported as conflicts.
+//! resolved commits are recorded in `deps.lock.cirru`, run `caps update` to refresh them.
+//! hashes of `compact.cirru` of modules are recorded there too, and checked by `cr` on start.
+//! `caps --offline` installs from local copies only.
+
+mod dep_source;
 mod git;
+mod lockfile;
 
 use argh::{self, FromArgs};
 
 use cirru_edn::Edn;
 use colored::*;
+use dep_source::DepSource;
 use git::*;
+use lockfile::{DepsLock, LockedDep};
 use std::{
-  collections::HashMap,
+  collections::{HashMap, HashSet},
   fs,
   path::{Path, PathBuf},
   sync::Arc,
@@ -21,21 +31,26 @@ use std::{
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct PackageDeps {
   calcit_version: Option<String>,
-  dependencies: HashMap<Arc<str>, Arc<str>>,
+  dependencies: HashMap<Arc<str>, DepSource>,
+  /// relative to `deps.cirru`
+  modules_dir: Option<Arc<str>>,
+  versioned_modules: bool,
 }
 
 impl TryFrom<Edn> for PackageDeps {
   type Error = String;
 
+  #[allow(clippy::mutable_key_type)]
   fn try_from(value: Edn) -> Result<Self, Self::Error> {
     let deps_info = value.view_map()?;
     let dict = deps_info.get_or_nil("dependencies").view_map()?.0;
 
-    let mut deps: HashMap<Arc<str>, Arc<str>> = HashMap::new();
+    let mut deps: HashMap<Arc<str>, DepSource> = HashMap::new();
     for (k, v) in &dict {
-      match (k, v) {
-        (Edn::Str(k), Edn::Str(v)) => {
-          deps.insert(k.to_owned(), v.to_owned());
+      match k {
+        Edn::Str(k) => {
+          let source = DepSource::try_from(v).map_err(|e| format!("invalid dependency {}: {}", k, e))?;
+          deps.insert(k.to_owned(), source);
         }
         _ => {
           return Err(format!("invalid dependency: {} {}", k, v));
@@ -47,130 +62,543 @@ impl TryFrom<Edn> for PackageDeps {
       Edn::Nil => None,
       v => return Err(format!("invalid calcit-version: {}", v)),
     };
+    let versioned_modules = match deps_info.get_or_nil("versioned-modules") {
+      Edn::Bool(b) => b,
+      Edn::Nil => false,
+      v => return Err(format!("invalid versioned-modules: {}", v)),
+    };
     Ok(PackageDeps {
       calcit_version: expected_version,
       dependencies: deps,
+      modules_dir: calcit::read_modules_dir(&deps_info)?,
+      versioned_modules,
     })
   }
 }
 
+impl PackageDeps {
+  /// local paths in dependencies are relative to the folder of `deps.cirru`
+  fn resolve_paths(&mut self, base_dir: &Path) {
+    for source in self.dependencies.values_mut() {
+      source.resolve_paths(base_dir);
+    }
+  }
+}
+
 pub fn main() -> Result<(), String> {
   // parse deps.cirru
 
   let cli_args: TopLevelCaps = argh::from_env();
 
-  // if file exists
+  let deps_file = if Path::new(&cli_args.input).exists() {
+    cli_args.input.to_owned()
+  } else if Path::new("package.cirru").exists() {
+    // be compatible with old name
+    "package.cirru".to_owned()
+  } else {
+    eprintln!("Error: no {} found!", cli_args.input);
+    std::process::exit(1);
+  };
 
-  if Path::new(&cli_args.input).exists() {
-    let content = fs::read_to_string(&cli_args.input).map_err(|e| e.to_string())?;
-    let parsed = cirru_edn::parse(&content)?;
-    let deps: PackageDeps = parsed.try_into()?;
+  let content = fs::read_to_string(&deps_file).map_err(|e| e.to_string())?;
+  let parsed = cirru_edn::parse(&content)?;
+  let mut deps: PackageDeps = parsed.try_into()?;
+  deps.resolve_paths(Path::new(&deps_file).parent().unwrap_or(Path::new(".")));
+
+  if let Some(version) = &deps.calcit_version {
+    if version != CALCIT_VERSION {
+      eprintln!(
+        "[Warn] calcit version mismatch, deps.cirru expected {}, running {}",
+        version, CALCIT_VERSION,
+      );
+    }
+  }
 
-    if let Some(version) = &deps.calcit_version {
-      if version != CALCIT_VERSION {
-        eprintln!(
-          "[Warn] calcit version mismatch, deps.cirru expected {}, running {}",
-          version, CALCIT_VERSION,
-        );
-      }
+  let modules = ModulesLayout {
+    dir: if cli_args.local_debug {
+      println!("{}", "  [DEBUG] local debug mode, cloning to test-modules/".yellow());
+      dirs::home_dir().ok_or("no config dir")?.join(".config/calcit/test-modules")
+    } else if let Some(dir) = &deps.modules_dir {
+      Path::new(&deps_file).with_file_name(&**dir)
+    } else {
+      calcit::global_module_folder()?
+    },
+    versioned: deps.versioned_modules,
+  };
+
+  if cli_args.offline {
+    // offline installing only runs local git commands, in case any of them reaches for remotes,
+    // git refuses protocols other than `file`
+    std::env::set_var("GIT_ALLOW_PROTOCOL", "file");
+  }
+
+  let lock_path = DepsLock::path_for(&deps_file);
+  match &cli_args.subcommand {
+    Some(SubCommand::Outdated(_)) => outdated_tags(deps.dependencies, &modules),
+    Some(SubCommand::Update(_)) => {
+      // branches are moved to latest commits too
+      let options = TopLevelCaps {
+        pull_branch: true,
+        ..cli_args
+      };
+      download_deps(deps.dependencies, None, &lock_path, &modules, options)
+    }
+    None => {
+      let lock = DepsLock::read(&lock_path)?;
+      download_deps(deps.dependencies, lock, &lock_path, &modules, cli_args)
     }
+  }
+}
 
-    if cli_args.subcommand.is_some() {
-      outdated_tags(deps.dependencies)?;
+/// where dependencies are cloned into
+#[derive(Debug, Clone)]
+struct ModulesLayout {
+  dir: PathBuf,
+  /// folders are named `{folder}@{version}`, otherwise only `{folder}`
+  versioned: bool,
+}
+
+impl ModulesLayout {
+  fn folder_of(&self, org_and_folder: &str, source: &DepSource) -> Result<String, String> {
+    let (_org, folder) = org_and_folder.split_once('/').ok_or("invalid name")?;
+    if self.versioned {
+      Ok(format!("{}@{}", folder, source.folder_suffix()))
     } else {
-      download_deps(deps.dependencies, cli_args)?;
+      Ok(folder.to_owned())
     }
+  }
+}
 
-    Ok(())
-  } else if Path::new("package.cirru").exists() {
-    // be compatible with old name
-    let content = fs::read_to_string("package.cirru").map_err(|e| e.to_string())?;
-    let parsed = cirru_edn::parse(&content)?;
-    let deps: PackageDeps = parsed.try_into()?;
+/// packages required by each installed package, with required versions
+type DepsTree = HashMap<Arc<str>, Vec<(Arc<str>, DepSource)>>;
+
+/// a version of package required by the project or by another package
+#[derive(Debug, Clone)]
+struct Requirement {
+  source: DepSource,
+  /// `None` for `deps.cirru` of the project
+  by: Option<Arc<str>>,
+}
+
+/// installs commits from lockfile when versions match, other dependencies are resolved from branches or tags.
+/// dependencies declared in `deps.cirru` of each package are installed too, round by round.
+/// build scripts of changed packages only run after the whole tree is checked for conflicts,
+/// then lockfile is written with commits being used
+fn download_deps(
+  deps: HashMap<Arc<str>, DepSource>,
+  lock: Option<DepsLock>,
+  lock_path: &Path,
+  modules: &ModulesLayout,
+  options: TopLevelCaps,
+) -> Result<(), String> {
+  if !modules.dir.exists() {
+    fs::create_dir_all(&modules.dir).map_err(|e| e.to_string())?;
+    dim_println(format!("created dir: {:?}", modules.dir));
+  }
+
+  let mut requirements: HashMap<Arc<str>, Vec<Requirement>> = HashMap::new();
+  let mut tree: DepsTree = HashMap::new();
+  let mut installed: HashMap<Arc<str>, DepSource> = HashMap::new();
+  let mut roots: Vec<(Arc<str>, DepSource)> = deps.into_iter().collect();
+  roots.sort();
+  for (name, source) in &roots {
+    requirements.entry(name.to_owned()).or_default().push(Requirement {
+      source: source.to_owned(),
+      by: None,
+    });
+  }
+
+  let mut next_lock = DepsLock::default();
+  let mut failed: Vec<Arc<str>> = vec![];
+  let mut to_build: Vec<(Arc<str>, DepSource)> = vec![];
+  let mut pending = roots.clone();
+
+  while !pending.is_empty() {
+    let mut children = vec![];
+
+    for (org_and_folder, source) in pending.drain(..) {
+      let options = options.to_owned();
+      let modules = modules.clone();
+      let locked = match &source {
+        DepSource::GitHub { version } => lock.as_ref().and_then(|l| l.commit_of(&org_and_folder, version, None)),
+        DepSource::Git { url, version } => lock.as_ref().and_then(|l| l.commit_of(&org_and_folder, version, Some(url))),
+        _ => None,
+      };
+
+      // TODO too many threads do not make it faster though
+      let ret = thread::spawn(move || {
+        let from_lock = locked.is_some();
+        let ret = handle_path(&modules, &source, locked, &options, &org_and_folder).and_then(|(commit, changed)| {
+          let hash = module_hash(&modules, &org_and_folder, &source)?;
+          Ok((commit, changed, hash, read_module_deps(&modules, &org_and_folder, &source)?))
+        });
+        (org_and_folder, source, from_lock, ret)
+      });
+      children.push(ret);
+    }
 
-    if cli_args.subcommand.is_some() {
-      outdated_tags(deps.dependencies)?;
+    for child in children {
+      let (name, source, from_lock, ret) = child.join().unwrap();
+      match ret {
+        Ok((commit, changed, hash, sub_deps)) => {
+          if changed {
+            to_build.push((name.to_owned(), source.to_owned()));
+          }
+          if let Some((path, hash)) = hash {
+            // same commit from lockfile should have the same content
+            let recorded = lock.as_ref().and_then(|l| l.integrity.get(path.as_str()));
+            if let Some(recorded) = recorded.filter(|recorded| from_lock && recorded.as_ref() != hash) {
+              err_println(format!(
+                "{} does not match lockfile, expected sha256 {}, got {}, remove the folder to install again\n",
+                path, recorded, hash
+              ));
+              failed.push(name.to_owned());
+            }
+            next_lock.integrity.insert(path.into(), hash.into());
+          }
+          if let (Some(commit), Some(version)) = (commit, source.git_version()) {
+            let url = match &source {
+              DepSource::Git { url, .. } => Some(url.to_owned()),
+              _ => None,
+            };
+            let dep = LockedDep {
+              version: version.into(),
+              url,
+              commit,
+            };
+            next_lock.dependencies.insert(name.to_owned(), dep);
+          }
+          installed.insert(name.to_owned(), source);
+          let mut sub_deps: Vec<(Arc<str>, DepSource)> = sub_deps.into_iter().collect();
+          sub_deps.sort();
+          for (dep, dep_source) in &sub_deps {
+            let reqs = requirements.entry(dep.to_owned()).or_default();
+            // only the first requirement gets installed, others are checked for conflicts later
+            if reqs.is_empty() {
+              pending.push((dep.to_owned(), dep_source.to_owned()));
+            }
+            reqs.push(Requirement {
+              source: dep_source.to_owned(),
+              by: Some(name.to_owned()),
+            });
+          }
+          tree.insert(name, sub_deps);
+        }
+        Err(e) => {
+          err_println(format!("{}\n", e));
+          failed.push(name);
+        }
+      }
+    }
+  }
+
+  println!("{}", "dependencies:".dimmed());
+  print_deps_tree(&roots, &tree, &installed, "", &mut HashSet::new());
+
+  if !failed.is_empty() {
+    failed.sort();
+    let names = failed.iter().map(|name| name.to_string()).collect::<Vec<_>>().join(", ");
+    let reason = if options.offline {
+      "not available offline"
     } else {
-      download_deps(deps.dependencies, cli_args)?;
+      "failed to install"
+    };
+    return Err(format!("{} {}, {} is not updated", names, reason, lock_path.display()));
+  }
+
+  let conflicts = find_conflicts(&requirements);
+  if !conflicts.is_empty() {
+    err_println(String::from("version conflicts:"));
+    for (name, reqs) in conflicts {
+      err_println(format!("    {}", name));
+      for req in reqs {
+        let by = req.by.as_deref().unwrap_or("deps.cirru");
+        err_println(format!("      {} required by {}", req.source, by));
+      }
     }
+    return Err(format!(
+      "dependencies require different versions of same packages, {} is not updated",
+      lock_path.display()
+    ));
+  }
+
+  to_build.sort();
+  for (name, source) in &to_build {
+    let folder_path = modules.dir.join(modules.folder_of(name, source)?);
+    run_build_script(&folder_path, name).map_err(|e| format!("{}, {} is not updated", e, lock_path.display()))?;
+  }
 
-    Ok(())
+  if lock.as_ref() != Some(&next_lock) {
+    next_lock.write(lock_path)?;
+    dim_println(format!("wrote {}", lock_path.display()));
+  }
+
+  Ok(())
+}
+
+/// packages required in different versions, sorted by names
+fn find_conflicts(requirements: &HashMap<Arc<str>, Vec<Requirement>>) -> Vec<(&Arc<str>, &Vec<Requirement>)> {
+  let mut conflicts: Vec<(&Arc<str>, &Vec<Requirement>)> = requirements
+    .iter()
+    .filter(|(_, reqs)| reqs.iter().any(|r| r.source != reqs[0].source))
+    .collect();
+  conflicts.sort_by(|a, b| a.0.cmp(b.0));
+  conflicts
+}
+
+/// sha256 of `compact.cirru` of an installed package, keyed by its path inside modules folder.
+/// linked local folders are skipped since they change during development, copies only change by `caps`
+fn module_hash(modules: &ModulesLayout, org_and_folder: &str, source: &DepSource) -> Result<Option<(String, String)>, String> {
+  if let DepSource::Path { copy: false, .. } = source {
+    return Ok(None);
+  }
+  let path = format!("{}/compact.cirru", modules.folder_of(org_and_folder, source)?);
+  let file = modules.dir.join(&path);
+  if file.exists() {
+    Ok(Some((path, calcit::file_sha256(&file)?)))
   } else {
-    eprintln!("Error: no {} found!", cli_args.input);
-    std::process::exit(1);
+    Ok(None)
+  }
+}
+
+/// dependencies declared by an installed package, empty when it has no `deps.cirru`
+fn read_module_deps(modules: &ModulesLayout, org_and_folder: &str, source: &DepSource) -> Result<HashMap<Arc<str>, DepSource>, String> {
+  let folder_path = modules.dir.join(modules.folder_of(org_and_folder, source)?);
+  // local paths of a symlinked package are relative to its original folder
+  let folder_path = folder_path.canonicalize().unwrap_or(folder_path);
+  let deps_file = folder_path.join("deps.cirru");
+  if !deps_file.exists() {
+    return Ok(HashMap::new());
+  }
+  let content = fs::read_to_string(&deps_file).map_err(|e| format!("failed to read {}: {}", deps_file.display(), e))?;
+  let mut deps: PackageDeps = cirru_edn::parse(&content)?
+    .try_into()
+    .map_err(|e| format!("invalid deps.cirru of {}: {}", org_and_folder, e))?;
+  deps.resolve_paths(&folder_path);
+  Ok(deps.dependencies)
+}
+
+/// packages appearing more than once are only expanded for the first time
+fn print_deps_tree(
+  deps: &[(Arc<str>, DepSource)],
+  tree: &DepsTree,
+  installed: &HashMap<Arc<str>, DepSource>,
+  prefix: &str,
+  shown: &mut HashSet<Arc<str>>,
+) {
+  for (idx, (name, source)) in deps.iter().enumerate() {
+    let is_last = idx + 1 == deps.len();
+    let branch = if is_last { "└─ " } else { "├─ " };
+    let required = source.to_string();
+    let label = match installed.get(name) {
+      None => format!("{} {}", name, required.red()),
+      Some(got) if got != source => format!("{} {} {}", name, required.red(), format!("(got {})", got).red()),
+      Some(_) if shown.contains(name) => format!("{} {} {}", name, gray(&required), "(*)".dimmed()),
+      Some(_) => format!("{} {}", name, gray(&required)),
+    };
+    println!("  {}{}{}", prefix.dimmed(), branch.dimmed(), label);
+    if shown.insert(name.to_owned()) {
+      if let Some(sub_deps) = tree.get(name) {
+        let next_prefix = format!("{}{}", prefix, if is_last { "   " } else { "│  " });
+        print_deps_tree(sub_deps, tree, installed, &next_prefix, shown);
+      }
+    }
   }
 }
 
-fn download_deps(deps: HashMap<Arc<str>, Arc<str>>, options: TopLevelCaps) -> Result<(), String> {
-  // ~/.config/calcit/modules/
-  let clone_target = if options.local_debug {
-    println!("{}", "  [DEBUG] local debug mode, cloning to test-modules/".yellow());
-    ".config/calcit/test-modules"
+/// returns the commit being checked out, `None` for sources not from git,
+/// along with whether files are changed and `build.sh` needs to run
+fn handle_path(
+  modules: &ModulesLayout,
+  source: &DepSource,
+  locked: Option<Arc<str>>,
+  options: &TopLevelCaps,
+  org_and_folder: &str,
+) -> Result<(Option<Arc<str>>, bool), String> {
+  let folder = modules.folder_of(org_and_folder, source)?;
+  let folder_path = modules.dir.join(&folder);
+  let (url, version) = match source {
+    DepSource::GitHub { version } => (github_url(org_and_folder, options), version),
+    DepSource::Git { url, version } => (url.to_string(), version),
+    DepSource::Path { path, copy } => {
+      let changed = dep_source::install_path(&folder_path, path, *copy)?;
+      if changed {
+        let action = if *copy { "copied" } else { "linked" };
+        dim_println(format!("√ {} {} from {}", action, gray(org_and_folder), gray(path)));
+      } else {
+        dim_println(format!("√ found {} of {}", gray(path), gray(&folder)));
+      }
+      return Ok((None, changed));
+    }
+    DepSource::Tarball { url, sha256 } => {
+      let changed = dep_source::install_tarball(&folder_path, url, sha256, &dep_source::tarball_cache_dir()?, options.offline)?;
+      if changed {
+        dim_println(format!("√ extracted {} from {}", gray(org_and_folder), gray(url)));
+      } else {
+        dim_println(format!("√ found {} of {}", gray(&source.to_string()), gray(&folder)));
+      }
+      return Ok((None, changed));
+    }
+  };
+  let git_repo = GitRepo { dir: folder_path.clone() };
+  let changed = if options.offline {
+    install_offline(&folder_path, &folder, &url, version, locked.as_deref(), org_and_folder)?
   } else {
-    ".config/calcit/modules"
+    match locked {
+      Some(commit) => install_locked(&folder_path, &folder, &url, version, &commit, options, org_and_folder)?,
+      None => resolve_version(&folder_path, &folder, &url, version, options, org_and_folder)?,
+    }
   };
-  let modules_dir = dirs::home_dir().ok_or("no config dir")?.join(clone_target);
+  Ok((Some(git_repo.rev_parse("HEAD")?.into()), changed))
+}
 
-  if !modules_dir.exists() {
-    fs::create_dir_all(&modules_dir).map_err(|e| e.to_string())?;
-    dim_println(format!("created dir: {:?}", modules_dir));
+/// checks out from existing checkout without fetching, a missing checkout is cloned from `file://` URLs
+/// or from the shared modules folder, while git is limited to local protocols. returns whether files are changed
+fn install_offline(
+  folder_path: &Path,
+  folder: &str,
+  url: &str,
+  version: &str,
+  locked: Option<&str>,
+  org_and_folder: &str,
+) -> Result<bool, String> {
+  let git_repo = GitRepo {
+    dir: folder_path.to_path_buf(),
+  };
+  let cloned = !folder_path.exists();
+  if cloned {
+    let local_copy = local_git_source(url, folder_path, folder, org_and_folder)
+      .ok_or_else(|| format!("{} at version {} is not available offline", org_and_folder, version))?;
+    dim_println(format!("↺ cloning {} from {}", gray(org_and_folder), gray(&local_copy)));
+    GitRepo::clone_local(folder_path, &local_copy, version)?;
+    git_repo.set_origin_url(url)?;
   }
+  let target = match locked {
+    Some(commit) if git_repo.has_commit(commit) => commit,
+    Some(commit) => {
+      return Err(format!(
+        "{} at commit {} is not available offline",
+        org_and_folder,
+        short_commit(commit)
+      ))
+    }
+    None => {
+      git_repo
+        .check_branch_or_tag(version, folder)
+        .map_err(|_| format!("{} at version {} is not available offline", org_and_folder, version))?;
+      version
+    }
+  };
+  if git_repo.rev_parse("HEAD")? == git_repo.rev_parse(target)? {
+    if !cloned {
+      dim_println(format!("√ found {} of {}", gray(version), gray(folder)));
+    }
+    return Ok(cloned);
+  }
+  git_repo.checkout(target)?;
+  dim_println(format!("√ checked out {} of {}", gray(version), gray(org_and_folder)));
+  Ok(true)
+}
 
-  let mut children = vec![];
-
-  for (org_and_folder, version) in deps {
-    // cloned
+/// a local repository to clone from without network
+fn local_git_source(url: &str, folder_path: &Path, folder: &str, org_and_folder: &str) -> Option<String> {
+  if url.starts_with("file://") || Path::new(url).is_dir() {
+    return Some(url.to_owned());
+  }
+  let shared = calcit::global_module_folder().ok()?;
+  let (_org, name) = org_and_folder.split_once('/')?;
+  [shared.join(folder), shared.join(name)]
+    .into_iter()
+    .find(|dir| dir.join(".git").exists() && dir.as_path() != folder_path)
+    .map(|dir| dir.display().to_string())
+}
 
-    let org_and_folder = org_and_folder.clone();
-    let options = options.to_owned();
-    let modules_dir = modules_dir.clone();
+/// if there's a build.sh file in the folder, run it
+fn run_build_script(folder_path: &Path, org_and_folder: &str) -> Result<(), String> {
+  if folder_path.join("build.sh").exists() {
+    let build_msg = call_build_script(folder_path)?;
+    dim_println(format!("ran build script for {}", gray(org_and_folder)));
+    dim_println(build_msg);
+  }
+  Ok(())
+}
 
-    // TODO too many threads do not make it faster though
-    let options2 = options.clone();
-    let ret = thread::spawn(move || {
-      let ret = handle_path(modules_dir, version, &options2, org_and_folder);
-      if let Err(e) = ret {
-        err_println(format!("{}\n", e));
-      }
-    });
-    children.push(ret);
+/// checks out the exact commit from lockfile, fetching it when it's not in local history.
+/// returns whether files are changed
+fn install_locked(
+  folder_path: &Path,
+  folder: &str,
+  url: &str,
+  version: &str,
+  commit: &str,
+  options: &TopLevelCaps,
+  org_and_folder: &str,
+) -> Result<bool, String> {
+  let git_repo = GitRepo {
+    dir: folder_path.to_path_buf(),
+  };
+  if !folder_path.exists() {
+    dim_println(format!("↺ cloning {} at version {}", gray(org_and_folder), gray(version)));
+    GitRepo::clone_to(folder_path, url, version, options.ci)?;
+  } else if git_repo.rev_parse("HEAD")? == commit {
+    dim_println(format!("√ found {} of {}", gray(&short_commit(commit)), gray(folder)));
+    return Ok(false);
   }
-  for child in children {
-    child.join().unwrap();
+  if !git_repo.has_commit(commit) {
+    dim_println(format!(
+      "↺ fetching {} at commit {}",
+      gray(org_and_folder),
+      gray(&short_commit(commit))
+    ));
+    git_repo.fetch_commit(commit)?;
   }
+  git_repo.checkout(commit)?;
+  dim_println(format!(
+    "√ checked out {} of {} ({})",
+    gray(&short_commit(commit)),
+    gray(org_and_folder),
+    gray(version)
+  ));
+  Ok(true)
+}
 
-  Ok(())
+fn github_url(org_and_folder: &str, options: &TopLevelCaps) -> String {
+  if options.ci {
+    format!("https://github.com/{}.git", org_and_folder)
+  } else {
+    format!("git@github.com:{}.git", org_and_folder)
+  }
 }
 
-fn handle_path(modules_dir: PathBuf, version: Arc<str>, options: &TopLevelCaps, org_and_folder: Arc<str>) -> Result<(), String> {
-  // check if exists
-  let (_org, folder) = org_and_folder.split_once('/').ok_or("invalid name")?;
-  // split with / into (org,folder)
+fn short_commit(commit: &str) -> String {
+  commit.chars().take(8).collect()
+}
 
-  let folder_path = modules_dir.join(folder);
-  let build_file = folder_path.join("build.sh");
-  let git_repo = GitRepo { dir: folder_path.clone() };
+/// checks out the branch or tag written in `deps.cirru`, returns whether files are changed
+fn resolve_version(
+  folder_path: &Path,
+  folder: &str,
+  url: &str,
+  version: &str,
+  options: &TopLevelCaps,
+  org_and_folder: &str,
+) -> Result<bool, String> {
+  let git_repo = GitRepo {
+    dir: folder_path.to_path_buf(),
+  };
   if folder_path.exists() {
     // println!("module {} exists", folder);
     // check branch
     let current_head = git_repo.current_head()?;
     if current_head.get_name() == *version {
-      dim_println(format!("√ found {} of {}", gray(&version), gray(folder)));
+      dim_println(format!("√ found {} of {}", gray(version), gray(folder)));
       if let GitHead::Branch(branch) = current_head {
         if options.pull_branch {
-          dim_println(format!("↺ pulling {} at version {}", gray(&org_and_folder), gray(&version)));
-          git_repo.pull(&branch)?;
-          dim_println(format!("pulled {} at {}", gray(folder), gray(&version)));
-
-          // if there's a build.sh file in the folder, run it
-          if build_file.exists() {
-            let build_msg = call_build_script(&folder_path)?;
-            dim_println(format!("ran build script for {}", gray(&org_and_folder)));
-            dim_println(build_msg);
-          }
+          pull_branch(&git_repo, &branch, options, folder, org_and_folder)?;
+          return Ok(true);
         }
       }
-      return Ok(());
+      return Ok(false);
     }
     // let msg = format!("module {} is at version {:?}, but required {}", folder, current_head, version);
     // println!("  {}", msg.yellow());
@@ -178,51 +606,42 @@ fn handle_path(modules_dir: PathBuf, version: Arc<str>, options: &TopLevelCaps,
     // load latest tags
     git_repo.fetch()?;
     // try if tag or branch exists in git history
-    let has_target = git_repo.check_branch_or_tag(&version, folder)?;
+    let has_target = git_repo.check_branch_or_tag(version, folder)?;
     if !has_target {
-      dim_println(format!("↺ fetching {} at version {}", gray(&org_and_folder), gray(&version)));
+      dim_println(format!("↺ fetching {} at version {}", gray(org_and_folder), gray(version)));
       git_repo.fetch()?;
-      dim_println(format!("fetched {} at version {}", gray(&org_and_folder), gray(&version)));
+      dim_println(format!("fetched {} at version {}", gray(org_and_folder), gray(version)));
       // fetch git repo and checkout target version
     }
-    git_repo.checkout(&version)?;
-    dim_println(format!("√ checked out {} of {}", gray(&version), gray(&org_and_folder)));
+    git_repo.checkout(version)?;
+    dim_println(format!("√ checked out {} of {}", gray(version), gray(org_and_folder)));
 
     let current_head = git_repo.current_head()?;
     if let GitHead::Branch(branch) = current_head {
       if options.pull_branch {
-        dim_println(format!("↺ pulling {} at version {}", gray(&org_and_folder), gray(&version)));
-        git_repo.pull(&branch)?;
-        dim_println(format!("pulled {} at {}", gray(folder), gray(&version)));
+        pull_branch(&git_repo, &branch, options, folder, org_and_folder)?;
       }
     }
-
-    // if there's a build.sh file in the folder, run it
-    if build_file.exists() {
-      let build_msg = call_build_script(&folder_path)?;
-      dim_println(format!("ran build script for {}", gray(&org_and_folder)));
-      dim_println(build_msg);
-    }
+    Ok(true)
   } else {
-    let url = if options.ci {
-      format!("https://github.com/{}.git", org_and_folder)
-    } else {
-      format!("git@github.com:{}.git", org_and_folder)
-    };
-    dim_println(format!("↺ cloning {} at version {}", gray(&org_and_folder), gray(&version)));
-    GitRepo::clone_to(&modules_dir, &url, &version, options.ci)?;
+    dim_println(format!("↺ cloning {} at version {}", gray(org_and_folder), gray(version)));
+    GitRepo::clone_to(folder_path, url, version, options.ci)?;
     // println!("downloading {} at version {}", url, version);
-    dim_println(format!("downloaded {} at version {}", gray(&org_and_folder), gray(&version)));
-
-    if !options.ci {
-      // if there's a build.sh file in the folder, run it
-      if build_file.exists() {
-        let build_msg = call_build_script(&folder_path)?;
-        dim_println(format!("ran build script for {}", gray(&org_and_folder)));
-        dim_println(build_msg);
-      }
-    }
+    dim_println(format!("downloaded {} at version {}", gray(org_and_folder), gray(version)));
+    // modules are not built in CI
+    Ok(!options.ci)
   }
+}
+
+/// `caps update` resets the branch to remote since lockfile is being refreshed, `--pull-branch` merges into local branch
+fn pull_branch(git_repo: &GitRepo, branch: &str, options: &TopLevelCaps, folder: &str, org_and_folder: &str) -> Result<(), String> {
+  dim_println(format!("↺ pulling {} at version {}", gray(org_and_folder), gray(branch)));
+  if let Some(SubCommand::Update(_)) = options.subcommand {
+    git_repo.reset_to_origin(branch)?;
+  } else {
+    git_repo.pull(branch)?;
+  }
+  dim_println(format!("pulled {} at {}", gray(folder), gray(branch)));
   Ok(())
 }
 
@@ -248,6 +667,9 @@ struct TopLevelCaps {
   /// debug mode, clone to test-modules/
   #[argh(switch)]
   local_debug: bool,
+  /// no network, install from existing checkouts, shared modules folder and tarball cache only
+  #[argh(switch)]
+  offline: bool,
 
   /// input file
   #[argh(positional, default = "\"deps.cirru\".to_owned()")]
@@ -259,6 +681,8 @@ struct TopLevelCaps {
 enum SubCommand {
   /// show outdated versions
   Outdated(OutdatedCaps),
+  /// resolve versions again and refresh lockfile
+  Update(UpdateCaps),
 }
 
 #[derive(FromArgs, PartialEq, Debug, Clone)]
@@ -266,6 +690,11 @@ enum SubCommand {
 #[argh(subcommand, name = "outdated")]
 struct OutdatedCaps {}
 
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+/// resolve branches and tags in deps.cirru again, and write new commits into deps.lock.cirru
+#[argh(subcommand, name = "update")]
+struct UpdateCaps {}
+
 fn dim_println(msg: String) {
   if msg.chars().nth(1) == Some(' ') {
     println!("{}", msg.truecolor(128, 128, 128));
@@ -316,14 +745,15 @@ fn call_build_script(folder_path: &Path) -> Result<String, String> {
 /// read packages from deps, find tag(or sha) and committed date,
 /// also git fetch to read latest tag from remote,
 /// then we can compare, get outdated version printed
-fn outdated_tags(deps: HashMap<Arc<str>, Arc<str>>) -> Result<(), String> {
+fn outdated_tags(deps: HashMap<Arc<str>, DepSource>, modules: &ModulesLayout) -> Result<(), String> {
   print_column("package".dimmed(), "expected".dimmed(), "latest".dimmed(), "hint".dimmed());
   println!();
   let mut children = vec![];
 
-  for (org_and_folder, version) in deps {
+  for (org_and_folder, source) in deps {
+    let modules = modules.clone();
     let ret = thread::spawn(move || {
-      let ret = show_package_versions(org_and_folder, version);
+      let ret = show_package_versions(&modules, org_and_folder, source);
       if let Err(e) = ret {
         err_println(format!("{}\n", e));
       }
@@ -337,9 +767,16 @@ fn outdated_tags(deps: HashMap<Arc<str>, Arc<str>>) -> Result<(), String> {
   Ok(())
 }
 
-fn show_package_versions(org_and_folder: Arc<str>, version: Arc<str>) -> Result<(), String> {
-  let (_org, folder) = org_and_folder.split_once('/').ok_or("invalid name")?;
-  let folder_path = dirs::home_dir().ok_or("no config dir")?.join(".config/calcit/modules").join(folder);
+fn show_package_versions(modules: &ModulesLayout, org_and_folder: Arc<str>, source: DepSource) -> Result<(), String> {
+  let version = match source.git_version() {
+    Some(version) => version.to_owned(),
+    None => {
+      let label = source.to_string();
+      print_column(org_and_folder.dimmed(), label.dimmed(), "-".dimmed(), "not from git".dimmed());
+      return Ok(());
+    }
+  };
+  let folder_path = modules.dir.join(modules.folder_of(&org_and_folder, &source)?);
   let git_repo = GitRepo { dir: folder_path.clone() };
   if folder_path.exists() {
     git_repo.fetch()?;
@@ -371,3 +808,96 @@ fn show_package_versions(org_and_folder: Arc<str>, version: Arc<str>) -> Result<
 fn print_column(pkg: ColoredString, expected: ColoredString, latest: ColoredString, hint: ColoredString) {
   println!("{:<32} {:<12} {:<12} {:<12}", pkg, expected, latest, hint);
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn require(version: &str, by: Option<&str>) -> Requirement {
+    Requirement {
+      source: DepSource::GitHub {
+        version: Arc::from(version),
+      },
+      by: by.map(Arc::from),
+    }
+  }
+
+  #[test]
+  fn conflicts_of_different_versions() {
+    let mut requirements: HashMap<Arc<str>, Vec<Requirement>> = HashMap::new();
+    requirements.insert(
+      Arc::from("org/lib"),
+      vec![require("0.1.0", Some("org/a")), require("main", Some("org/b"))],
+    );
+    requirements.insert(Arc::from("org/a"), vec![require("0.1.0", None), require("0.1.0", Some("org/b"))]);
+    requirements.insert(Arc::from("org/b"), vec![require("0.1.0", None)]);
+    requirements.insert(Arc::from("org/base"), vec![require("0.1.0", None), require("0.2.0", Some("org/a"))]);
+
+    let names: Vec<&str> = find_conflicts(&requirements).iter().map(|(name, _)| &***name).collect();
+    assert_eq!(names, vec!["org/base", "org/lib"]);
+  }
+
+  fn git(dir: &Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+      .current_dir(dir)
+      .args(["-c", "user.name=caps", "-c", "user.email=caps@example.com"])
+      .args(args)
+      .output()
+      .expect("run git")
+      .status;
+    assert!(status.success(), "git {:?} failed", args);
+  }
+
+  /// a repo with tag `0.1.0` to install from, inside a fresh temp folder
+  fn demo_repo(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("caps-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let repo = root.join("lib.git");
+    fs::create_dir_all(&repo).unwrap();
+    git(&repo, &["init", "-q", "-b", "main"]);
+    fs::write(repo.join("compact.cirru"), "{}").unwrap();
+    git(&repo, &["add", "."]);
+    git(&repo, &["commit", "-q", "-m", "init"]);
+    git(&repo, &["tag", "0.1.0"]);
+    fs::create_dir_all(root.join("modules")).unwrap();
+    root
+  }
+
+  #[test]
+  fn install_offline_from_local_copy() -> Result<(), String> {
+    let root = demo_repo("offline");
+    let url = format!("file://{}", root.join("lib.git").display());
+    let folder_path = root.join("modules/lib");
+    assert!(install_offline(&folder_path, "lib", &url, "0.1.0", None, "org/lib")?);
+    let repo = GitRepo { dir: folder_path.clone() };
+    let tagged = GitRepo { dir: root.join("lib.git") }.rev_parse("0.1.0")?;
+    assert_eq!(repo.rev_parse("HEAD")?, tagged);
+    // already in place
+    assert!(!install_offline(&folder_path, "lib", &url, "0.1.0", Some(&tagged), "org/lib")?);
+
+    let missing = "0000000000000000000000000000000000000000";
+    let e = install_offline(&folder_path, "lib", &url, "0.1.0", Some(missing), "org/lib").unwrap_err();
+    assert!(e.contains("not available offline"), "{}", e);
+
+    let name = format!("org/caps-offline-missing-{}", std::process::id());
+    let remote = format!("git@github.com:{}.git", name);
+    let e = install_offline(&root.join("modules/missing"), "missing", &remote, "0.1.0", None, &name).unwrap_err();
+    assert!(e.contains("not available offline"), "{}", e);
+    fs::remove_dir_all(root).map_err(|e| e.to_string())
+  }
+
+  #[test]
+  fn local_clone_refuses_remotes() {
+    let root = demo_repo("remote");
+    assert!(GitRepo::clone_local(&root.join("modules/remote"), "https://github.com/calcit-lang/memof.git", "main").is_err());
+    assert!(!root.join("modules/remote").exists());
+    fs::remove_dir_all(root).unwrap();
+  }
+
+  #[test]
+  fn no_conflicts_for_same_versions() {
+    let mut requirements: HashMap<Arc<str>, Vec<Requirement>> = HashMap::new();
+    requirements.insert(Arc::from("org/lib"), vec![require("0.1.0", None), require("0.1.0", Some("org/a"))]);
+    assert!(find_conflicts(&requirements).is_empty());
+  }
+}
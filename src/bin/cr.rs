@@ -6,15 +6,23 @@ use std::sync::Arc;
 use std::time::Duration;
 use std::time::Instant;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod debugger;
+mod formatter;
 #[cfg(not(target_arch = "wasm32"))]
 mod injection;
+mod linter;
+#[cfg(not(target_arch = "wasm32"))]
+mod lsp;
+#[cfg(not(target_arch = "wasm32"))]
+mod repl;
+mod test_runner;
 
 use calcit::calcit::LocatedWarning;
 use calcit::call_stack::CallStackList;
 use calcit::cli_args::{CalcitCommand, ToplevelCalcit};
 use calcit::snapshot::ChangesDict;
 use calcit::util::string::strip_shebang;
-use dirs::home_dir;
 use notify::RecursiveMode;
 use notify_debouncer_mini::new_debouncer;
 
@@ -23,14 +31,35 @@ use calcit::{
   ProgramEntries,
 };
 
+/// rows of hottest functions printed after profiling
+const PROFILE_TABLE_SIZE: usize = 20;
+
 fn main() -> Result<(), String> {
   builtins::effects::init_effects_states();
 
+  let cli_args: ToplevelCalcit = argh::from_env();
+
   // get dirty functions injected
   #[cfg(not(target_arch = "wasm32"))]
   injection::inject_platform_apis();
 
-  let cli_args: ToplevelCalcit = argh::from_env();
+  let input_path = PathBuf::from(&cli_args.input);
+  let base_dir = input_path.parent().expect("extract parent");
+  let module_folder = calcit::find_module_folder(base_dir)?;
+
+  // stdout is taken by the protocol, so start before printing anything
+  #[cfg(not(target_arch = "wasm32"))]
+  if let Some(CalcitCommand::Lsp(_)) = &cli_args.subcommand {
+    return lsp::start_lsp(&cli_args, &module_folder);
+  }
+
+  // formatting does not load program
+  if let Some(CalcitCommand::Fmt(fmt_options)) = &cli_args.subcommand {
+    return formatter::run_fmt(&cli_args, fmt_options);
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  println!("registered platform APIs");
 
   let mut eval_once = cli_args.once;
   let assets_watch = cli_args.watch_dir.to_owned();
@@ -41,19 +70,11 @@ fn main() -> Result<(), String> {
 
   let mut snapshot = snapshot::Snapshot::default(); // placeholder data
 
-  let module_folder = home_dir()
-    .map(|buf| buf.as_path().join(".config/calcit/modules/"))
-    .expect("failed to load $HOME");
-  println!("module folder: {}", module_folder.to_str().expect("extract path"));
-
   if cli_args.disable_stack {
     call_stack::set_using_stack(false);
     println!("stack trace disabled.")
   }
 
-  let input_path = PathBuf::from(&cli_args.input);
-  let base_dir = input_path.parent().expect("extract parent");
-
   if let Some(CalcitCommand::Eval(ref command)) = cli_args.subcommand {
     let snippet = &command.snippet;
     eval_once = true;
@@ -64,44 +85,17 @@ fn main() -> Result<(), String> {
       Err(e) => return Err(e),
     }
 
+    println!("module folder: {}", module_folder.display());
+    let module_hashes = calcit::read_module_hashes(base_dir)?;
     for module_path in &command.dep {
+      calcit::verify_module_hash(module_path, &module_folder, module_hashes.as_ref())?;
       let module_data = calcit::load_module(module_path, base_dir, &module_folder)?;
       for (k, v) in &module_data.files {
         snapshot.files.insert(k.to_owned(), v.to_owned());
       }
     }
   } else {
-    if !Path::new(&cli_args.input).exists() {
-      return Err(format!("{} does not exist", cli_args.input));
-    }
-    // load entry file
-    let mut content = fs::read_to_string(&cli_args.input).unwrap_or_else(|_| panic!("expected Cirru snapshot: {}", cli_args.input));
-    strip_shebang(&mut content);
-    let data = cirru_edn::parse(&content)?;
-    // println!("reading: {}", content);
-    snapshot = snapshot::load_snapshot_data(&data, &cli_args.input)?;
-
-    // config in entry will overwrite default configs
-    if let Some(entry) = cli_args.entry.to_owned() {
-      if snapshot.entries.contains_key(entry.as_str()) {
-        println!("running entry: {entry}");
-        snapshot.entries[entry.as_str()].clone_into(&mut snapshot.configs);
-      } else {
-        return Err(format!(
-          "unknown entry `{}` in `{}`",
-          entry,
-          snapshot.entries.keys().map(|x| (*x).to_owned()).collect::<Vec<_>>().join("/")
-        ));
-      }
-    }
-
-    // attach modules
-    for module_path in &snapshot.configs.modules {
-      let module_data = calcit::load_module(module_path, base_dir, &module_folder)?;
-      for (k, v) in &module_data.files {
-        snapshot.files.insert(k.to_owned(), v.to_owned());
-      }
-    }
+    snapshot = load_snapshot_file(&cli_args, base_dir, &module_folder)?;
   }
   let config_init = snapshot.configs.init_fn.to_string();
   let config_reload = snapshot.configs.reload_fn.to_string();
@@ -140,6 +134,23 @@ fn main() -> Result<(), String> {
   )
   .map_err(|e| e.msg)?;
 
+  #[cfg(not(target_arch = "wasm32"))]
+  if let Some(CalcitCommand::Repl(repl_options)) = &cli_args.subcommand {
+    let ns = repl_options.ns.as_deref().unwrap_or(&entries.init_ns);
+    return repl::start_repl(ns, &cli_args, &module_folder);
+  }
+
+  if let Some(CalcitCommand::Test(test_options)) = &cli_args.subcommand {
+    return test_runner::run_tests(test_options);
+  }
+
+  if let Some(CalcitCommand::Lint(lint_options)) = &cli_args.subcommand {
+    return linter::run_lint(&snapshot, &entries, lint_options);
+  }
+
+  let interpreting = !matches!(cli_args.subcommand, Some(CalcitCommand::EmitJs(_)) | Some(CalcitCommand::EmitIr(_)));
+  let persist_path = cli_args.persist_atoms.as_deref().filter(|_| interpreting);
+
   let task = if let Some(CalcitCommand::EmitJs(js_options)) = &cli_args.subcommand {
     if js_options.once {
       // redundant config, during watching mode, emit once
@@ -148,6 +159,15 @@ fn main() -> Result<(), String> {
     if cli_args.skip_arity_check {
       codegen::set_code_gen_skip_arity_check(true);
     }
+    if js_options.source_map {
+      codegen::set_code_gen_source_map(true);
+    }
+    if js_options.no_tree_shake {
+      codegen::set_code_gen_tree_shake(false);
+    }
+    if js_options.verbose {
+      codegen::set_code_gen_verbose(true);
+    }
     run_codegen(&entries, &cli_args.emit_path, false)
   } else if let Some(CalcitCommand::EmitIr(ir_options)) = &cli_args.subcommand {
     if ir_options.once {
@@ -156,15 +176,42 @@ fn main() -> Result<(), String> {
     }
     run_codegen(&entries, &cli_args.emit_path, true)
   } else {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Some(CalcitCommand::Debug(debug_options)) = &cli_args.subcommand {
+      eval_once = true;
+      debugger::start(&debug_options.breakpoints)?;
+    }
+    if cli_args.profile {
+      runner::profile::start_profiling();
+    }
+    if let Some(path) = &cli_args.persist_atoms {
+      // missing file is created on exit
+      if Path::new(path).exists() {
+        let size = builtins::load_persisted_refs(Path::new(path))?;
+        println!("loaded {size} atoms from {path}");
+      }
+      // watch mode is usually ended by Ctrl-C, never reaching the end of main
+      #[cfg(not(target_arch = "wasm32"))]
+      injection::persist_on_ctrl_c(Path::new(path));
+    }
     let started_time = Instant::now();
 
     let v = calcit::run_program(entries.init_ns.to_owned(), entries.init_def.to_owned(), &[]).map_err(|e| {
       LocatedWarning::print_list(&e.warnings);
+      // atoms changed before the failure are still kept
+      if let Some(path) = persist_path {
+        if let Err(persist_error) = persist_atoms(path) {
+          eprintln!("failed to persist atoms, {persist_error}");
+        }
+      }
       e.msg
     })?;
 
     let duration = Instant::now().duration_since(started_time);
     println!("took {}ms: {v}", duration.as_micros() as f64 / 1000.0);
+    if cli_args.profile {
+      runner::profile::report_profile(PROFILE_TABLE_SIZE)?;
+    }
     Ok(())
   };
 
@@ -186,9 +233,63 @@ fn main() -> Result<(), String> {
     std::thread::spawn(move || watch_files(entries, args, assets_watch));
   }
   runner::track::exit_when_cleared();
+
+  if let Some(path) = persist_path {
+    persist_atoms(path)?;
+  }
+  Ok(())
+}
+
+fn persist_atoms(path: &str) -> Result<(), String> {
+  let size = builtins::persist_refs(Path::new(path), None)?;
+  println!("persisted {size} atoms to {path}");
   Ok(())
 }
 
+/// load snapshot from input file, with entry configs and modules attached
+fn load_snapshot_file(cli_args: &ToplevelCalcit, base_dir: &Path, module_folder: &Path) -> Result<snapshot::Snapshot, String> {
+  if !Path::new(&cli_args.input).exists() {
+    return Err(format!("{} does not exist", cli_args.input));
+  }
+  // load entry file
+  let mut content = fs::read_to_string(&cli_args.input).unwrap_or_else(|_| panic!("expected Cirru snapshot: {}", cli_args.input));
+  strip_shebang(&mut content);
+  let data = cirru_edn::parse(&content)?;
+  // println!("reading: {}", content);
+  let mut snapshot = snapshot::load_snapshot_data(&data, &cli_args.input)?;
+  // a bundled snapshot carries `:modules-dir` of its project
+  let module_folder = match &snapshot.configs.modules_dir {
+    Some(dir) => base_dir.join(&**dir),
+    None => module_folder.to_owned(),
+  };
+  println!("module folder: {}", module_folder.display());
+
+  // config in entry will overwrite default configs
+  if let Some(entry) = cli_args.entry.to_owned() {
+    if snapshot.entries.contains_key(entry.as_str()) {
+      println!("running entry: {entry}");
+      snapshot.entries[entry.as_str()].clone_into(&mut snapshot.configs);
+    } else {
+      return Err(format!(
+        "unknown entry `{}` in `{}`",
+        entry,
+        snapshot.entries.keys().map(|x| (*x).to_owned()).collect::<Vec<_>>().join("/")
+      ));
+    }
+  }
+
+  // attach modules, checked against hashes in lockfile
+  let module_hashes = calcit::read_module_hashes(base_dir)?;
+  for module_path in &snapshot.configs.modules {
+    calcit::verify_module_hash(module_path, &module_folder, module_hashes.as_ref())?;
+    let module_data = calcit::load_module(module_path, base_dir, &module_folder)?;
+    for (k, v) in &module_data.files {
+      snapshot.files.insert(k.to_owned(), v.to_owned());
+    }
+  }
+  Ok(snapshot)
+}
+
 pub fn watch_files(entries: ProgramEntries, settings: ToplevelCalcit, assets_watch: Option<String>) {
   println!("\nRunning: in watch mode...\n");
   let (tx, rx) = channel();
@@ -230,9 +331,15 @@ pub fn watch_files(entries: ProgramEntries, settings: ToplevelCalcit, assets_wat
           eprintln!("failed re-compiling, got empty inc file");
           continue;
         }
-        if let Err(e) = recall_program(&content, &entries, &settings) {
-          eprintln!("error: {e}");
-        };
+        // reloading runs in the event loop, not together with callbacks of timers and other threads,
+        // and not inside `await` of a callback either
+        let entries = entries.to_owned();
+        let settings = settings.to_owned();
+        runner::event_loop::enqueue_top_level(Box::new(move || {
+          if let Err(e) = recall_program(&content, &entries, &settings) {
+            eprintln!("error: {e}");
+          };
+        }));
       }
       Ok(Err(e)) => println!("watch error: {e:?}"),
       Err(e) => eprintln!("watch error: {e:?}"),
@@ -268,6 +375,9 @@ fn recall_program(content: &str, entries: &ProgramEntries, settings: &ToplevelCa
     run_codegen(entries, &settings.emit_path, true)
   } else {
     // run from `reload_fn` after reload
+    if settings.profile {
+      runner::profile::reset_profile();
+    }
     let started_time = Instant::now();
     let task_size = runner::track::count_pending_tasks();
     println!("checking pending tasks: {task_size}");
@@ -289,6 +399,9 @@ fn recall_program(content: &str, entries: &ProgramEntries, settings: &ToplevelCa
     })?;
     let duration = Instant::now().duration_since(started_time);
     println!("took {}ms: {v}", duration.as_micros() as f64 / 1000.0);
+    if settings.profile {
+      runner::profile::report_profile(PROFILE_TABLE_SIZE)?;
+    }
     Ok(())
   };
 
@@ -370,7 +483,7 @@ fn run_codegen(entries: &ProgramEntries, emit_path: &str, ir_mode: bool) -> Resu
     }
   } else {
     // TODO entry ns
-    match codegen::emit_js::emit_js(&entries.init_ns, emit_path) {
+    match codegen::emit_js::emit_js(entries, emit_path) {
       Ok(_) => (),
       Err(failure) => {
         eprintln!("\nfailed codegen, {failure}");
@@ -0,0 +1,130 @@
+use rustyline::DefaultEditor;
+
+use calcit::{
+  calcit::{Calcit, NodeLocation},
+  call_stack::StackKind,
+  program,
+  runner::debug::{self, Breakpoint, DebugPause, PauseReason, StepAction},
+};
+
+const HELP: &str = "commands:
+  c, continue       resume until next breakpoint
+  s, step           step into next expression
+  n, next           step over current expression
+  o, out            step out of current function
+  l, locals         list local variables
+  p <name>          print a local variable
+  bt, stack         print call stack
+  b <ns/def> [1-2]  add breakpoint, `b` alone lists breakpoints
+  d <ns/def> [1-2]  delete breakpoint
+  q, quit           exit program";
+
+/// turn on debugger in runner with breakpoints from command line
+pub fn start(breakpoints: &[String]) -> Result<(), String> {
+  let mut xs: Vec<Breakpoint> = vec![];
+  for b in breakpoints {
+    xs.push(Breakpoint::parse(b)?);
+  }
+  if xs.is_empty() {
+    println!("debugger started, pausing at first expression. type `h` for help.");
+  } else {
+    println!("debugger started with {} breakpoints. type `h` for help when paused.", xs.len());
+  }
+  debug::start_debugging(xs, on_pause);
+  Ok(())
+}
+
+fn on_pause(p: &DebugPause) -> StepAction {
+  let reason = match p.reason {
+    PauseReason::Breakpoint => "breakpoint",
+    PauseReason::Step => "step",
+  };
+  if p.location.coord.is_empty() {
+    println!("\npaused at {}/{} ({reason})", p.location.ns, p.location.def);
+  } else {
+    println!("\npaused at {} ({reason})", p.location);
+  }
+  println!("  {}", source_at(&p.location).unwrap_or_else(|| p.expr.lisp_str()));
+
+  let mut editor = match DefaultEditor::new() {
+    Ok(e) => e,
+    Err(e) => {
+      eprintln!("failed to read commands: {e}");
+      return StepAction::Continue;
+    }
+  };
+  loop {
+    let line = match editor.readline("debug> ") {
+      Ok(line) => line,
+      // stdin closed, nothing more to ask
+      Err(_) => return StepAction::Continue,
+    };
+    let line = line.trim();
+    let _ = editor.add_history_entry(line);
+    let (command, arg) = match line.split_once(' ') {
+      Some((a, b)) => (a, b.trim()),
+      None => (line, ""),
+    };
+    match command {
+      "c" | "continue" => return StepAction::Continue,
+      "s" | "step" => return StepAction::StepInto,
+      "n" | "next" => return StepAction::StepOver,
+      "o" | "out" => return StepAction::StepOut,
+      "l" | "locals" => {
+        let pairs = p.scope.get_pairs();
+        if pairs.is_empty() {
+          println!("no locals");
+        }
+        for (name, value) in pairs {
+          println!("  {name}: {value}");
+        }
+      }
+      "p" | "print" => match p.scope.get_by_name(arg) {
+        Some(v) => println!("{v}"),
+        None => eprintln!("no local named `{arg}`"),
+      },
+      "bt" | "stack" => {
+        for s in &p.call_stack.0 {
+          let is_macro = s.kind == StackKind::Macro;
+          println!("  {}/{}{}", s.ns, s.def, if is_macro { "\t ~macro" } else { "" });
+        }
+      }
+      "b" | "break" if arg.is_empty() => {
+        for b in debug::list_breakpoints() {
+          println!("  {b}");
+        }
+      }
+      "b" | "break" => match Breakpoint::parse(arg) {
+        Ok(b) => {
+          println!("added breakpoint {b}");
+          debug::add_breakpoint(b);
+        }
+        Err(e) => eprintln!("{e}"),
+      },
+      "d" | "delete" => match Breakpoint::parse(arg) {
+        Ok(b) => {
+          if !debug::remove_breakpoint(&b) {
+            eprintln!("breakpoint not found: {b}");
+          }
+        }
+        Err(e) => eprintln!("{e}"),
+      },
+      "q" | "quit" => std::process::exit(1),
+      "h" | "help" => println!("{HELP}"),
+      "" => {}
+      _ => eprintln!("unknown command `{command}`, type `h` for help"),
+    }
+  }
+}
+
+/// code written at location, preprocessed code is not readable
+fn source_at(location: &NodeLocation) -> Option<String> {
+  let mut code = program::lookup_def_code(&location.ns, &location.def)?;
+  for idx in location.coord.iter() {
+    code = match code {
+      Calcit::List(xs) => xs.get(*idx as usize)?.to_owned(),
+      _ => return None,
+    };
+  }
+  Some(code.lisp_str())
+}
@@ -0,0 +1,503 @@
+//! sources of a dependency in `deps.cirru`, a plain string is a branch or tag on GitHub,
+//! maps are used for other sources:
+//!
+//! ```cirru
+//! {}
+//!   :dependencies $ {}
+//!     |calcit-lang/memof |0.0.11
+//!     |team/lib $ {} (:git |https://git.example.com/team/lib.git) (:version |0.1.0)
+//!     |team/local-lib $ {} (:path |../local-lib)
+//!     |team/packed $ {} (:tarball |https://example.com/packed.tar.gz) (:sha256 |...)
+//! ```
+
+use std::{
+  fmt, fs,
+  path::{Path, PathBuf},
+  process::Command,
+  sync::Arc,
+};
+
+use cirru_edn::Edn;
+use walkdir::WalkDir;
+
+/// file recording checksum of the archive a folder was extracted from, or of the folder it was copied from.
+/// folders without it are not replaced by `caps`
+const SHA256_MARKER: &str = ".caps-sha256";
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DepSource {
+  /// branch or tag of the GitHub repo named by the dependency
+  GitHub { version: Arc<str> },
+  /// branch or tag from any git URL, including `file://` and self-hosted servers
+  Git { url: Arc<str>, version: Arc<str> },
+  /// folder on local disk, symlinked into modules folder, or copied with `:copy true`
+  Path { path: Arc<str>, copy: bool },
+  /// `.tar.gz` archive from a URL or a local file, verified with SHA-256
+  Tarball { url: Arc<str>, sha256: Arc<str> },
+}
+
+impl TryFrom<&Edn> for DepSource {
+  type Error = String;
+
+  fn try_from(value: &Edn) -> Result<Self, Self::Error> {
+    if let Edn::Str(version) = value {
+      return Ok(DepSource::GitHub {
+        version: version.to_owned(),
+      });
+    }
+    let info = value.view_map()?;
+    let read_str = |key: &str| -> Result<Option<Arc<str>>, String> {
+      match info.get_or_nil(key) {
+        Edn::Str(s) => Ok(Some(s)),
+        Edn::Nil => Ok(None),
+        v => Err(format!("expected a string for :{}, got: {}", key, v)),
+      }
+    };
+    if let Some(url) = read_str("git")? {
+      let version = read_str("version")?.ok_or_else(|| format!("expected :version for git source {}", url))?;
+      Ok(DepSource::Git { url, version })
+    } else if let Some(path) = read_str("path")? {
+      let copy = match info.get_or_nil("copy") {
+        Edn::Bool(b) => b,
+        Edn::Nil => false,
+        v => return Err(format!("expected a bool for :copy, got: {}", v)),
+      };
+      Ok(DepSource::Path { path, copy })
+    } else if let Some(url) = read_str("tarball")? {
+      let sha256 = read_str("sha256")?.ok_or_else(|| format!("expected :sha256 for tarball {}", url))?;
+      Ok(DepSource::Tarball {
+        url,
+        sha256: sha256.to_lowercase().into(),
+      })
+    } else {
+      Err(format!("expected :git, :path or :tarball in dependency, got: {}", value))
+    }
+  }
+}
+
+impl fmt::Display for DepSource {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DepSource::GitHub { version } => write!(f, "{}", version),
+      DepSource::Git { url, version } => write!(f, "{} from {}", version, url),
+      DepSource::Path { path, .. } => write!(f, "path {}", path),
+      DepSource::Tarball { sha256, .. } => write!(f, "tarball {}", short_sha(sha256)),
+    }
+  }
+}
+
+impl DepSource {
+  /// branch or tag, for sources from git
+  pub fn git_version(&self) -> Option<&str> {
+    match self {
+      DepSource::GitHub { version } | DepSource::Git { version, .. } => Some(version),
+      _ => None,
+    }
+  }
+
+  /// suffix of versioned module folders
+  pub fn folder_suffix(&self) -> String {
+    match self {
+      // branch names may contain `/`
+      DepSource::GitHub { version } | DepSource::Git { version, .. } => version.replace('/', "-"),
+      DepSource::Path { .. } => String::from("local"),
+      DepSource::Tarball { sha256, .. } => short_sha(sha256).to_owned(),
+    }
+  }
+
+  /// local paths and local tarballs are relative to the `deps.cirru` declaring them
+  pub fn resolve_paths(&mut self, base_dir: &Path) {
+    match self {
+      DepSource::Path { path, .. } => *path = absolute_path(base_dir, path).into(),
+      DepSource::Tarball { url, .. } if !url.contains("://") => *url = absolute_path(base_dir, url).into(),
+      _ => {}
+    }
+  }
+}
+
+fn short_sha(sha256: &str) -> &str {
+  &sha256[..sha256.len().min(8)]
+}
+
+fn absolute_path(base_dir: &Path, path: &str) -> String {
+  let full = base_dir.join(path);
+  full.canonicalize().unwrap_or(full).display().to_string()
+}
+
+/// symlinks or copies a local folder into `folder_path`, returns false when the link is already in place,
+/// or when the copy has the same content as the folder
+pub fn install_path(folder_path: &Path, path: &str, copy: bool) -> Result<bool, String> {
+  let source = Path::new(path);
+  if !source.is_dir() {
+    return Err(format!("local dependency {} is not a folder", path));
+  }
+  if copy {
+    let sha256 = folder_sha256(source)?;
+    let marker = folder_path.join(SHA256_MARKER);
+    if fs::read_to_string(&marker).map(|s| s.trim() == sha256).unwrap_or(false) {
+      return Ok(false);
+    }
+    remove_module(folder_path)?;
+    copy_folder(source, folder_path)?;
+    fs::write(&marker, sha256).map_err(|e| format!("failed to write {}: {}", marker.display(), e))?;
+  } else {
+    if fs::read_link(folder_path).map(|target| target == source).unwrap_or(false) {
+      return Ok(false);
+    }
+    remove_module(folder_path)?;
+    symlink_folder(source, folder_path)?;
+  }
+  Ok(true)
+}
+
+/// previous installations by `caps` get replaced, which are symlinks or folders with a checksum marker.
+/// other folders, git repos in particular, may contain changes by users, so they are left for users to remove
+fn remove_module(folder_path: &Path) -> Result<(), String> {
+  let meta = match fs::symlink_metadata(folder_path) {
+    Ok(meta) => meta,
+    Err(_) => return Ok(()),
+  };
+  if !meta.is_dir() {
+    return fs::remove_file(folder_path).map_err(|e| format!("failed to remove {}: {}", folder_path.display(), e));
+  }
+  if folder_path.join(".git").exists() || !folder_path.join(SHA256_MARKER).is_file() {
+    return Err(format!(
+      "{} was not installed by caps and may contain changes, remove it yourself to install again",
+      folder_path.display()
+    ));
+  }
+  fs::remove_dir_all(folder_path).map_err(|e| format!("failed to remove {}: {}", folder_path.display(), e))
+}
+
+/// sha256 over paths and contents of files in a folder, `.git` and the checksum marker are skipped
+fn folder_sha256(source: &Path) -> Result<String, String> {
+  use sha2::{Digest, Sha256};
+  let mut hasher = Sha256::new();
+  let entries = WalkDir::new(source)
+    .sort_by_file_name()
+    .into_iter()
+    .filter_entry(|e| e.file_name() != ".git" && e.file_name() != SHA256_MARKER);
+  for entry in entries {
+    let entry = entry.map_err(|e| e.to_string())?;
+    if entry.file_type().is_dir() {
+      continue;
+    }
+    let relative = entry.path().strip_prefix(source).map_err(|e| e.to_string())?;
+    let content = fs::read(entry.path()).map_err(|e| format!("failed to read {}: {}", entry.path().display(), e))?;
+    hasher.update(relative.to_string_lossy().as_bytes());
+    hasher.update([0]);
+    hasher.update((content.len() as u64).to_le_bytes());
+    hasher.update(&content);
+  }
+  Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(unix)]
+fn symlink_folder(source: &Path, target: &Path) -> Result<(), String> {
+  std::os::unix::fs::symlink(source, target).map_err(|e| format!("failed to link {}: {}", target.display(), e))
+}
+
+#[cfg(windows)]
+fn symlink_folder(source: &Path, target: &Path) -> Result<(), String> {
+  std::os::windows::fs::symlink_dir(source, target).map_err(|e| format!("failed to link {}: {}", target.display(), e))
+}
+
+/// `.git` and the checksum marker are skipped
+fn copy_folder(source: &Path, target: &Path) -> Result<(), String> {
+  let entries = WalkDir::new(source)
+    .into_iter()
+    .filter_entry(|e| e.file_name() != ".git" && e.file_name() != SHA256_MARKER);
+  for entry in entries {
+    let entry = entry.map_err(|e| e.to_string())?;
+    let relative = entry.path().strip_prefix(source).map_err(|e| e.to_string())?;
+    let dest = target.join(relative);
+    if entry.file_type().is_dir() {
+      fs::create_dir_all(&dest).map_err(|e| format!("failed to create {}: {}", dest.display(), e))?;
+    } else {
+      fs::copy(entry.path(), &dest).map_err(|e| format!("failed to copy {}: {}", entry.path().display(), e))?;
+    }
+  }
+  Ok(())
+}
+
+/// verified archives are kept in `~/.config/calcit/cache/tarballs/`, named by checksums
+pub fn tarball_cache_dir() -> Result<PathBuf, String> {
+  dirs::home_dir()
+    .map(|dir| dir.join(".config/calcit/cache/tarballs"))
+    .ok_or_else(|| String::from("no config dir"))
+}
+
+/// downloads and extracts an archive into `folder_path`, returns false when the same archive was extracted before.
+/// an archive with a single top-level folder is unwrapped, like those from GitHub releases.
+/// archives in `cache_dir` are used first, and `offline` refuses to download
+pub fn install_tarball(folder_path: &Path, url: &str, sha256: &str, cache_dir: &Path, offline: bool) -> Result<bool, String> {
+  let marker = folder_path.join(SHA256_MARKER);
+  if fs::read_to_string(&marker).map(|s| s.trim() == sha256).unwrap_or(false) {
+    return Ok(false);
+  }
+  let parent = folder_path.parent().ok_or("invalid module folder")?;
+  let folder = folder_path.file_name().and_then(|s| s.to_str()).ok_or("invalid module folder")?;
+  let archive = parent.join(format!(".{}.tar.gz", folder));
+  let staging = parent.join(format!(".{}.extracting", folder));
+
+  let cached = cache_dir.join(format!("{}.tar.gz", sha256));
+  let fetched = if cached.exists() {
+    fs::copy(&cached, &archive)
+      .map(|_| ())
+      .map_err(|e| format!("failed to read {}: {}", cached.display(), e))
+  } else if offline && url.contains("://") && !url.starts_with("file://") {
+    Err(format!("tarball {} is not in local cache", url))
+  } else {
+    fetch_archive(url, &archive)
+  };
+  let ret = fetched
+    .and_then(|_| verify_checksum(&archive, url, sha256))
+    .and_then(|_| save_to_cache(&archive, &cached))
+    .and_then(|_| extract_archive(&archive, &staging, folder_path));
+  let _ = fs::remove_file(&archive);
+  let _ = fs::remove_dir_all(&staging);
+  ret?;
+
+  fs::write(&marker, sha256).map_err(|e| format!("failed to write {}: {}", marker.display(), e))?;
+  Ok(true)
+}
+
+fn fetch_archive(url: &str, archive: &Path) -> Result<(), String> {
+  if !url.contains("://") {
+    return fs::copy(url, archive)
+      .map(|_| ())
+      .map_err(|e| format!("failed to read tarball {}: {}", url, e));
+  }
+  let output = Command::new("curl")
+    .args(["-fsSL", "-o"])
+    .arg(archive)
+    .arg(url)
+    .output()
+    .map_err(|e| format!("failed to run curl: {}", e))?;
+  if output.status.success() {
+    Ok(())
+  } else {
+    Err(format!(
+      "failed to download {}: {}",
+      url,
+      String::from_utf8_lossy(&output.stderr).trim()
+    ))
+  }
+}
+
+fn save_to_cache(archive: &Path, cached: &Path) -> Result<(), String> {
+  if cached.exists() {
+    return Ok(());
+  }
+  let dir = cached.parent().ok_or("invalid cache path")?;
+  fs::create_dir_all(dir).map_err(|e| format!("failed to create {}: {}", dir.display(), e))?;
+  fs::copy(archive, cached)
+    .map(|_| ())
+    .map_err(|e| format!("failed to write {}: {}", cached.display(), e))
+}
+
+fn verify_checksum(archive: &Path, url: &str, sha256: &str) -> Result<(), String> {
+  let actual = calcit::file_sha256(archive)?;
+  if actual == sha256 {
+    Ok(())
+  } else {
+    Err(format!("checksum mismatch for {}, expected {}, got {}", url, sha256, actual))
+  }
+}
+
+fn extract_archive(archive: &Path, staging: &Path, folder_path: &Path) -> Result<(), String> {
+  if staging.exists() {
+    fs::remove_dir_all(staging).map_err(|e| format!("failed to remove {}: {}", staging.display(), e))?;
+  }
+  fs::create_dir_all(staging).map_err(|e| format!("failed to create {}: {}", staging.display(), e))?;
+  let output = Command::new("tar")
+    .arg("-xzf")
+    .arg(archive)
+    .arg("-C")
+    .arg(staging)
+    .output()
+    .map_err(|e| format!("failed to run tar: {}", e))?;
+  if !output.status.success() {
+    return Err(format!(
+      "failed to extract {}: {}",
+      archive.display(),
+      String::from_utf8_lossy(&output.stderr).trim()
+    ));
+  }
+
+  let entries = fs::read_dir(staging)
+    .map_err(|e| e.to_string())?
+    .collect::<Result<Vec<_>, _>>()
+    .map_err(|e| e.to_string())?;
+  let root: PathBuf = match entries.as_slice() {
+    [single] if single.path().is_dir() => single.path(),
+    _ => staging.to_path_buf(),
+  };
+  remove_module(folder_path)?;
+  fs::rename(&root, folder_path).map_err(|e| format!("failed to move into {}: {}", folder_path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parse(code: &str) -> Result<DepSource, String> {
+    DepSource::try_from(&cirru_edn::parse(code)?)
+  }
+
+  #[test]
+  fn parse_sources() -> Result<(), String> {
+    assert_eq!(
+      parse("do |0.1.0")?,
+      DepSource::GitHub {
+        version: Arc::from("0.1.0")
+      }
+    );
+    assert_eq!(
+      parse("{} (:git |https://git.example.com/team/lib.git) (:version |main)")?,
+      DepSource::Git {
+        url: Arc::from("https://git.example.com/team/lib.git"),
+        version: Arc::from("main")
+      }
+    );
+    assert_eq!(
+      parse("{} (:path |../local-lib)")?,
+      DepSource::Path {
+        path: Arc::from("../local-lib"),
+        copy: false
+      }
+    );
+    assert_eq!(
+      parse("{} (:path |../local-lib) (:copy true)")?,
+      DepSource::Path {
+        path: Arc::from("../local-lib"),
+        copy: true
+      }
+    );
+    assert_eq!(
+      parse("{} (:tarball |https://example.com/packed.tar.gz) (:sha256 |9B83346F30D5AA0B)")?,
+      DepSource::Tarball {
+        url: Arc::from("https://example.com/packed.tar.gz"),
+        sha256: Arc::from("9b83346f30d5aa0b")
+      }
+    );
+    Ok(())
+  }
+
+  #[test]
+  fn reject_invalid_sources() {
+    assert!(parse("{} (:git |https://git.example.com/team/lib.git)").is_err());
+    assert!(parse("{} (:tarball |https://example.com/packed.tar.gz)").is_err());
+    assert!(parse("{} (:path |../local-lib) (:copy |yes)").is_err());
+    assert!(parse("{} (:version |0.1.0)").is_err());
+  }
+
+  #[test]
+  fn folder_suffixes() -> Result<(), String> {
+    assert_eq!(parse("do |0.1.0")?.folder_suffix(), "0.1.0");
+    assert_eq!(parse("do |feature/demo")?.folder_suffix(), "feature-demo");
+    assert_eq!(
+      parse("{} (:git |file:///tmp/lib.git) (:version |release/1.0)")?.folder_suffix(),
+      "release-1.0"
+    );
+    assert_eq!(parse("{} (:path |../local-lib)")?.folder_suffix(), "local");
+    assert_eq!(
+      parse("{} (:tarball |packed.tar.gz) (:sha256 |9b83346f30d5aa0be4b39fc5ade0b31a)")?.folder_suffix(),
+      "9b83346f"
+    );
+    Ok(())
+  }
+
+  /// a fresh temp folder with a local library in `lib/`
+  fn demo_dir(name: &str) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("caps-source-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(root.join("lib/.git")).unwrap();
+    fs::create_dir_all(root.join("modules")).unwrap();
+    fs::write(root.join("lib/compact.cirru"), "{}").unwrap();
+    root
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn link_local_folder() -> Result<(), String> {
+    let root = demo_dir("link");
+    let lib = root.join("lib").display().to_string();
+    let folder_path = root.join("modules/lib");
+    assert!(install_path(&folder_path, &lib, false)?);
+    assert_eq!(fs::read_link(&folder_path).map_err(|e| e.to_string())?, root.join("lib"));
+    assert!(!install_path(&folder_path, &lib, false)?);
+    // the link is replaced with a copy
+    assert!(install_path(&folder_path, &lib, true)?);
+    assert!(!fs::symlink_metadata(&folder_path).map_err(|e| e.to_string())?.is_symlink());
+    fs::remove_dir_all(root).map_err(|e| e.to_string())
+  }
+
+  #[test]
+  fn copy_local_folder() -> Result<(), String> {
+    let root = demo_dir("copy");
+    let lib = root.join("lib").display().to_string();
+    let folder_path = root.join("modules/lib");
+    assert!(install_path(&folder_path, &lib, true)?);
+    assert!(folder_path.join("compact.cirru").is_file());
+    assert!(!folder_path.join(".git").exists());
+    // same content, nothing to copy
+    assert!(!install_path(&folder_path, &lib, true)?);
+    fs::write(root.join("lib/compact.cirru"), "{} (:package |lib)").unwrap();
+    assert!(install_path(&folder_path, &lib, true)?);
+    assert_eq!(
+      fs::read_to_string(folder_path.join("compact.cirru")).map_err(|e| e.to_string())?,
+      "{} (:package |lib)"
+    );
+    fs::remove_dir_all(root).map_err(|e| e.to_string())
+  }
+
+  #[test]
+  fn keep_folders_not_installed_by_caps() -> Result<(), String> {
+    let root = demo_dir("keep");
+    let lib = root.join("lib").display().to_string();
+    let cloned = root.join("modules/cloned");
+    fs::create_dir_all(cloned.join(".git")).unwrap();
+    let e = install_path(&cloned, &lib, true).unwrap_err();
+    assert!(e.contains("remove it yourself"), "{}", e);
+    assert!(cloned.join(".git").exists());
+
+    let edited = root.join("modules/edited");
+    fs::create_dir_all(&edited).unwrap();
+    fs::write(edited.join("compact.cirru"), "{}").unwrap();
+    assert!(install_path(&edited, &lib, false).is_err());
+    assert!(edited.join("compact.cirru").exists());
+    fs::remove_dir_all(root).map_err(|e| e.to_string())
+  }
+
+  #[test]
+  fn extract_local_tarball() -> Result<(), String> {
+    let root = demo_dir("tarball");
+    let status = Command::new("tar")
+      .arg("-czf")
+      .arg(root.join("packed.tar.gz"))
+      .arg("-C")
+      .arg(&root)
+      .arg("lib/compact.cirru")
+      .status()
+      .map_err(|e| e.to_string())?;
+    assert!(status.success());
+    let archive = root.join("packed.tar.gz").display().to_string();
+    let sha256 = calcit::file_sha256(&root.join("packed.tar.gz"))?;
+    let cache_dir = root.join("cache");
+    let folder_path = root.join("modules/packed");
+
+    let wrong = "0".repeat(64);
+    let e = install_tarball(&folder_path, &archive, &wrong, &cache_dir, false).unwrap_err();
+    assert!(e.contains("checksum mismatch"), "{}", e);
+    assert!(!folder_path.exists());
+    assert!(!cache_dir.join(format!("{}.tar.gz", wrong)).exists());
+
+    assert!(install_tarball(&folder_path, &archive, &sha256, &cache_dir, true)?);
+    // single top-level folder is unwrapped
+    assert!(folder_path.join("compact.cirru").is_file());
+    assert!(cache_dir.join(format!("{}.tar.gz", sha256)).is_file());
+    assert!(!install_tarball(&folder_path, &archive, &sha256, &cache_dir, true)?);
+    fs::remove_dir_all(root).map_err(|e| e.to_string())
+  }
+}
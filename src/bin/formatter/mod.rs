@@ -0,0 +1,109 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use cirru_edn::{Edn, EdnListView, EdnMapView, EdnRecordView};
+use cirru_parser::{Cirru, CirruWriterOptions};
+use walkdir::WalkDir;
+
+use calcit::cli_args::{FmtCommand, ToplevelCalcit};
+
+/// format the snapshot file, and source files in `src/` next to it like what `bundle_calcit` reads
+pub fn run_fmt(settings: &ToplevelCalcit, options: &FmtCommand) -> Result<(), String> {
+  let input = Path::new(&settings.input);
+  let mut paths: Vec<PathBuf> = vec![];
+  if input.exists() {
+    paths.push(input.to_owned());
+  }
+  let src_dir = input.parent().unwrap_or(Path::new(".")).join("src");
+  if src_dir.is_dir() {
+    let mut sources: Vec<PathBuf> = WalkDir::new(&src_dir)
+      .into_iter()
+      .flatten()
+      .map(|entry| entry.path().to_owned())
+      .filter(|path| path.extension().map(|ext| ext == "cirru").unwrap_or(false))
+      .collect();
+    sources.sort();
+    paths.extend(sources);
+  }
+  if paths.is_empty() {
+    return Err(format!("nothing to format, {} does not exist", settings.input));
+  }
+
+  let mut changed: Vec<&PathBuf> = vec![];
+  for path in &paths {
+    let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+    let formatted = if path == input {
+      format_snapshot(&content)
+    } else {
+      format_source(&content)
+    }
+    .map_err(|e| format!("failed to format {}: {e}", path.display()))?;
+
+    if formatted != content {
+      changed.push(path);
+      if options.check {
+        println!("would reformat {}", path.display());
+      } else {
+        fs::write(path, formatted).map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+        println!("formatted {}", path.display());
+      }
+    }
+  }
+
+  if options.check && !changed.is_empty() {
+    Err(format!("{} of {} files need formatting", changed.len(), paths.len()))
+  } else {
+    println!("checked {} files, {} changed", paths.len(), changed.len());
+    Ok(())
+  }
+}
+
+/// snapshot like `compact.cirru`, maps are sorted by `cirru_edn::format`, records are sorted here
+pub fn format_snapshot(content: &str) -> Result<String, String> {
+  // shebang line is kept as it is
+  let (shebang, text) = match content.split_once('\n') {
+    Some((line, rest)) if line.starts_with("#!") => (&content[..line.len() + 1], rest),
+    _ => ("", content),
+  };
+  let data = cirru_edn::parse(text)?;
+  Ok(format!("{shebang}{}", cirru_edn::format(&sort_records(data), true)?))
+}
+
+/// source file with `ns` form at top, defs are sorted by name after it
+pub fn format_source(content: &str) -> Result<String, String> {
+  let xs = cirru_parser::parse(content)?;
+  let (ns_form, defs) = match xs.split_first() {
+    Some((ns_form @ Cirru::List(ys), defs)) if ys.first().map(|x| x.eq_leaf("ns")).unwrap_or(false) => (ns_form, defs),
+    _ => return Err(String::from("first expression of file should be a ns form")),
+  };
+  let mut named: Vec<(&str, &Cirru)> = Vec::with_capacity(defs.len());
+  for line in defs {
+    match line {
+      Cirru::List(ys) => match (ys.first(), ys.get(1)) {
+        (Some(Cirru::Leaf(x0)), Some(Cirru::Leaf(x1))) if x0.starts_with("def") => named.push((x1, line)),
+        (a, b) => return Err(format!("invalid def code: {a:?} {b:?}")),
+      },
+      Cirru::Leaf(_) => return Err(format!("file line not an expr: {line}")),
+    }
+  }
+  // stable, so defs sharing a name keep their order
+  named.sort_by(|a, b| a.0.cmp(b.0));
+
+  let mut lines = vec![ns_form.to_owned()];
+  lines.extend(named.into_iter().map(|(_, line)| line.to_owned()));
+  cirru_parser::format(&lines, CirruWriterOptions { use_inline: true })
+}
+
+/// record fields keep their order after parsing, sort them so different writers produce same output
+fn sort_records(data: Edn) -> Edn {
+  match data {
+    Edn::Map(EdnMapView(xs)) => Edn::Map(EdnMapView(xs.into_iter().map(|(k, v)| (k, sort_records(v))).collect())),
+    Edn::List(EdnListView(xs)) => Edn::List(EdnListView(xs.into_iter().map(sort_records).collect())),
+    Edn::Record(EdnRecordView { tag, pairs }) => {
+      let mut pairs: Vec<_> = pairs.into_iter().map(|(k, v)| (k, sort_records(v))).collect();
+      pairs.sort_by(|a, b| a.0.cmp(&b.0));
+      Edn::Record(EdnRecordView { tag, pairs })
+    }
+    a => a,
+  }
+}
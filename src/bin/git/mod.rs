@@ -27,23 +27,47 @@ impl GitRepo {
     self.run_command(&["checkout", version]).map(|_a| ())
   }
 
-  /// clone to directory
-  pub fn clone_to(dir: &Path, url: &str, version: &str, shallow: bool) -> Result<(), String> {
+  /// clone into `target`, which is a folder inside an existing directory
+  pub fn clone_to(target: &Path, url: &str, version: &str, shallow: bool) -> Result<(), String> {
+    let dir = target.parent().ok_or("invalid clone target")?;
+    let folder = target.file_name().and_then(|s| s.to_str()).ok_or("invalid clone target")?;
     let container = GitRepo { dir: dir.to_path_buf() };
     if shallow {
-      container.run_command(&["clone", "--branch", version, "--depth", "1", url])?;
+      container.run_command(&["clone", "--branch", version, "--depth", "1", url, folder])?;
     } else {
-      container.run_command(&["clone", "--branch", version, url])?;
+      container.run_command(&["clone", "--branch", version, url, folder])?;
     }
     Ok(())
   }
 
+  /// clone from a local repository, git is not allowed to use any protocol other than `file`
+  /// ```bash
+  /// git -c protocol.allow=never -c protocol.file.allow=always clone --branch <VERSION> <SOURCE> <FOLDER>
+  /// ```
+  pub fn clone_local(target: &Path, source: &str, version: &str) -> Result<(), String> {
+    let dir = target.parent().ok_or("invalid clone target")?;
+    let folder = target.file_name().and_then(|s| s.to_str()).ok_or("invalid clone target")?;
+    let container = GitRepo { dir: dir.to_path_buf() };
+    container.run_command(&[
+      "-c",
+      "protocol.allow=never",
+      "-c",
+      "protocol.file.allow=always",
+      "clone",
+      "--branch",
+      version,
+      source,
+      folder,
+    ])?;
+    Ok(())
+  }
+
   /// get the current head of the repository
   pub fn current_head(&self) -> Result<GitHead, String> {
     let branch = self.run_command(&["branch", "--show-current"])?;
     if branch.is_empty() {
-      // probably a tag
-      Ok(GitHead::Tag(self.describe_tag()?))
+      // probably a tag, or a commit checked out from lockfile
+      Ok(GitHead::Tag(self.describe_tag().or_else(|_| self.rev_parse("HEAD"))?))
     } else {
       Ok(GitHead::Branch(branch))
     }
@@ -74,7 +98,6 @@ impl GitRepo {
   /// ```bash
   /// git rev-parse <REF>
   /// ```
-  #[allow(dead_code)]
   pub fn rev_parse(&self, ref_name: &str) -> Result<String, String> {
     let sha = self.run_command(&["rev-parse", ref_name])?;
     Ok(sha.trim().to_string())
@@ -93,6 +116,29 @@ impl GitRepo {
     }
   }
 
+  /// whether a commit is already in local history
+  /// ```bash
+  /// git cat-file -e <SHA>^{commit}
+  /// ```
+  pub fn has_commit(&self, sha: &str) -> bool {
+    self.run_command(&["cat-file", "-e", &format!("{}^{{commit}}", sha)]).is_ok()
+  }
+
+  /// points origin to the real remote, after cloning from a local copy
+  /// ```bash
+  /// git remote set-url origin <URL>
+  /// ```
+  pub fn set_origin_url(&self, url: &str) -> Result<(), String> {
+    self.run_command(&["remote", "set-url", "origin", url])?;
+    Ok(())
+  }
+
+  /// fetch a single commit, for commits not reachable from branches or tags, or in shallow clones
+  pub fn fetch_commit(&self, sha: &str) -> Result<(), String> {
+    self.run_command(&["fetch", "origin", sha])?;
+    Ok(())
+  }
+
   pub fn fetch(&self) -> Result<(), String> {
     self.run_command(&["fetch", "origin", "--tags"])?;
     Ok(())
@@ -107,6 +153,17 @@ impl GitRepo {
     self.run_command(&["pull", "origin", branch])?;
     Ok(())
   }
+
+  /// moves local branch to the latest commit of remote, local commits are dropped
+  /// ```bash
+  /// git fetch origin <BRANCH>
+  /// git reset --hard origin/<BRANCH>
+  /// ```
+  pub fn reset_to_origin(&self, branch: &str) -> Result<(), String> {
+    self.run_command(&["fetch", "origin", branch])?;
+    self.run_command(&["reset", "--hard", &format!("origin/{}", branch)])?;
+    Ok(())
+  }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -1,7 +1,8 @@
 use crate::runner;
 use cirru_edn::Edn;
 use std::collections::HashMap;
-use std::sync::{Arc, LazyLock, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, Once};
 use std::thread;
 
 use calcit::{
@@ -45,7 +46,6 @@ pub fn inject_platform_apis() {
   builtins::register_import_proc("&blocking-dylib-edn-fn", blocking_dylib_edn_fn);
   builtins::register_import_proc("async-sleep", builtins::meta::async_sleep);
   builtins::register_import_proc("on-control-c", on_ctrl_c);
-  println!("registered platform APIs");
 }
 
 // &call-dylib-edn
@@ -267,22 +267,59 @@ pub fn blocking_dylib_edn_fn(xs: Vec<Calcit>, call_stack: &CallStackList) -> Res
   Ok(Calcit::Nil)
 }
 
+/// Ctrl-C handler can only be set once, callback and persisting path are shared with it
+#[derive(Default)]
+struct CtrlCState {
+  callback: Option<(Calcit, CallStackList)>,
+  persist_path: Option<PathBuf>,
+}
+
+static CTRL_C_STATE: LazyLock<Mutex<CtrlCState>> = LazyLock::new(|| Mutex::new(CtrlCState::default()));
+static CTRL_C_HANDLER: Once = Once::new();
+
+fn install_ctrl_c_handler() {
+  CTRL_C_HANDLER.call_once(|| {
+    ctrlc::set_handler(handle_ctrl_c).expect("Error setting Ctrl-C handler");
+  });
+}
+
+fn handle_ctrl_c() {
+  let (callback, persist_path) = {
+    let state = CTRL_C_STATE.lock().expect("lock ctrl-c state");
+    (state.callback.to_owned(), state.persist_path.to_owned())
+  };
+  if let Some(path) = persist_path {
+    match builtins::persist_refs(&path, None) {
+      Ok(size) => println!("persisted {size} atoms to {}", path.display()),
+      Err(e) => eprintln!("failed to persist atoms, {e}"),
+    }
+  }
+  match callback {
+    Some((Calcit::Fn { info, .. }, call_stack)) => {
+      if let Err(e) = runner::run_fn(&[], &info, &call_stack) {
+        eprintln!("error: {e}");
+      }
+    }
+    // without a callback, keep the default behavior of exiting
+    _ => std::process::exit(130),
+  }
+}
+
+/// persist atoms to the path before exiting by Ctrl-C
+pub fn persist_on_ctrl_c(path: &Path) {
+  CTRL_C_STATE.lock().expect("lock ctrl-c state").persist_path = Some(path.to_owned());
+  install_ctrl_c_handler();
+}
+
 /// need to put it here since the crate does not compile for dylib
 #[no_mangle]
 pub fn on_ctrl_c(xs: Vec<Calcit>, call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
-  if xs.len() == 1 {
-    let cb = Arc::new(xs[0].to_owned());
-    let copied_stack = Arc::new(call_stack.to_owned());
-    ctrlc::set_handler(move || {
-      if let Calcit::Fn { info, .. } = cb.as_ref() {
-        if let Err(e) = runner::run_fn(&[], info, &copied_stack) {
-          eprintln!("error: {e}");
-        }
-      }
-    })
-    .expect("Error setting Ctrl-C handler");
-    Ok(Calcit::Nil)
-  } else {
-    CalcitErr::err_str(format!("on-control-c expected a callback function {xs:?}"))
+  match xs.as_slice() {
+    [f @ Calcit::Fn { .. }] => {
+      CTRL_C_STATE.lock().expect("lock ctrl-c state").callback = Some((f.to_owned(), call_stack.to_owned()));
+      install_ctrl_c_handler();
+      Ok(Calcit::Nil)
+    }
+    _ => CalcitErr::err_str(format!("on-control-c expected a callback function {xs:?}")),
   }
 }
@@ -0,0 +1,576 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::sync::Arc;
+
+use cirru_edn::{Edn, EdnListView};
+
+use calcit::{
+  calcit::{Calcit, CalcitProc, LocatedWarning, CORE_NS},
+  call_stack::CallStackList,
+  cli_args::LintCommand,
+  program::{self, ImportRule, ProgramFileData},
+  runner,
+  snapshot::{LintRuleConfig, Snapshot},
+  util::string::extract_ns_def,
+  ProgramEntries,
+};
+
+/// defs written in this macro are called by test runner
+const TEST_MACRO: &str = "deftest";
+
+/// rules are all turned on unless disabled in `:lint` of configs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum LintRule {
+  /// warnings from preprocessing, like unknown symbols and arity mismatches
+  Preprocess,
+  UnusedDef,
+  UnusedLocal,
+  UnusedImport,
+  UnreachableBranch,
+  DuplicatedKey,
+  Deprecated,
+}
+
+const ALL_RULES: [LintRule; 7] = [
+  LintRule::Preprocess,
+  LintRule::UnusedDef,
+  LintRule::UnusedLocal,
+  LintRule::UnusedImport,
+  LintRule::UnreachableBranch,
+  LintRule::DuplicatedKey,
+  LintRule::Deprecated,
+];
+
+impl LintRule {
+  fn name(&self) -> &'static str {
+    match self {
+      LintRule::Preprocess => "preprocess",
+      LintRule::UnusedDef => "unused-def",
+      LintRule::UnusedLocal => "unused-local",
+      LintRule::UnusedImport => "unused-import",
+      LintRule::UnreachableBranch => "unreachable-branch",
+      LintRule::DuplicatedKey => "duplicated-key",
+      LintRule::Deprecated => "deprecated",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct LintWarning {
+  ns: Arc<str>,
+  /// `None` for problems in the ns form
+  def: Option<Arc<str>>,
+  coord: Vec<u8>,
+  rule: LintRule,
+  message: String,
+}
+
+impl fmt::Display for LintWarning {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "warning[{}] {} @{}", self.rule.name(), self.message, self.ns)?;
+    if let Some(def) = &self.def {
+      write!(f, "/{def}")?;
+    }
+    if !self.coord.is_empty() {
+      write!(f, " {}", self.coord.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("-"))?;
+    }
+    Ok(())
+  }
+}
+
+impl From<&LintWarning> for Edn {
+  fn from(w: &LintWarning) -> Edn {
+    Edn::map_from_iter([
+      (Edn::tag("rule"), Edn::tag(w.rule.name())),
+      (Edn::tag("message"), Edn::str(w.message.as_str())),
+      (Edn::tag("ns"), Edn::str(&*w.ns)),
+      (Edn::tag("def"), w.def.as_ref().map(|d| Edn::str(&**d)).unwrap_or(Edn::Nil)),
+      (
+        Edn::tag("coord"),
+        EdnListView(w.coord.iter().map(|x| Edn::Number(*x as f64)).collect()).into(),
+      ),
+    ])
+  }
+}
+
+/// check namespaces of the package in snapshot, returns error when some warnings are found
+pub fn run_lint(snapshot: &Snapshot, entries: &ProgramEntries, options: &LintCommand) -> Result<(), String> {
+  let warnings = collect_warnings(snapshot, entries)?;
+  let pkg = &snapshot.package;
+  let namespaces = package_namespaces(pkg);
+
+  match &options.report {
+    Some(path) => {
+      let xs = EdnListView(warnings.iter().map(Edn::from).collect());
+      let content = cirru_edn::format(&xs.into(), true)?;
+      fs::write(path, content).map_err(|e| format!("failed to write report {path}: {e}"))?;
+      println!("report wrote to {path}");
+    }
+    None => {
+      for w in &warnings {
+        println!("{w}");
+      }
+    }
+  }
+  println!("\nchecked {} namespaces, found {} warnings", namespaces.len(), warnings.len());
+
+  if warnings.is_empty() {
+    Ok(())
+  } else {
+    Err(format!("found {} lint warnings", warnings.len()))
+  }
+}
+
+/// namespaces of the package in loaded program, `$meta` is skipped
+fn package_namespaces(pkg: &str) -> Vec<Arc<str>> {
+  let program_code = program::PROGRAM_CODE_DATA.read().expect("read program code");
+  let meta_ns = format!("{pkg}.$meta");
+  let mut namespaces: Vec<Arc<str>> = program_code
+    .keys()
+    .filter(|ns| (&***ns == pkg || ns.starts_with(&format!("{pkg}."))) && ***ns != *meta_ns)
+    .map(ToOwned::to_owned)
+    .collect();
+  namespaces.sort();
+  namespaces
+}
+
+/// warnings of the package, sorted
+fn collect_warnings(snapshot: &Snapshot, entries: &ProgramEntries) -> Result<Vec<LintWarning>, String> {
+  let (rules, skipped) = enabled_rules(&snapshot.configs.lint)?;
+  let program_code = program::PROGRAM_CODE_DATA.read().expect("read program code").to_owned();
+  let namespaces = package_namespaces(&snapshot.package);
+
+  let mut deprecated: HashMap<(Arc<str>, Arc<str>), String> = HashMap::new();
+  for (ns, file) in &snapshot.files {
+    for (def, entry) in &file.defs {
+      if let Some(note) = deprecation_note(&entry.doc) {
+        deprecated.insert((ns.to_owned(), def.to_owned()), note);
+      }
+    }
+  }
+
+  let mut linter = Linter {
+    program_code: &program_code,
+    deprecated: &deprecated,
+    rules: &rules,
+    references: HashSet::new(),
+    used_imports: HashSet::new(),
+    warnings: vec![],
+  };
+
+  // references from other packages are also counted, core does not use user code
+  let mut all_namespaces: Vec<&Arc<str>> = program_code.keys().filter(|ns| &***ns != CORE_NS).collect();
+  all_namespaces.sort();
+  for ns in all_namespaces {
+    let report = namespaces.contains(ns);
+    let mut defs: Vec<(&Arc<str>, &Calcit)> = program_code[ns].defs.iter().collect();
+    defs.sort_by(|a, b| a.0.cmp(b.0));
+    for (def, code) in defs {
+      let mut walker = DefWalker {
+        linter: &mut linter,
+        ns: ns.to_owned(),
+        def: def.to_owned(),
+        report,
+      };
+      walker.walk(code);
+    }
+  }
+
+  let roots = roots(snapshot, entries);
+  for ns in &namespaces {
+    let file = &program_code[ns];
+    linter.check_imports(ns, file);
+    linter.check_defs(ns, file, &roots);
+  }
+
+  if rules.contains(&LintRule::Preprocess) {
+    linter.warnings.extend(preprocess_warnings(&namespaces, &program_code));
+  }
+
+  let mut warnings = linter.warnings;
+  warnings.retain(|w| match (skipped.get(&w.rule), &w.def) {
+    (Some(defs), Some(def)) => !defs.contains(&*format!("{}/{def}", w.ns)),
+    _ => true,
+  });
+  warnings.sort();
+  warnings.dedup();
+  Ok(warnings)
+}
+
+/// rules turned on, along with `ns/def`s where some of them are skipped
+type EnabledRules = (Vec<LintRule>, HashMap<LintRule, HashSet<Arc<str>>>);
+
+fn enabled_rules(configs: &HashMap<Arc<str>, LintRuleConfig>) -> Result<EnabledRules, String> {
+  for name in configs.keys() {
+    if !ALL_RULES.iter().any(|r| r.name() == &**name) {
+      let names: Vec<&str> = ALL_RULES.iter().map(|r| r.name()).collect();
+      return Err(format!("unknown lint rule `{name}`, expected one of: {}", names.join(" ")));
+    }
+  }
+  let mut rules = vec![];
+  let mut skipped = HashMap::new();
+  for rule in ALL_RULES {
+    match configs.get(rule.name()) {
+      None | Some(LintRuleConfig::Enabled(true)) => rules.push(rule),
+      Some(LintRuleConfig::Enabled(false)) => {}
+      Some(LintRuleConfig::SkippedIn(defs)) => {
+        rules.push(rule);
+        skipped.insert(rule, defs.to_owned());
+      }
+    }
+  }
+  Ok((rules, skipped))
+}
+
+/// docs starting with "deprecated" mark a def as deprecated, the first line is used as message
+fn deprecation_note(doc: &str) -> Option<String> {
+  let line = doc.trim().lines().next()?.trim();
+  if line.to_lowercase().starts_with("deprecated") {
+    Some(line.to_owned())
+  } else {
+    None
+  }
+}
+
+/// defs called from outside, treated as used
+fn roots(snapshot: &Snapshot, entries: &ProgramEntries) -> HashSet<(Arc<str>, Arc<str>)> {
+  let mut fns: Vec<&str> = vec![
+    &entries.init_fn,
+    &entries.reload_fn,
+    &snapshot.configs.init_fn,
+    &snapshot.configs.reload_fn,
+  ];
+  for configs in snapshot.entries.values() {
+    fns.push(&configs.init_fn);
+    fns.push(&configs.reload_fn);
+  }
+  fns
+    .into_iter()
+    .filter_map(|f| extract_ns_def(f).ok())
+    .map(|(ns, def)| (Arc::from(ns), Arc::from(def)))
+    .collect()
+}
+
+/// warnings of preprocessing every def, same as what running the program reports
+fn preprocess_warnings(namespaces: &[Arc<str>], program_code: &HashMap<Arc<str>, ProgramFileData>) -> Vec<LintWarning> {
+  let check_warnings = RefCell::new(LocatedWarning::default_list());
+  let stack = CallStackList::default();
+  let mut xs: Vec<LintWarning> = vec![];
+  for ns in namespaces {
+    let mut defs: Vec<&Arc<str>> = program_code[ns].defs.keys().collect();
+    defs.sort();
+    for def in defs {
+      if let Err(e) = runner::preprocess::preprocess_ns_def(ns, def, &check_warnings, &stack) {
+        xs.push(LintWarning {
+          ns: ns.to_owned(),
+          def: Some(def.to_owned()),
+          coord: vec![],
+          rule: LintRule::Preprocess,
+          message: e.msg,
+        });
+      }
+    }
+  }
+  for warning in check_warnings.borrow().iter() {
+    let location = warning.location();
+    if namespaces.contains(&location.ns) {
+      xs.push(LintWarning {
+        ns: location.ns.to_owned(),
+        def: Some(location.def.to_owned()),
+        coord: location.coord.to_vec(),
+        rule: LintRule::Preprocess,
+        message: warning.message().to_owned(),
+      });
+    }
+  }
+  xs
+}
+
+struct Linter<'a> {
+  program_code: &'a HashMap<Arc<str>, ProgramFileData>,
+  deprecated: &'a HashMap<(Arc<str>, Arc<str>), String>,
+  rules: &'a [LintRule],
+  /// defs referenced from other defs
+  references: HashSet<(Arc<str>, Arc<str>)>,
+  /// names in import maps that are used, by ns
+  used_imports: HashSet<(Arc<str>, Arc<str>)>,
+  warnings: Vec<LintWarning>,
+}
+
+impl Linter<'_> {
+  fn add(&mut self, rule: LintRule, ns: &Arc<str>, def: Option<&Arc<str>>, coord: Vec<u8>, message: String) {
+    if self.rules.contains(&rule) {
+      self.warnings.push(LintWarning {
+        ns: ns.to_owned(),
+        def: def.map(ToOwned::to_owned),
+        coord,
+        rule,
+        message,
+      });
+    }
+  }
+
+  fn check_imports(&mut self, ns: &Arc<str>, file: &ProgramFileData) {
+    let mut names: Vec<(&Arc<str>, &Arc<ImportRule>)> = file.import_map.iter().collect();
+    names.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, rule) in names {
+      if !self.used_imports.contains(&(ns.to_owned(), name.to_owned())) {
+        let message = match &**rule {
+          ImportRule::NsReferDef(target, _) => format!("`{name}` is referred from `{target}` but not used"),
+          ImportRule::NsAs(target) | ImportRule::NsDefault(target) => format!("`{target}` is imported as `{name}` but not used"),
+        };
+        self.add(LintRule::UnusedImport, ns, None, vec![], message);
+      }
+    }
+  }
+
+  fn check_defs(&mut self, ns: &Arc<str>, file: &ProgramFileData, roots: &HashSet<(Arc<str>, Arc<str>)>) {
+    let mut defs: Vec<(&Arc<str>, &Calcit)> = file.defs.iter().collect();
+    defs.sort_by(|a, b| a.0.cmp(b.0));
+    for (def, code) in defs {
+      let key = (ns.to_owned(), def.to_owned());
+      if !self.references.contains(&key) && !roots.contains(&key) && !is_test(code) {
+        self.add(
+          LintRule::UnusedDef,
+          ns,
+          Some(def),
+          vec![],
+          format!("`{def}` is defined but not used"),
+        );
+      }
+    }
+  }
+}
+
+fn is_test(code: &Calcit) -> bool {
+  match code {
+    Calcit::List(xs) => matches!(xs.first(), Some(Calcit::Symbol { sym, .. }) if &**sym == TEST_MACRO),
+    _ => false,
+  }
+}
+
+/// walks code of a def before macro expanding, locals are not tracked so a local sharing name with a def counts as a reference
+struct DefWalker<'a, 'b> {
+  linter: &'a mut Linter<'b>,
+  ns: Arc<str>,
+  def: Arc<str>,
+  /// only references are collected from code outside the package
+  report: bool,
+}
+
+impl DefWalker<'_, '_> {
+  fn warn(&mut self, rule: LintRule, coord: Vec<u8>, message: String) {
+    if self.report {
+      self.linter.add(rule, &self.ns, Some(&self.def), coord, message);
+    }
+  }
+
+  fn walk(&mut self, code: &Calcit) {
+    match code {
+      Calcit::Symbol { sym, location, .. } => {
+        if let Some((ns, def)) = self.resolve(sym) {
+          if ns != self.ns || def != self.def {
+            if let Some(note) = self.linter.deprecated.get(&(ns.to_owned(), def.to_owned())) {
+              let message = format!("`{sym}` is deprecated: {note}");
+              self.warn(LintRule::Deprecated, coord_of(location), message);
+            }
+            self.linter.references.insert((ns, def));
+          }
+        }
+      }
+      Calcit::List(xs) => {
+        let xs = xs.to_vec();
+        self.check_form(&xs);
+        for x in xs.iter() {
+          self.walk(x);
+        }
+      }
+      _ => {}
+    }
+  }
+
+  /// symbols are resolved as preprocessing does, except that locals are skipped
+  fn resolve(&mut self, sym: &Arc<str>) -> Option<(Arc<str>, Arc<str>)> {
+    let file = self.linter.program_code.get(&self.ns)?;
+    if let Some((alias, name)) = sym.split_once('/') {
+      if alias.is_empty() || name.is_empty() {
+        return None;
+      }
+      let target = match file.import_map.get(alias).map(|r| &**r) {
+        Some(ImportRule::NsAs(ns)) | Some(ImportRule::NsDefault(ns)) => {
+          self.linter.used_imports.insert((self.ns.to_owned(), Arc::from(alias)));
+          ns.to_owned()
+        }
+        _ => Arc::from(alias),
+      };
+      return Some((target, Arc::from(name)));
+    }
+    if file.defs.contains_key(sym) {
+      return Some((self.ns.to_owned(), sym.to_owned()));
+    }
+    match file.import_map.get(sym).map(|r| &**r) {
+      Some(rule) => {
+        self.linter.used_imports.insert((self.ns.to_owned(), sym.to_owned()));
+        match rule {
+          ImportRule::NsReferDef(ns, def) => Some((ns.to_owned(), def.to_owned())),
+          _ => None,
+        }
+      }
+      None => None,
+    }
+  }
+
+  fn check_form(&mut self, xs: &[Calcit]) {
+    let (head, location) = match xs.first() {
+      Some(Calcit::Symbol { sym, location, .. }) => (&**sym, location),
+      Some(Calcit::Proc(CalcitProc::NativeMap)) => {
+        self.check_keys(xs[1..].chunks(2).filter_map(|pair| pair.first()), vec![]);
+        return;
+      }
+      _ => return,
+    };
+    match head {
+      "if" => {
+        let branch = match xs.get(1) {
+          Some(Calcit::Bool(false) | Calcit::Nil) => "then",
+          Some(Calcit::Bool(true) | Calcit::Number(_) | Calcit::BigInt(_) | Calcit::Str(_) | Calcit::Tag(_)) => "else",
+          _ => return,
+        };
+        let message = format!("condition of `if` is a literal, {branch} branch is unreachable");
+        self.warn(LintRule::UnreachableBranch, coord_of(location), message);
+      }
+      "{}" => {
+        let keys = xs[1..].iter().filter_map(|pair| match pair {
+          Calcit::List(ys) => ys.first(),
+          _ => None,
+        });
+        self.check_keys(keys, coord_of(location));
+      }
+      // args of `fn` are not checked, they are usually callbacks with signatures decided by callers
+      "defn" | "defmacro" => self.check_args(xs.get(2), xs.get(3..)),
+      "&let" => {
+        if let Some(Calcit::List(pair)) = xs.get(1) {
+          self.check_local(pair.first(), xs.get(2..).unwrap_or_default());
+        }
+      }
+      "let" => {
+        if let Some(Calcit::List(pairs)) = xs.get(1) {
+          let pairs = pairs.to_vec();
+          for (idx, pair) in pairs.iter().enumerate() {
+            if let Calcit::List(ys) = pair {
+              // later bindings are also in scope
+              let mut scope: Vec<Calcit> = pairs[idx + 1..].to_vec();
+              scope.extend(xs[2..].iter().cloned());
+              self.check_local(ys.first(), &scope);
+            }
+          }
+        }
+      }
+      _ => {}
+    }
+  }
+
+  fn check_args(&mut self, args: Option<&Calcit>, body: Option<&[Calcit]>) {
+    if let Some(Calcit::List(args)) = args {
+      for arg in args.iter() {
+        // `&` and `?` mark rest and optional arguments
+        if !matches!(arg, Calcit::Symbol { sym, .. } if &**sym == "&" || &**sym == "?") {
+          self.check_local(Some(arg), body.unwrap_or_default());
+        }
+      }
+    }
+  }
+
+  /// names starting with `_` are meant to be unused
+  fn check_local(&mut self, name: Option<&Calcit>, scope: &[Calcit]) {
+    if let Some(Calcit::Symbol { sym, location, .. }) = name {
+      if !sym.starts_with('_') && !scope.iter().any(|x| has_symbol(x, sym)) {
+        self.warn(LintRule::UnusedLocal, coord_of(location), format!("`{sym}` is bound but not used"));
+      }
+    }
+  }
+
+  fn check_keys<'c>(&mut self, keys: impl Iterator<Item = &'c Calcit>, coord: Vec<u8>) {
+    let mut seen: HashSet<String> = HashSet::new();
+    for key in keys {
+      if matches!(
+        key,
+        Calcit::Tag(_) | Calcit::Str(_) | Calcit::Number(_) | Calcit::BigInt(_) | Calcit::Bool(_) | Calcit::Nil
+      ) {
+        let text = key.to_string();
+        if !seen.insert(text.to_owned()) {
+          self.warn(
+            LintRule::DuplicatedKey,
+            coord.to_owned(),
+            format!("duplicated key `{text}` in map literal"),
+          );
+        }
+      }
+    }
+  }
+}
+
+fn coord_of(location: &Option<Arc<Vec<u8>>>) -> Vec<u8> {
+  location.as_ref().map(|l| l.to_vec()).unwrap_or_default()
+}
+
+fn has_symbol(code: &Calcit, name: &str) -> bool {
+  match code {
+    Calcit::Symbol { sym, .. } => &**sym == name,
+    Calcit::List(xs) => xs.iter().any(|x| has_symbol(x, name)),
+    _ => false,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use calcit::{calcit::BUILTIN_CLASSES_ENTRY, snapshot};
+
+  /// loads the fixture as `cr` does before linting
+  fn lint_fixture() -> Result<Vec<LintWarning>, String> {
+    let data = cirru_edn::parse(include_str!("fixture.cirru"))?;
+    let mut snapshot = snapshot::load_snapshot_data(&data, "fixture.cirru")?;
+    let entries = ProgramEntries {
+      init_fn: Arc::from("lint-demo.main/main!"),
+      init_ns: Arc::from("lint-demo.main"),
+      init_def: Arc::from("main!"),
+      reload_fn: Arc::from("lint-demo.main/reload!"),
+      reload_ns: Arc::from("lint-demo.main"),
+      reload_def: Arc::from("reload!"),
+    };
+    snapshot.files.extend(calcit::load_core_snapshot()?.files);
+    *program::PROGRAM_CODE_DATA.write().expect("open program data") = program::extract_program_data(&snapshot)?;
+    let check_warnings = RefCell::new(vec![]);
+    runner::preprocess::preprocess_ns_def(CORE_NS, BUILTIN_CLASSES_ENTRY, &check_warnings, &CallStackList::default())
+      .map_err(|e| e.msg)?;
+    collect_warnings(&snapshot, &entries)
+  }
+
+  #[test]
+  fn reports_each_rule() -> Result<(), String> {
+    let warnings = lint_fixture()?;
+    let found: Vec<(&str, Option<&str>)> = warnings.iter().map(|w| (w.rule.name(), w.def.as_deref())).collect();
+    let expected = [
+      ("unused-def", Some("spare")),
+      ("unused-import", None),
+      ("duplicated-key", Some("duplicated-key")),
+      ("deprecated", Some("main!")),
+      ("preprocess", Some("preprocess")),
+      ("unreachable-branch", Some("unreachable-branch")),
+      ("unused-def", Some("unused-def")),
+      ("unused-local", Some("unused-local")),
+    ];
+    assert_eq!(found, expected);
+    for rule in ALL_RULES {
+      assert!(found.iter().any(|(name, _)| *name == rule.name()), "no fixture for {}", rule.name());
+    }
+    // args of `fn`, `&` and `?` markers, and `_` names are exempted
+    assert!(!found.iter().any(|(_, def)| *def == Some("exempted")));
+    // skipped in `:lint` of configs
+    assert!(!found.iter().any(|(_, def)| *def == Some("skipped-branch")));
+    Ok(())
+  }
+}
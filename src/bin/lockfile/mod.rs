@@ -0,0 +1,197 @@
+//! `deps.lock.cirru` records the commit each dependency was resolved to,
+//! so that `caps` installs the same code on every machine until `caps update`.
+//! local paths and tarballs are not locked, tarballs are pinned by checksums already
+
+use std::{
+  collections::HashMap,
+  fs,
+  path::{Path, PathBuf},
+  sync::Arc,
+};
+
+use cirru_edn::{Edn, EdnMapView};
+
+pub const LOCK_FILENAME: &str = "deps.lock.cirru";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedDep {
+  /// version written in `deps.cirru` when it was resolved
+  pub version: Arc<str>,
+  /// git URL when it's not from GitHub
+  pub url: Option<Arc<str>>,
+  pub commit: Arc<str>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DepsLock {
+  pub dependencies: HashMap<Arc<str>, LockedDep>,
+  /// sha256 of `compact.cirru` of each installed module, keyed by path inside modules folder, checked by `cr` on start
+  pub integrity: HashMap<Arc<str>, Arc<str>>,
+}
+
+impl TryFrom<Edn> for DepsLock {
+  type Error = String;
+
+  #[allow(clippy::mutable_key_type)]
+  fn try_from(value: Edn) -> Result<Self, Self::Error> {
+    let dict = value.view_map()?.get_or_nil("dependencies").view_map()?.0;
+    let mut deps: HashMap<Arc<str>, LockedDep> = HashMap::new();
+    for (k, v) in &dict {
+      let name = match k {
+        Edn::Str(s) => s.to_owned(),
+        _ => return Err(format!("invalid locked dependency: {}", k)),
+      };
+      let info = v.view_map()?;
+      let url = match info.get_or_nil("url") {
+        Edn::Str(url) => Some(url),
+        Edn::Nil => None,
+        v => return Err(format!("invalid url of locked dependency {}: {}", name, v)),
+      };
+      match (info.get_or_nil("version"), info.get_or_nil("commit")) {
+        (Edn::Str(version), Edn::Str(commit)) => {
+          deps.insert(name, LockedDep { version, url, commit });
+        }
+        (a, b) => return Err(format!("invalid locked dependency {}: {} {}", name, a, b)),
+      }
+    }
+    let mut integrity: HashMap<Arc<str>, Arc<str>> = HashMap::new();
+    if let Edn::Map(dict) = value.view_map()?.get_or_nil("integrity") {
+      for (k, v) in &dict.0 {
+        match (k, v) {
+          (Edn::Str(path), Edn::Str(hash)) => {
+            integrity.insert(path.to_owned(), hash.to_owned());
+          }
+          _ => return Err(format!("invalid integrity entry: {} {}", k, v)),
+        }
+      }
+    }
+    Ok(DepsLock {
+      dependencies: deps,
+      integrity,
+    })
+  }
+}
+
+impl From<&DepsLock> for Edn {
+  fn from(lock: &DepsLock) -> Edn {
+    let mut deps = EdnMapView::default();
+    for (name, dep) in &lock.dependencies {
+      let mut info = EdnMapView::default();
+      info.insert_key("version", Edn::Str(dep.version.to_owned()));
+      if let Some(url) = &dep.url {
+        info.insert_key("url", Edn::Str(url.to_owned()));
+      }
+      info.insert_key("commit", Edn::Str(dep.commit.to_owned()));
+      deps.insert(Edn::Str(name.to_owned()), info.into());
+    }
+    let mut data = EdnMapView::default();
+    data.insert_key("dependencies", deps.into());
+    if !lock.integrity.is_empty() {
+      let mut integrity = EdnMapView::default();
+      for (path, hash) in &lock.integrity {
+        integrity.insert(Edn::Str(path.to_owned()), Edn::Str(hash.to_owned()));
+      }
+      data.insert_key("integrity", integrity.into());
+    }
+    data.into()
+  }
+}
+
+impl DepsLock {
+  /// lockfile sits next to the deps file
+  pub fn path_for(deps_file: &str) -> PathBuf {
+    Path::new(deps_file).with_file_name(LOCK_FILENAME)
+  }
+
+  /// `None` when there's no lockfile yet
+  pub fn read(path: &Path) -> Result<Option<Self>, String> {
+    if !path.exists() {
+      return Ok(None);
+    }
+    let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+    let lock: DepsLock = cirru_edn::parse(&content)?.try_into()?;
+    Ok(Some(lock))
+  }
+
+  pub fn write(&self, path: &Path) -> Result<(), String> {
+    let content = cirru_edn::format(&Edn::from(self), true)?;
+    fs::write(path, content).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+  }
+
+  /// commit of a dependency, only when it was locked from the same version and URL
+  pub fn commit_of(&self, name: &str, version: &str, url: Option<&str>) -> Option<Arc<str>> {
+    match self.dependencies.get(name) {
+      Some(dep) if &*dep.version == version && dep.url.as_deref() == url => Some(dep.commit.to_owned()),
+      _ => None,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn demo_lock() -> DepsLock {
+    let mut lock = DepsLock::default();
+    lock.dependencies.insert(
+      Arc::from("org/lib"),
+      LockedDep {
+        version: Arc::from("0.1.0"),
+        url: None,
+        commit: Arc::from("aaf9cb46cad0ba902108ffd892f5b3d2d8870624"),
+      },
+    );
+    lock.dependencies.insert(
+      Arc::from("org/other"),
+      LockedDep {
+        version: Arc::from("main"),
+        url: Some(Arc::from("https://git.example.com/org/other.git")),
+        commit: Arc::from("061578252fd6e6734c42f14386f16da18e05e432"),
+      },
+    );
+    lock
+      .integrity
+      .insert(Arc::from("lib/compact.cirru"), Arc::from("7448935139bb7f76d2ff76113a90965d"));
+    lock
+  }
+
+  #[test]
+  fn round_trip() -> Result<(), String> {
+    let lock = demo_lock();
+    let content = cirru_edn::format(&Edn::from(&lock), true)?;
+    let parsed: DepsLock = cirru_edn::parse(&content)?.try_into()?;
+    assert_eq!(parsed, lock);
+
+    let empty: DepsLock = cirru_edn::parse(&cirru_edn::format(&Edn::from(&DepsLock::default()), true)?)?.try_into()?;
+    assert_eq!(empty, DepsLock::default());
+    Ok(())
+  }
+
+  #[test]
+  fn commit_matches_version_and_url() {
+    let lock = demo_lock();
+    assert_eq!(
+      lock.commit_of("org/lib", "0.1.0", None).as_deref(),
+      Some("aaf9cb46cad0ba902108ffd892f5b3d2d8870624")
+    );
+    assert_eq!(lock.commit_of("org/lib", "0.2.0", None), None);
+    assert_eq!(
+      lock.commit_of("org/lib", "0.1.0", Some("https://git.example.com/org/lib.git")),
+      None
+    );
+    assert_eq!(
+      lock
+        .commit_of("org/other", "main", Some("https://git.example.com/org/other.git"))
+        .as_deref(),
+      Some("061578252fd6e6734c42f14386f16da18e05e432")
+    );
+    assert_eq!(lock.commit_of("org/other", "main", None), None);
+    assert_eq!(lock.commit_of("org/missing", "0.1.0", None), None);
+  }
+
+  #[test]
+  fn rejects_invalid_entries() {
+    let missing_commit = cirru_edn::parse("{} $ :dependencies $ {} (|org/lib $ {} (:version |0.1.0))").unwrap();
+    assert!(DepsLock::try_from(missing_commit).is_err());
+  }
+}
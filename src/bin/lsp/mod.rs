@@ -0,0 +1,502 @@
+mod workspace;
+
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cirru_parser::Cirru;
+use serde_json::{json, Value};
+use strum::IntoEnumIterator;
+
+use calcit::{
+  builtins,
+  calcit::{Calcit, CalcitProc, CalcitSyntax, LocatedWarning, NodeLocation, BUILTIN_CLASSES_ENTRY, CORE_NS},
+  call_stack::CallStackList,
+  cli_args::{ToplevelCalcit, CALCIT_VERSION},
+  program::{self, ImportRule},
+  runner, snapshot,
+};
+
+use crate::injection;
+use workspace::Workspace;
+
+const SEVERITY_ERROR: u8 = 1;
+const SEVERITY_WARNING: u8 = 2;
+
+const KIND_FUNCTION: u8 = 3;
+const KIND_VARIABLE: u8 = 6;
+const KIND_MODULE: u8 = 9;
+const KIND_KEYWORD: u8 = 14;
+
+/// what a symbol refers to
+enum Target {
+  Def(Arc<str>, Arc<str>),
+  Ns(Arc<str>),
+  Proc(CalcitProc),
+  Syntax(CalcitSyntax),
+}
+
+/// serve LSP over stdio, messages are handled one by one
+pub fn start_lsp(settings: &ToplevelCalcit, module_folder: &Path) -> Result<(), String> {
+  // macros may print during reloading, which should not break the protocol on stdout
+  builtins::register_import_proc("echo", injection::stderr_println);
+  builtins::register_import_proc("println", injection::stderr_println);
+
+  let mut ws = Workspace::load(Path::new(&settings.input), module_folder)?;
+  if let Err(e) = reload_program(&ws) {
+    eprintln!("failed to load program: {e}");
+  }
+
+  let stdin = io::stdin();
+  let mut reader = stdin.lock();
+  let mut stdout = io::stdout();
+
+  while let Some(message) = read_message(&mut reader)? {
+    let message = match message {
+      Ok(v) => v,
+      Err(e) => {
+        eprintln!("skipped message: {e}");
+        continue;
+      }
+    };
+    let method = message.get("method").and_then(Value::as_str).unwrap_or("");
+    let params = message.get("params").unwrap_or(&Value::Null);
+    let result: Option<Value> = match method {
+      "initialize" => Some(json!({
+        "capabilities": {
+          "textDocumentSync": { "openClose": true, "change": 1, "save": { "includeText": true } },
+          "definitionProvider": true,
+          "hoverProvider": true,
+          "completionProvider": { "triggerCharacters": ["/"] },
+        },
+        "serverInfo": { "name": "calcit", "version": CALCIT_VERSION },
+      })),
+      "shutdown" => Some(Value::Null),
+      "exit" => break,
+      "textDocument/didOpen" => {
+        let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+        check_document(&mut ws, &mut stdout, params, text.to_owned())?;
+        None
+      }
+      "textDocument/didChange" => {
+        // only full sync is declared in capabilities
+        if let Some(change) = params["contentChanges"].as_array().and_then(|xs| xs.last()) {
+          let text = change["text"].as_str().unwrap_or_default();
+          check_document(&mut ws, &mut stdout, params, text.to_owned())?;
+        }
+        None
+      }
+      "textDocument/didSave" => {
+        if let Some(text) = params["text"].as_str() {
+          check_document(&mut ws, &mut stdout, params, text.to_owned())?;
+        }
+        None
+      }
+      "textDocument/didClose" => {
+        ws.documents
+          .remove(&uri_to_path(params["textDocument"]["uri"].as_str().unwrap_or_default()));
+        None
+      }
+      "textDocument/definition" => Some(definition(&ws, params)),
+      "textDocument/hover" => Some(hover(&ws, params)),
+      "textDocument/completion" => Some(completion(&ws, params)),
+      _ => None,
+    };
+
+    // notifications have no ids, and need no responses
+    if let Some(id) = message.get("id") {
+      let response = match result {
+        Some(v) => json!({ "jsonrpc": "2.0", "id": id, "result": v }),
+        None => json!({
+          "jsonrpc": "2.0",
+          "id": id,
+          "error": { "code": -32601, "message": format!("unknown method: {method}") },
+        }),
+      };
+      send(&mut stdout, &response)?;
+    }
+  }
+  Ok(())
+}
+
+/// `None` at the end of input, a malformed message is returned as an inner error so it can be skipped
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Result<Value, String>>, String> {
+  let mut length: Option<Result<usize, String>> = None;
+  loop {
+    let mut line: Vec<u8> = vec![];
+    if reader.read_until(b'\n', &mut line).map_err(|e| e.to_string())? == 0 {
+      return Ok(None);
+    }
+    let line = String::from_utf8_lossy(&line);
+    let line = line.trim_end();
+    if line.is_empty() {
+      break;
+    }
+    if let Some(v) = line.strip_prefix("Content-Length:") {
+      length = Some(v.trim().parse().map_err(|e| format!("invalid Content-Length: {e}")));
+    }
+  }
+  let length = match length {
+    Some(Ok(n)) => n,
+    Some(Err(e)) => return Ok(Some(Err(e))),
+    None => return Ok(Some(Err(String::from("missing Content-Length header")))),
+  };
+  let mut buf = vec![0; length];
+  reader.read_exact(&mut buf).map_err(|e| e.to_string())?;
+  Ok(Some(serde_json::from_slice(&buf).map_err(|e| format!("invalid message: {e}"))))
+}
+
+fn send(out: &mut impl Write, message: &Value) -> Result<(), String> {
+  let body = message.to_string();
+  write!(out, "Content-Length: {}\r\n\r\n{body}", body.len()).map_err(|e| e.to_string())?;
+  out.flush().map_err(|e| e.to_string())
+}
+
+fn uri_to_path(uri: &str) -> PathBuf {
+  let raw = uri.strip_prefix("file://").unwrap_or(uri);
+  let bytes = raw.as_bytes();
+  let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+  let mut idx = 0;
+  while idx < bytes.len() {
+    if bytes[idx] == b'%' && idx + 2 < bytes.len() {
+      // a multibyte char may follow `%`, so hex digits are read from bytes
+      let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).ok();
+      if let Some(b) = hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+        decoded.push(b);
+        idx += 3;
+        continue;
+      }
+    }
+    decoded.push(bytes[idx]);
+    idx += 1;
+  }
+  PathBuf::from(String::from_utf8_lossy(&decoded).to_string())
+}
+
+fn path_to_uri(path: &Path) -> String {
+  let full = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+  let mut uri = String::from("file://");
+  for c in full.display().to_string().chars() {
+    match c {
+      ' ' | '%' | '#' | '?' => uri.push_str(&format!("%{:02X}", c as u32)),
+      _ => uri.push(c),
+    }
+  }
+  uri
+}
+
+/// columns are counted in chars here, while LSP counts UTF-16 code units
+fn to_utf16_col(text: &str, line: usize, col: usize) -> usize {
+  text
+    .lines()
+    .nth(line)
+    .map(|l| l.chars().take(col).map(char::len_utf16).sum())
+    .unwrap_or(col)
+}
+
+fn from_utf16_col(text: &str, line: usize, col: usize) -> usize {
+  let Some(l) = text.lines().nth(line) else {
+    return col;
+  };
+  let mut units = 0;
+  for (idx, c) in l.chars().enumerate() {
+    if units >= col {
+      return idx;
+    }
+    units += c.len_utf16();
+  }
+  l.chars().count()
+}
+
+fn range(line: usize, start: usize, end: usize) -> Value {
+  json!({
+    "start": { "line": line, "character": start },
+    "end": { "line": line, "character": end },
+  })
+}
+
+fn diagnostic(line: usize, start: usize, end: usize, message: &str, severity: u8) -> Value {
+  json!({ "range": range(line, start, end), "severity": severity, "source": "calcit", "message": message })
+}
+
+/// use code in workspace as program, evaluated states are dropped
+fn reload_program(ws: &Workspace) -> Result<(), String> {
+  let data = program::extract_program_data(&snapshot::Snapshot {
+    files: ws.files.to_owned(),
+    ..Default::default()
+  })?;
+  {
+    let mut prgm = program::PROGRAM_CODE_DATA.write().expect("open program data");
+    *prgm = data;
+  }
+  program::clear_all_program_evaled_defs(Arc::from(""), Arc::from(""), true)?;
+  builtins::meta::force_reset_gensym_index()?;
+  Ok(())
+}
+
+fn check_document(ws: &mut Workspace, out: &mut impl Write, params: &Value, text: String) -> Result<(), String> {
+  let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+  let path = uri_to_path(uri);
+  let diagnostics = match ws.update_document(&path, text.to_owned()) {
+    Ok(namespaces) => {
+      // preprocessing may panic on malformed code, keep the server alive
+      panic::catch_unwind(panic::AssertUnwindSafe(|| check_namespaces(ws, &namespaces, &text)))
+        .unwrap_or_else(|_| vec![diagnostic(0, 0, 0, "internal error during preprocessing", SEVERITY_ERROR)])
+    }
+    Err(e) => vec![diagnostic(0, 0, 0, &e, SEVERITY_ERROR)],
+  };
+  send(
+    out,
+    &json!({
+      "jsonrpc": "2.0",
+      "method": "textDocument/publishDiagnostics",
+      "params": { "uri": uri, "diagnostics": diagnostics },
+    }),
+  )
+}
+
+/// preprocess every def in namespaces, warnings and errors are located in document text
+fn check_namespaces(ws: &Workspace, namespaces: &[Arc<str>], text: &str) -> Vec<Value> {
+  if let Err(e) = reload_program(ws) {
+    return vec![diagnostic(0, 0, 0, &e, SEVERITY_ERROR)];
+  }
+
+  let mut diagnostics: Vec<Value> = vec![];
+  let check_warnings = RefCell::new(LocatedWarning::default_list());
+  let stack = CallStackList::default();
+  if let Err(e) = runner::preprocess::preprocess_ns_def(CORE_NS, BUILTIN_CLASSES_ENTRY, &check_warnings, &stack) {
+    diagnostics.push(diagnostic(0, 0, 0, &e.msg, SEVERITY_ERROR));
+  }
+
+  for ns in namespaces {
+    let mut defs: Vec<&Arc<str>> = ws.files[ns].defs.keys().collect();
+    defs.sort();
+    for def in defs {
+      if let Err(e) = runner::preprocess::preprocess_ns_def(ns, def, &check_warnings, &stack) {
+        let line = workspace::find_def_line(text, ns, Some(def)).unwrap_or(0);
+        let width = text.lines().nth(line).map(|l| l.encode_utf16().count()).unwrap_or(0);
+        diagnostics.push(diagnostic(line, 0, width, &e.msg, SEVERITY_ERROR));
+      }
+    }
+  }
+
+  for warning in check_warnings.borrow().iter() {
+    let location = warning.location();
+    if namespaces.contains(&location.ns) {
+      let (line, start, end) = locate_warning(text, location, warning.message());
+      diagnostics.push(diagnostic(line, start, end, warning.message(), SEVERITY_WARNING));
+    }
+  }
+  diagnostics
+}
+
+/// coords are not tracked in text, so find the symbol quoted in message inside the def
+fn locate_warning(text: &str, location: &NodeLocation, message: &str) -> (usize, usize, usize) {
+  let start = workspace::find_def_line(text, &location.ns, Some(&location.def)).unwrap_or(0);
+  if let Some(name) = message.split('`').nth(1) {
+    let end = workspace::def_block_end(text, start);
+    if let Some((line, from, to)) = workspace::find_token(text, start, end, name) {
+      return (line, to_utf16_col(text, line, from), to_utf16_col(text, line, to));
+    }
+  }
+  let width = text.lines().nth(start).map(|l| l.encode_utf16().count()).unwrap_or(0);
+  (start, 0, width)
+}
+
+/// text, line and character from a position request, character is converted to a char index
+fn read_position(ws: &Workspace, params: &Value) -> Option<(String, usize, usize)> {
+  let path = uri_to_path(params["textDocument"]["uri"].as_str()?);
+  let text = ws.read_text(&path)?;
+  let line = params["position"]["line"].as_u64()? as usize;
+  let character = from_utf16_col(&text, line, params["position"]["character"].as_u64()? as usize);
+  Some((text, line, character))
+}
+
+/// resolve in the order preprocessing follows, locals are not tracked
+fn resolve_symbol(ns: &str, token: &str) -> Option<Target> {
+  if let Some((alias, name)) = token.split_once('/') {
+    if !alias.is_empty() && !name.is_empty() {
+      let target_ns = program::lookup_ns_target_in_import(ns, alias).unwrap_or_else(|| Arc::from(alias));
+      return program::has_def_code(&target_ns, name).then(|| Target::Def(target_ns, Arc::from(name)));
+    }
+  }
+  if let Ok(s) = token.parse::<CalcitSyntax>() {
+    Some(Target::Syntax(s))
+  } else if let Ok(p) = token.parse::<CalcitProc>() {
+    Some(Target::Proc(p))
+  } else if program::has_def_code(CORE_NS, token) {
+    Some(Target::Def(Arc::from(CORE_NS), Arc::from(token)))
+  } else if program::has_def_code(ns, token) {
+    Some(Target::Def(Arc::from(ns), Arc::from(token)))
+  } else if let Some(target_ns) = program::lookup_def_target_in_import(ns, token) {
+    Some(Target::Def(target_ns, Arc::from(token)))
+  } else {
+    program::lookup_ns_target_in_import(ns, token).map(Target::Ns)
+  }
+}
+
+fn target_at(ws: &Workspace, params: &Value) -> Option<(Target, usize, usize, usize)> {
+  let (text, line, character) = read_position(ws, params)?;
+  let (token, start, end) = workspace::token_at(&text, line, character)?;
+  let (ns, _def) = workspace::context_at(&text, line);
+  let target = resolve_symbol(&ns?, &token)?;
+  Some((target, line, to_utf16_col(&text, line, start), to_utf16_col(&text, line, end)))
+}
+
+fn definition(ws: &Workspace, params: &Value) -> Value {
+  let (ns, def) = match target_at(ws, params) {
+    Some((Target::Def(ns, def), ..)) => (ns, Some(def)),
+    Some((Target::Ns(ns), ..)) => (ns, None),
+    _ => return Value::Null,
+  };
+  // core is bundled in binary, no file to jump to
+  let Some(path) = ws.sources.get(&ns) else {
+    return Value::Null;
+  };
+  let line = ws
+    .read_text(path)
+    .and_then(|text| workspace::find_def_line(&text, &ns, def.as_deref()))
+    .unwrap_or(0);
+  json!({ "uri": path_to_uri(path), "range": range(line, 0, 0) })
+}
+
+/// leading part of def code, like `defn f (a b)`
+fn code_header(code: &Cirru) -> String {
+  match code {
+    Cirru::List(xs) => {
+      let size = match xs.first() {
+        Some(Cirru::Leaf(op)) if &**op == "defn" || &**op == "defmacro" => 3,
+        _ => 2,
+      };
+      let header = xs.iter().take(size).map(|x| x.to_string()).collect::<Vec<_>>().join(" ");
+      if xs.len() > size {
+        format!("{header} ...")
+      } else {
+        header
+      }
+    }
+    Cirru::Leaf(s) => s.to_string(),
+  }
+}
+
+fn hover(ws: &Workspace, params: &Value) -> Value {
+  let Some((target, line, start, end)) = target_at(ws, params) else {
+    return Value::Null;
+  };
+  let content = match target {
+    Target::Def(ns, def) => {
+      let mut content = format!("**{ns}/{def}**");
+      if let Some(entry) = ws.files.get(&ns).and_then(|file| file.defs.get(&def)) {
+        content.push_str(&format!("\n\n```cirru\n{}\n```", code_header(&entry.code)));
+        if !entry.doc.trim().is_empty() {
+          content.push_str(&format!("\n\n{}", entry.doc));
+        }
+      }
+      content
+    }
+    Target::Ns(ns) => {
+      let mut content = format!("namespace **{ns}**");
+      if let Some(file) = ws.files.get(&ns) {
+        if !file.ns.doc.trim().is_empty() {
+          content.push_str(&format!("\n\n{}", file.ns.doc));
+        }
+      }
+      content
+    }
+    Target::Proc(p) => format!("**{p}**\n\nbuiltin function"),
+    Target::Syntax(s) => format!("**{s}**\n\nbuiltin syntax"),
+  };
+  json!({
+    "contents": { "kind": "markdown", "value": content },
+    "range": range(line, start, end),
+  })
+}
+
+fn def_kind(code: &Calcit) -> u8 {
+  match code {
+    Calcit::List(xs) => match xs.first() {
+      Some(Calcit::Symbol { sym, .. }) if &**sym == "defn" || &**sym == "defmacro" => KIND_FUNCTION,
+      _ => KIND_VARIABLE,
+    },
+    _ => KIND_VARIABLE,
+  }
+}
+
+fn completion(ws: &Workspace, params: &Value) -> Value {
+  let Some((text, line, character)) = read_position(ws, params) else {
+    return Value::Null;
+  };
+  let prefix: String = match workspace::token_at(&text, line, character.saturating_sub(1)) {
+    Some((token, start, _)) => token.chars().take(character.saturating_sub(start)).collect(),
+    None => String::new(),
+  };
+  let Some(ns) = workspace::context_at(&text, line).0 else {
+    return Value::Null;
+  };
+
+  let mut items: Vec<Value> = vec![];
+  let mut seen: HashSet<String> = HashSet::new();
+  let mut add = |label: String, kind: u8, detail: &str| {
+    if label.starts_with(&prefix) && seen.insert(label.to_owned()) {
+      items.push(json!({ "label": label, "kind": kind, "detail": detail }));
+    }
+  };
+
+  let program_code = program::PROGRAM_CODE_DATA.read().expect("read program code");
+  if let Some((alias, _)) = prefix.split_once('/') {
+    let target_ns = program::lookup_ns_target_in_import(&ns, alias).unwrap_or_else(|| Arc::from(alias));
+    if let Some(file) = program_code.get(&target_ns) {
+      for (def, code) in &file.defs {
+        add(format!("{alias}/{def}"), def_kind(code), &target_ns);
+      }
+    }
+  } else {
+    for target_ns in [&*ns, CORE_NS] {
+      if let Some(file) = program_code.get(target_ns) {
+        for (def, code) in &file.defs {
+          add(def.to_string(), def_kind(code), target_ns);
+        }
+      }
+    }
+    if let Some(file) = program_code.get(&ns) {
+      for (name, rule) in &file.import_map {
+        match &**rule {
+          ImportRule::NsReferDef(target_ns, def) => {
+            let kind = program_code
+              .get(target_ns)
+              .and_then(|f| f.defs.get(def))
+              .map(def_kind)
+              .unwrap_or(KIND_VARIABLE);
+            add(name.to_string(), kind, target_ns);
+          }
+          ImportRule::NsAs(target_ns) | ImportRule::NsDefault(target_ns) => add(name.to_string(), KIND_MODULE, target_ns),
+        }
+      }
+    }
+    for p in CalcitProc::iter() {
+      add(p.to_string(), KIND_FUNCTION, "builtin function");
+    }
+    for s in CalcitSyntax::iter() {
+      add(s.to_string(), KIND_KEYWORD, "builtin syntax");
+    }
+  }
+
+  json!({ "isIncomplete": false, "items": items })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn decode_file_uris() {
+    assert_eq!(uri_to_path("file:///tmp/a%20b/c.cirru"), PathBuf::from("/tmp/a b/c.cirru"));
+    assert_eq!(uri_to_path("file:///tmp/%E6%96%87.cirru"), PathBuf::from("/tmp/文.cirru"));
+    // not escapes, kept as they are
+    assert_eq!(uri_to_path("file:///tmp/%文件.cirru"), PathBuf::from("/tmp/%文件.cirru"));
+    assert_eq!(uri_to_path("file:///tmp/100%"), PathBuf::from("/tmp/100%"));
+  }
+}
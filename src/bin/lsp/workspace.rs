@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cirru_parser::Cirru;
+use walkdir::WalkDir;
+
+use calcit::snapshot::{self, CodeEntry, FileInSnapShot};
+use calcit::util::string::strip_shebang;
+
+/// files are either snapshots like `compact.cirru`, or source files starting with a `ns` form
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DocKind {
+  Snapshot,
+  Source,
+}
+
+/// program files known to the server, updated from documents in editor
+pub struct Workspace {
+  /// all files, including core
+  pub files: HashMap<Arc<str>, FileInSnapShot>,
+  /// paths where namespaces are defined, core is not included
+  pub sources: HashMap<Arc<str>, PathBuf>,
+  /// text of opened documents
+  pub documents: HashMap<PathBuf, String>,
+}
+
+impl Workspace {
+  /// load entry snapshot with its modules, and source files in `src/` next to it
+  pub fn load(input: &Path, module_folder: &Path) -> Result<Self, String> {
+    let base_dir = input.parent().unwrap_or(Path::new("."));
+    let mut ws = Workspace {
+      files: HashMap::new(),
+      sources: HashMap::new(),
+      documents: HashMap::new(),
+    };
+    ws.files = calcit::load_core_snapshot()?.files;
+
+    if input.exists() {
+      let entry = read_snapshot(input)?;
+      let module_folder = match &entry.configs.modules_dir {
+        Some(dir) => base_dir.join(&**dir),
+        None => module_folder.to_owned(),
+      };
+      for module_path in &entry.configs.modules {
+        let path = calcit::resolve_module_path(module_path, base_dir, &module_folder);
+        match read_snapshot(&path) {
+          Ok(module_data) => ws.add_files(module_data.files, &path),
+          Err(e) => eprintln!("failed to load module {}: {e}", path.display()),
+        }
+      }
+      ws.add_files(entry.files, input);
+    }
+
+    let src_dir = base_dir.join("src");
+    if src_dir.is_dir() {
+      for entry in WalkDir::new(&src_dir).into_iter().flatten() {
+        let path = entry.path();
+        if path.extension().map(|ext| ext == "cirru").unwrap_or(false) {
+          match fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| parse_source(&text))
+          {
+            Ok((ns, file)) => ws.add_files(HashMap::from([(ns, file)]), path),
+            Err(e) => eprintln!("failed to load {}: {e}", path.display()),
+          }
+        }
+      }
+    }
+    Ok(ws)
+  }
+
+  fn add_files(&mut self, files: HashMap<Arc<str>, FileInSnapShot>, path: &Path) {
+    for (ns, file) in files {
+      self.sources.insert(ns.to_owned(), path.to_owned());
+      self.files.insert(ns, file);
+    }
+  }
+
+  /// update files from document text, returns namespaces defined in it
+  pub fn update_document(&mut self, path: &Path, text: String) -> Result<Vec<Arc<str>>, String> {
+    let files = match doc_kind(&text) {
+      DocKind::Snapshot => {
+        let mut content = text.to_owned();
+        strip_shebang(&mut content);
+        let data = cirru_edn::parse(&content)?;
+        snapshot::load_snapshot_data(&data, &path.display().to_string())?.files
+      }
+      DocKind::Source => {
+        let (ns, file) = parse_source(&text)?;
+        HashMap::from([(ns, file)])
+      }
+    };
+    self.documents.insert(path.to_owned(), text);
+    let mut namespaces: Vec<Arc<str>> = files.keys().map(ToOwned::to_owned).collect();
+    namespaces.sort();
+    self.add_files(files, path);
+    Ok(namespaces)
+  }
+
+  /// text from editor if opened, otherwise from disk
+  pub fn read_text(&self, path: &Path) -> Option<String> {
+    match self.documents.get(path) {
+      Some(text) => Some(text.to_owned()),
+      None => fs::read_to_string(path).ok(),
+    }
+  }
+}
+
+fn read_snapshot(path: &Path) -> Result<snapshot::Snapshot, String> {
+  let mut content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+  strip_shebang(&mut content);
+  let data = cirru_edn::parse(&content)?;
+  snapshot::load_snapshot_data(&data, &path.display().to_string())
+}
+
+pub fn doc_kind(text: &str) -> DocKind {
+  if text.trim_start().starts_with("{}") {
+    DocKind::Snapshot
+  } else {
+    DocKind::Source
+  }
+}
+
+/// parse a file with `ns` form at top and defs after it, same as `bundle_calcit` does
+fn parse_source(text: &str) -> Result<(Arc<str>, FileInSnapShot), String> {
+  let xs = cirru_parser::parse(text)?;
+  let (ns, ns_code) = match xs.first() {
+    Some(Cirru::List(ys)) => match (ys.first(), ys.get(1)) {
+      (Some(Cirru::Leaf(x0)), Some(Cirru::Leaf(x1))) if &**x0 == "ns" => (x1.to_owned(), ys.to_owned()),
+      (a, b) => return Err(format!("invalid ns form: {a:?} {b:?}")),
+    },
+    a => return Err(format!("first expression of file should be a ns form, got: {a:?}")),
+  };
+  let mut defs: HashMap<Arc<str>, CodeEntry> = HashMap::new();
+  for line in xs.iter().skip(1) {
+    match line {
+      Cirru::List(ys) => match (ys.first(), ys.get(1)) {
+        (Some(Cirru::Leaf(x0)), Some(Cirru::Leaf(x1))) if x0.starts_with("def") => {
+          defs.insert(x1.to_owned(), CodeEntry::from_code(line.to_owned()));
+        }
+        (a, b) => return Err(format!("invalid def code: {a:?} {b:?}")),
+      },
+      Cirru::Leaf(_) => return Err(format!("file line not an expr: {line}")),
+    }
+  }
+  Ok((
+    ns,
+    FileInSnapShot {
+      ns: CodeEntry::from_code(Cirru::List(ns_code)),
+      defs,
+    },
+  ))
+}
+
+/// entry header in snapshot, like `|app.main $ %{} :FileEntry` or `|main! $ %{} :CodeEntry (:doc |)`
+fn entry_header(line: &str) -> Option<(Arc<str>, bool)> {
+  let (name, rest) = line.trim_start().split_once(" $ %{} :")?;
+  let is_file = if rest.starts_with("FileEntry") {
+    true
+  } else if rest.starts_with("CodeEntry") {
+    false
+  } else {
+    return None;
+  };
+  let name = name.trim_matches('"');
+  Some((Arc::from(name.strip_prefix('|').unwrap_or(name)), is_file))
+}
+
+/// def name from a top level line of source file
+fn source_def_header(line: &str) -> Option<&str> {
+  if line.starts_with(char::is_whitespace) {
+    return None;
+  }
+  let mut words = line.split_whitespace();
+  match (words.next(), words.next()) {
+    (Some(op), Some(name)) if op.starts_with("def") => Some(name),
+    _ => None,
+  }
+}
+
+/// line where a def is written, or where the ns starts when `def` is `None`
+pub fn find_def_line(text: &str, ns: &str, def: Option<&str>) -> Option<usize> {
+  match doc_kind(text) {
+    DocKind::Snapshot => {
+      let mut in_ns = false;
+      for (idx, line) in text.lines().enumerate() {
+        match entry_header(line) {
+          Some((name, true)) => {
+            if in_ns {
+              return None;
+            }
+            if &*name == ns {
+              match def {
+                Some(_) => in_ns = true,
+                None => return Some(idx),
+              }
+            }
+          }
+          Some((name, false)) if in_ns && Some(&*name) == def => return Some(idx),
+          _ => {}
+        }
+      }
+      None
+    }
+    DocKind::Source => {
+      for (idx, line) in text.lines().enumerate() {
+        match def {
+          None if line.starts_with("ns ") => return Some(idx),
+          Some(d) if source_def_header(line) == Some(d) => return Some(idx),
+          _ => {}
+        }
+      }
+      None
+    }
+  }
+}
+
+/// lines of a def starting from its header, until next header
+pub fn def_block_end(text: &str, start: usize) -> usize {
+  let kind = doc_kind(text);
+  let lines: Vec<&str> = text.lines().collect();
+  for (idx, line) in lines.iter().enumerate().skip(start + 1) {
+    let is_header = match kind {
+      DocKind::Snapshot => entry_header(line).is_some(),
+      DocKind::Source => !line.is_empty() && !line.starts_with(char::is_whitespace),
+    };
+    if is_header {
+      return idx;
+    }
+  }
+  lines.len()
+}
+
+/// namespace and def where a line is located
+pub fn context_at(text: &str, line_idx: usize) -> (Option<Arc<str>>, Option<Arc<str>>) {
+  let lines: Vec<&str> = text.lines().take(line_idx + 1).collect();
+  match doc_kind(text) {
+    DocKind::Snapshot => {
+      // `:ns` is also a code entry, but holds the ns form
+      let mut def: Option<Option<Arc<str>>> = None;
+      for line in lines.iter().rev() {
+        match entry_header(line) {
+          Some((name, true)) => return (Some(name), def.flatten()),
+          Some((name, false)) if def.is_none() => def = Some(if name.starts_with(':') { None } else { Some(name) }),
+          _ => {}
+        }
+      }
+      (None, None)
+    }
+    DocKind::Source => {
+      let ns = text
+        .lines()
+        .find_map(|line| line.strip_prefix("ns "))
+        .and_then(|rest| rest.split_whitespace().next());
+      let def = lines.iter().rev().find_map(|line| source_def_header(line));
+      (ns.map(Arc::from), def.map(Arc::from))
+    }
+  }
+}
+
+fn is_token_char(c: char) -> bool {
+  !c.is_whitespace() && c != '(' && c != ')'
+}
+
+/// token under cursor, with its start and end columns
+pub fn token_at(text: &str, line_idx: usize, character: usize) -> Option<(String, usize, usize)> {
+  let line: Vec<char> = text.lines().nth(line_idx)?.chars().collect();
+  let mut start = character.min(line.len());
+  while start > 0 && is_token_char(line[start - 1]) {
+    start -= 1;
+  }
+  let mut end = character.min(line.len());
+  while end < line.len() && is_token_char(line[end]) {
+    end += 1;
+  }
+  if start == end {
+    return None;
+  }
+  let token: String = line[start..end].iter().collect();
+  if token == "$" || token == "," {
+    None
+  } else {
+    Some((token, start, end))
+  }
+}
+
+/// find a token in lines, returns line and columns
+pub fn find_token(text: &str, from: usize, to: usize, token: &str) -> Option<(usize, usize, usize)> {
+  for (idx, line) in text.lines().enumerate().skip(from).take(to.saturating_sub(from)) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut col = 0;
+    while col < chars.len() {
+      if is_token_char(chars[col]) {
+        let start = col;
+        while col < chars.len() && is_token_char(chars[col]) {
+          col += 1;
+        }
+        if chars[start..col].iter().collect::<String>() == token {
+          return Some((idx, start, col));
+        }
+      } else {
+        col += 1;
+      }
+    }
+  }
+  None
+}
@@ -0,0 +1,258 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use cirru_parser::Cirru;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use calcit::{
+  builtins,
+  calcit::{Calcit, CalcitErr, CalcitScope, LocatedWarning},
+  call_stack::{self, CallStackList},
+  cli_args::ToplevelCalcit,
+  data::cirru::code_to_calcit,
+  program, runner,
+};
+
+/// def name attached to symbols typed in REPL, not a valid symbol so never collides with user defs
+const REPL_DEF: &str = "repl%";
+
+/// history is shared by REPLs of all projects, kept out of working directories
+fn history_file() -> Option<PathBuf> {
+  dirs::home_dir().map(|home| home.join(".config/calcit/repl-history"))
+}
+
+/// starts a read-eval-print loop, program data is expected to be loaded already
+pub fn start_repl(init_ns: &str, settings: &ToplevelCalcit, module_folder: &Path) -> Result<(), String> {
+  let mut ns: Arc<str> = Arc::from(init_ns);
+  if !has_ns(&ns) {
+    return Err(format!("unknown namespace for REPL: {ns}"));
+  }
+
+  let mut editor = DefaultEditor::new().map_err(|e| format!("failed to start REPL: {e}"))?;
+  let history = history_file();
+  if let Some(path) = &history {
+    let _ = editor.load_history(path);
+  }
+
+  println!("\nREPL started in `{ns}`, use `:ns <ns>` to switch, `:reload` to reload code, `:quit` to exit.\n");
+
+  let mut buffer = String::new();
+  // set when input continues after a trailing `\`, then an empty line is required to finish
+  let mut by_indent = false;
+  loop {
+    let prompt = if buffer.is_empty() {
+      format!("{ns}=> ")
+    } else {
+      String::from("... ")
+    };
+    let line = match editor.readline(&prompt) {
+      Ok(line) => line,
+      Err(ReadlineError::Interrupted) => {
+        // drop current input, like a shell
+        buffer.clear();
+        by_indent = false;
+        continue;
+      }
+      Err(ReadlineError::Eof) => break,
+      Err(e) => {
+        eprintln!("failed to read input: {e}");
+        break;
+      }
+    };
+
+    if buffer.is_empty() {
+      let trimmed = line.trim();
+      if trimmed.is_empty() {
+        continue;
+      }
+      if trimmed.starts_with(':') {
+        let _ = editor.add_history_entry(trimmed);
+        let mut words = trimmed.split_whitespace();
+        match (words.next(), words.next()) {
+          (Some(":quit" | ":q"), _) => break,
+          (Some(":ns"), None) => println!("{ns}"),
+          (Some(":ns"), Some(target)) => {
+            if has_ns(target) {
+              ns = Arc::from(target);
+            } else {
+              eprintln!("unknown namespace: {target}");
+            }
+          }
+          (Some(":reload"), _) => match reload_program(settings, module_folder) {
+            Ok(()) => println!("reloaded program."),
+            Err(e) => eprintln!("failed to reload, {e}"),
+          },
+          (Some(command), _) => eprintln!("unknown command `{command}`, expected `:ns`, `:reload` or `:quit`"),
+          (None, _) => {}
+        }
+        continue;
+      }
+      match line.trim_end().strip_suffix('\\') {
+        Some(head) => {
+          buffer.push_str(head);
+          by_indent = true;
+        }
+        None => {
+          buffer.push_str(&line);
+          if paren_depth(&buffer) <= 0 {
+            submit(&mut editor, &mut buffer, &ns);
+          }
+        }
+      }
+    } else if by_indent {
+      // indented lines are children of previous lines, ended by an empty line
+      if line.trim().is_empty() {
+        submit(&mut editor, &mut buffer, &ns);
+        by_indent = false;
+      } else {
+        buffer.push('\n');
+        buffer.push_str(&line);
+      }
+    } else {
+      // expressions in parentheses are kept in a single line
+      buffer.push(' ');
+      buffer.push_str(line.trim());
+      if paren_depth(&buffer) <= 0 {
+        submit(&mut editor, &mut buffer, &ns);
+      }
+    }
+  }
+
+  if let Some(path) = &history {
+    let saved = match path.parent() {
+      Some(dir) => std::fs::create_dir_all(dir).map_err(|e| e.to_string()),
+      None => Ok(()),
+    }
+    .and_then(|_| editor.save_history(path).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+      eprintln!("failed to save REPL history to {}: {e}", path.display());
+    }
+  }
+  Ok(())
+}
+
+fn submit(editor: &mut DefaultEditor, buffer: &mut String, ns: &str) {
+  let _ = editor.add_history_entry(buffer.as_str());
+  match eval_code(buffer, ns) {
+    Ok(v) => println!("{v}"),
+    Err(failure) => {
+      LocatedWarning::print_list(&failure.warnings);
+      if failure.warnings.is_empty() {
+        if let Err(e) = call_stack::display_stack(&failure.msg, &failure.stack, failure.location.as_ref()) {
+          eprintln!("failed to display stack: {e}");
+        }
+      } else {
+        eprintln!("{}", failure.msg);
+      }
+    }
+  }
+  buffer.clear();
+}
+
+fn has_ns(ns: &str) -> bool {
+  let program_code = program::PROGRAM_CODE_DATA.read().expect("read program code");
+  program_code.contains_key(ns)
+}
+
+/// evaluate lines in a namespace, returns value of the last expression
+fn eval_code(code: &str, ns: &str) -> Result<Calcit, CalcitErr> {
+  let lines = cirru_parser::parse(code).map_err(|e| CalcitErr::use_str(format!("failed to parse: {e}")))?;
+  let mut ret = Calcit::Nil;
+  for (idx, line) in lines.iter().enumerate() {
+    // a line with a single node is the expression itself, `a` for a value, `(f)` for a call
+    let expr = match line {
+      Cirru::List(xs) if xs.len() == 1 => &xs[0],
+      _ => line,
+    };
+    let code = code_to_calcit(expr, ns, REPL_DEF, vec![idx as u8])?;
+
+    let check_warnings = RefCell::new(LocatedWarning::default_list());
+    let resolved = runner::preprocess::preprocess_expr(&code, &HashSet::new(), ns, &check_warnings, &CallStackList::default())?;
+    let warnings = check_warnings.borrow();
+    if !warnings.is_empty() {
+      return Err(CalcitErr {
+        msg: format!("Found {} warnings, evaluation blocked", warnings.len()),
+        data: None,
+        warnings: warnings.to_owned(),
+        stack: CallStackList::default(),
+        location: None,
+      });
+    }
+
+    ret = runner::evaluate_expr(&resolved, &CalcitScope::default(), ns, &CallStackList::default())?;
+  }
+  Ok(ret)
+}
+
+/// re-read program code from files, evaluated states are cleared
+fn reload_program(settings: &ToplevelCalcit, module_folder: &Path) -> Result<(), String> {
+  let input_path = PathBuf::from(&settings.input);
+  let base_dir = input_path.parent().expect("extract parent");
+  let mut snapshot = crate::load_snapshot_file(settings, base_dir, module_folder)?;
+  for (k, v) in calcit::load_core_snapshot()?.files {
+    snapshot.files.insert(k, v);
+  }
+
+  {
+    let mut prgm = program::PROGRAM_CODE_DATA.write().expect("open program data");
+    *prgm = program::extract_program_data(&snapshot)?;
+  }
+  // core is reloaded too, so clear everything
+  program::clear_all_program_evaled_defs(Arc::from(""), Arc::from(""), true)?;
+  builtins::meta::force_reset_gensym_index()?;
+
+  let check_warnings = RefCell::new(LocatedWarning::default_list());
+  runner::preprocess::preprocess_ns_def(
+    calcit::calcit::CORE_NS,
+    calcit::calcit::BUILTIN_CLASSES_ENTRY,
+    &check_warnings,
+    &CallStackList::default(),
+  )
+  .map_err(|e| e.msg)?;
+  Ok(())
+}
+
+/// count unclosed parentheses, skipping those in string literals.
+/// an opening paren inside a `|`-prefixed string is not counted, so `|(` does not keep the prompt waiting
+fn paren_depth(code: &str) -> i32 {
+  let mut depth = 0;
+  let mut in_str = false;
+  let mut in_bar_str = false;
+  let mut escaped = false;
+  let mut token_start = true;
+  for c in code.chars() {
+    if in_str {
+      if escaped {
+        escaped = false;
+      } else if c == '\\' {
+        escaped = true;
+      } else if c == '"' {
+        in_str = false;
+      }
+      continue;
+    }
+    if in_bar_str {
+      match c {
+        '(' => continue,
+        ')' => depth -= 1,
+        _ if !c.is_whitespace() => continue,
+        _ => {}
+      }
+      in_bar_str = false;
+      token_start = true;
+      continue;
+    }
+    match c {
+      '"' => in_str = true,
+      '|' if token_start => in_bar_str = true,
+      '(' => depth += 1,
+      ')' => depth -= 1,
+      _ => {}
+    }
+    token_start = c.is_whitespace() || c == '(' || c == ')';
+  }
+  depth
+}
@@ -0,0 +1,221 @@
+use std::cell::RefCell;
+use std::fs;
+use std::sync::Arc;
+use std::time::Instant;
+
+use cirru_edn::{Edn, EdnListView};
+use colored::*;
+
+use calcit::{
+  calcit::{Calcit, CalcitErr, LocatedWarning, NodeLocation},
+  call_stack::{CallStackList, StackKind},
+  cli_args::TestCommand,
+  program, runner,
+};
+
+/// defs defined with this macro are collected as tests
+const TEST_MACRO: &str = "deftest";
+
+struct TestFailure {
+  message: String,
+  location: Option<NodeLocation>,
+}
+
+struct TestResult {
+  ns: Arc<str>,
+  def: Arc<str>,
+  /// in milliseconds
+  time: f64,
+  failure: Option<TestFailure>,
+}
+
+/// run tests found in program, returns error when some of them failed
+pub fn run_tests(options: &TestCommand) -> Result<(), String> {
+  let tests = collect_tests(options.filter.as_deref());
+  println!("\nrunning {} tests\n", tests.len());
+
+  let mut results: Vec<TestResult> = Vec::with_capacity(tests.len());
+  for (ns, def) in tests {
+    let started_time = Instant::now();
+    let failure = run_test(&ns, &def).err();
+    let time = Instant::now().duration_since(started_time).as_micros() as f64 / 1000.0;
+    match &failure {
+      None => println!("  {} {ns}/{def} ({time}ms)", "pass".green()),
+      Some(f) => {
+        println!("  {} {ns}/{def} ({time}ms)", "FAIL".red());
+        println!("      {}", f.message);
+        if let Some(location) = &f.location {
+          println!("      at {location}");
+        }
+      }
+    }
+    results.push(TestResult { ns, def, time, failure });
+  }
+
+  let failed = results.iter().filter(|r| r.failure.is_some()).count();
+  let passed = results.len() - failed;
+  println!("\n{passed} passed, {failed} failed");
+
+  if let Some(path) = &options.report {
+    write_report(path, &results)?;
+    println!("report wrote to {path}");
+  }
+
+  if failed > 0 {
+    Err(format!("{failed} of {} tests failed", results.len()))
+  } else {
+    Ok(())
+  }
+}
+
+/// find defs written in `deftest`, sorted by ns and def
+fn collect_tests(filter: Option<&str>) -> Vec<(Arc<str>, Arc<str>)> {
+  let program_code = program::PROGRAM_CODE_DATA.read().expect("read program code");
+  let mut xs: Vec<(Arc<str>, Arc<str>)> = vec![];
+  for (ns, file) in program_code.iter() {
+    for (def, code) in &file.defs {
+      if let Calcit::List(ys) = code {
+        if let Some(Calcit::Symbol { sym, .. }) = ys.first() {
+          if &**sym == TEST_MACRO {
+            let name = format!("{ns}/{def}");
+            if filter.map(|f| name.contains(f)).unwrap_or(true) {
+              xs.push((ns.to_owned(), def.to_owned()));
+            }
+          }
+        }
+      }
+    }
+  }
+  xs.sort();
+  xs
+}
+
+fn run_test(ns: &str, def: &str) -> Result<(), TestFailure> {
+  let check_warnings = RefCell::new(LocatedWarning::default_list());
+  let v = runner::preprocess::preprocess_ns_def(ns, def, &check_warnings, &CallStackList::default()).map_err(to_failure)?;
+  let warnings = check_warnings.borrow();
+  if !warnings.is_empty() {
+    LocatedWarning::print_list(&warnings);
+    return Err(TestFailure {
+      message: format!("found {} warnings in preprocessing", warnings.len()),
+      location: None,
+    });
+  }
+  match v {
+    Some(Calcit::Fn { info, .. }) => {
+      let scope = (*info.scope).to_owned();
+      let stack = CallStackList::default().extend(ns, def, StackKind::Fn, &Calcit::Nil, &[]);
+      // run expressions one by one, so a failure can be located at least to its top-level expression
+      for (idx, line) in info.body.iter().enumerate() {
+        if let Err(e) = runner::evaluate_expr(line, &scope, &info.def_ns, &stack) {
+          let mut failure = to_failure(e);
+          // body starts after `deftest` and name, coords are bytes so longer bodies are left unlocated
+          if let (None, Ok(coord)) = (&failure.location, u8::try_from(idx + 2)) {
+            failure.location = Some(NodeLocation::new(Arc::from(ns), Arc::from(def), Arc::new(vec![coord])));
+          }
+          return Err(failure);
+        }
+      }
+      Ok(())
+    }
+    Some(a) => Err(TestFailure {
+      message: format!("expected a function from {TEST_MACRO}, got: {a}"),
+      location: None,
+    }),
+    None => Err(TestFailure {
+      message: format!("test not found: {ns}/{def}"),
+      location: None,
+    }),
+  }
+}
+
+fn to_failure(e: CalcitErr) -> TestFailure {
+  let location = match &e.location {
+    Some(l) => Some((**l).to_owned()),
+    None => e.stack.guess_location(),
+  };
+  TestFailure { message: e.msg, location }
+}
+
+fn write_report(path: &str, results: &[TestResult]) -> Result<(), String> {
+  let content = if path.ends_with(".xml") {
+    junit_report(results)
+  } else if path.ends_with(".cirru") {
+    let mut tests = EdnListView::default();
+    for r in results {
+      let (message, location) = match &r.failure {
+        Some(f) => (Edn::str(f.message.as_str()), f.location.as_ref().map(Edn::from).unwrap_or(Edn::Nil)),
+        None => (Edn::Nil, Edn::Nil),
+      };
+      tests.push(Edn::map_from_iter([
+        (Edn::tag("ns"), Edn::str(&*r.ns)),
+        (Edn::tag("def"), Edn::str(&*r.def)),
+        (Edn::tag("passed?"), Edn::Bool(r.failure.is_none())),
+        (Edn::tag("time"), Edn::Number(r.time)),
+        (Edn::tag("message"), message),
+        (Edn::tag("location"), location),
+      ]));
+    }
+    let failed = results.iter().filter(|r| r.failure.is_some()).count();
+    cirru_edn::format(
+      &Edn::map_from_iter([
+        (Edn::tag("passed"), Edn::Number((results.len() - failed) as f64)),
+        (Edn::tag("failed"), Edn::Number(failed as f64)),
+        (Edn::tag("tests"), tests.into()),
+      ]),
+      true,
+    )?
+  } else {
+    return Err(format!("unknown report format, expected *.xml or *.cirru, got: {path}"));
+  };
+  fs::write(path, content).map_err(|e| format!("failed to write report {path}: {e}"))
+}
+
+/// JUnit style XML, one testsuite for each namespace
+fn junit_report(results: &[TestResult]) -> String {
+  let mut namespaces: Vec<&Arc<str>> = results.iter().map(|r| &r.ns).collect();
+  namespaces.dedup();
+
+  let mut content = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<testsuites>\n");
+  for ns in namespaces {
+    let cases: Vec<&TestResult> = results.iter().filter(|r| &r.ns == ns).collect();
+    let failures = cases.iter().filter(|r| r.failure.is_some()).count();
+    let time: f64 = cases.iter().map(|r| r.time).sum();
+    content.push_str(&format!(
+      "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{failures}\" time=\"{}\">\n",
+      escape_xml(ns),
+      cases.len(),
+      time / 1000.0
+    ));
+    for r in cases {
+      let attrs = format!(
+        "classname=\"{}\" name=\"{}\" time=\"{}\"",
+        escape_xml(ns),
+        escape_xml(&r.def),
+        r.time / 1000.0
+      );
+      match &r.failure {
+        None => content.push_str(&format!("    <testcase {attrs}/>\n")),
+        Some(f) => {
+          let detail = f.location.as_ref().map(|l| format!("at {l}")).unwrap_or_default();
+          content.push_str(&format!(
+            "    <testcase {attrs}>\n      <failure message=\"{}\">{}</failure>\n    </testcase>\n",
+            escape_xml(&f.message),
+            escape_xml(&detail)
+          ));
+        }
+      }
+    }
+    content.push_str("  </testsuite>\n");
+  }
+  content.push_str("</testsuites>\n");
+  content
+}
+
+fn escape_xml(s: &str) -> String {
+  s.replace('&', "&amp;")
+    .replace('<', "&lt;")
+    .replace('>', "&gt;")
+    .replace('"', "&quot;")
+    .replace('\'', "&apos;")
+}
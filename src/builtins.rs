@@ -1,11 +1,17 @@
+mod asyncs;
+mod datetime;
 pub mod effects;
+mod files;
 mod lists;
 mod logics;
 mod maps;
 mod math;
 pub mod meta;
+mod process;
+mod random;
 mod records;
 mod refs;
+mod regexes;
 mod sets;
 mod strings;
 pub mod syntax;
@@ -17,6 +23,7 @@ use crate::calcit::{Calcit, CalcitErr, CalcitList, CalcitProc, CalcitScope, Calc
 use crate::call_stack::{using_stack, CallStackList};
 
 use im_ternary_tree::TernaryTreeList;
+pub use refs::{load_persisted_refs, persist_refs};
 pub(crate) use refs::{quick_build_atom, ValueAndListeners};
 
 pub type FnType = fn(xs: Vec<Calcit>, call_stack: &CallStackList) -> Result<Calcit, CalcitErr>;
@@ -92,17 +99,52 @@ fn handle_proc_internal(name: CalcitProc, args: &[Calcit], call_stack: &CallStac
     CalcitProc::Quit => effects::quit(args),
     CalcitProc::GetEnv => effects::get_env(args),
     CalcitProc::NativeGetCalcitBackend => effects::call_get_calcit_backend(args),
-    CalcitProc::ReadFile => effects::read_file(args),
-    CalcitProc::WriteFile => effects::write_file(args),
+    // files
+    CalcitProc::ReadFile => files::read_file(args),
+    CalcitProc::WriteFile => files::write_file(args),
+    CalcitProc::AppendFile => files::append_file(args),
+    CalcitProc::ReadFileBuffer => files::read_file_buffer(args),
+    CalcitProc::WriteFileBuffer => files::write_file_buffer(args),
+    CalcitProc::ReadDir => files::read_dir(args),
+    CalcitProc::WalkDir => files::walk_dir(args),
+    CalcitProc::PathExists => files::path_exists(args),
+    CalcitProc::PathMetadata => files::path_metadata(args),
+    CalcitProc::CreateDir => files::create_dir(args),
+    CalcitProc::RemovePath => files::remove_path(args),
+    CalcitProc::RenamePath => files::rename_path(args),
+    // process
+    CalcitProc::RunCommand => process::run_command(args),
+    CalcitProc::SpawnCommand => process::spawn_command(args, call_stack),
+    CalcitProc::KillCommand => process::kill_command(args),
+    CalcitProc::WriteCommandStdin => process::write_command_stdin(args),
+    CalcitProc::CloseCommandStdin => process::close_command_stdin(args),
     // external data format
     CalcitProc::ParseCirru => meta::parse_cirru(args),
     CalcitProc::ParseCirruList => meta::parse_cirru_list(args),
     CalcitProc::FormatCirru => meta::format_cirru(args),
     CalcitProc::ParseCirruEdn => meta::parse_cirru_edn(args),
     CalcitProc::FormatCirruEdn => meta::format_cirru_edn(args),
+    CalcitProc::ParseJson => meta::parse_json(args),
+    CalcitProc::FormatJson => meta::format_json(args),
     CalcitProc::NativeCirruQuoteToList => meta::cirru_quote_to_list(args),
     // time
     CalcitProc::CpuTime => effects::cpu_time(args),
+    CalcitProc::NowMs => datetime::now_ms(args),
+    CalcitProc::NowNs => datetime::now_ns(args),
+    CalcitProc::ParseTime => datetime::parse_time(args),
+    CalcitProc::FormatTime => datetime::format_time(args),
+    CalcitProc::TimeFields => datetime::time_fields(args),
+    CalcitProc::TimeFromFields => datetime::time_from_fields(args),
+    CalcitProc::TimeAdd => datetime::time_add(args),
+    // timers and promises
+    CalcitProc::SetTimeout => asyncs::set_timeout(args, call_stack),
+    CalcitProc::SetInterval => asyncs::set_interval(args, call_stack),
+    CalcitProc::ClearTimer => asyncs::clear_timer(args),
+    CalcitProc::Promise => asyncs::new_promise(args),
+    CalcitProc::NativePromiseResolve => asyncs::promise_resolve(args),
+    CalcitProc::NativePromiseReject => asyncs::promise_reject(args),
+    CalcitProc::NativePromiseThen => asyncs::promise_then(args, call_stack),
+    CalcitProc::Await => asyncs::await_promise(args),
     // logics
     CalcitProc::NativeEquals => logics::binary_equal(args),
     CalcitProc::NativeLessThan => logics::binary_less(args),
@@ -133,6 +175,13 @@ fn handle_proc_internal(name: CalcitProc, args: &[Calcit], call_stack: &CallStac
     CalcitProc::BitOr => math::bit_or(args),
     CalcitProc::BitXor => math::bit_xor(args),
     CalcitProc::BitNot => math::bit_not(args),
+    CalcitProc::Bigint => math::bigint(args),
+    CalcitProc::NativeBigintToNumber => math::bigint_to_number(args),
+    CalcitProc::RandGen => random::rand_gen(args),
+    CalcitProc::Rand => random::rand(args),
+    CalcitProc::RandInt => random::rand_int(args),
+    CalcitProc::RandNth => random::rand_nth(args),
+    CalcitProc::Shuffle => random::shuffle(args),
     // strings
     CalcitProc::Trim => strings::trim(args),
     CalcitProc::NativeStr => strings::call_str(args),
@@ -161,6 +210,12 @@ fn handle_proc_internal(name: CalcitProc, args: &[Calcit], call_stack: &CallStac
     CalcitProc::NativeStrRest => strings::rest(args),
     CalcitProc::NativeStrPadLeft => strings::pad_left(args),
     CalcitProc::NativeStrPadRight => strings::pad_right(args),
+    // regular expressions
+    CalcitProc::ReMatches => regexes::re_matches(args),
+    CalcitProc::ReFind => regexes::re_find(args),
+    CalcitProc::ReFindAll => regexes::re_find_all(args),
+    CalcitProc::ReReplace => regexes::re_replace(args),
+    CalcitProc::ReSplit => regexes::re_split(args),
     // lists
     CalcitProc::List => lists::new_list(args),
     CalcitProc::Append => lists::append(args),
@@ -219,8 +274,11 @@ fn handle_proc_internal(name: CalcitProc, args: &[Calcit], call_stack: &CallStac
     // refs
     CalcitProc::Atom => refs::atom(args),
     CalcitProc::AtomDeref => refs::atom_deref(args),
+    CalcitProc::AtomSwap => refs::atom_swap(args, call_stack),
     CalcitProc::AddWatch => refs::add_watch(args),
     CalcitProc::RemoveWatch => refs::remove_watch(args),
+    CalcitProc::SetValidator => refs::set_validator(args),
+    CalcitProc::PersistAtoms => refs::persist_atoms(args),
     // records
     CalcitProc::NewRecord => records::new_record(args),
     CalcitProc::NewClassRecord => records::new_class_record(args),
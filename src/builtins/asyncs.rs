@@ -0,0 +1,154 @@
+//! timers and promises, callbacks run in the event loop of main thread.
+//! `await` runs the loop until the promise is settled, so code reads the same as in JavaScript
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::builtins::effects;
+use crate::calcit::{Calcit, CalcitErr, CalcitPromise, PromiseReaction};
+use crate::call_stack::CallStackList;
+use crate::runner::{self, event_loop};
+
+fn add_timer(name: &str, xs: &[Calcit], repeat: bool, call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Number(ms)), Some(Calcit::Fn { info, .. })) => {
+      let delay = Duration::from_secs_f64(ms.max(0.0) / 1000.0);
+      let id = event_loop::add_timer(delay, repeat, info.to_owned(), call_stack);
+      Ok(Calcit::Number(id as f64))
+    }
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("{name} expected milliseconds and a function, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes(format!("{name} expected 2 arguments, got:"), xs),
+  }
+}
+
+/// `set-timeout ms f`, returns id of the timer
+pub fn set_timeout(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  add_timer("set-timeout", xs, false, call_stack)
+}
+
+/// `set-interval ms f`, returns id of the timer
+pub fn set_interval(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  add_timer("set-interval", xs, true, call_stack)
+}
+
+/// works for both timeouts and intervals
+pub fn clear_timer(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Number(id)) => {
+      event_loop::clear_timer(*id as usize);
+      Ok(Calcit::Nil)
+    }
+    Some(a) => CalcitErr::err_str(format!("clear-timer expected a timer id, got: {a}")),
+    None => CalcitErr::err_str("clear-timer expected a timer id, got nothing"),
+  }
+}
+
+/// a pending promise, settled with `&promise:resolve` or `&promise:reject`
+pub fn new_promise(_xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  Ok(Calcit::Promise(Arc::new(CalcitPromise::default())))
+}
+
+/// value from `raise` is kept as reason of rejection
+fn error_value(e: &CalcitErr) -> Calcit {
+  match &e.data {
+    Some(data) => (**data).to_owned(),
+    None => Calcit::new_str(e.msg.to_owned()),
+  }
+}
+
+/// reaction runs in the event loop, even when the promise is already settled
+fn react_later(p: &CalcitPromise, reaction: PromiseReaction) {
+  if let Some((reaction, result)) = p.react(reaction) {
+    event_loop::enqueue(Box::new(move || reaction(result)));
+  }
+}
+
+/// resolving with another promise follows its result
+fn settle(p: Arc<CalcitPromise>, result: Result<Calcit, Calcit>) {
+  if let Ok(Calcit::Promise(q)) = &result {
+    react_later(q, Box::new(move |r| settle(p, r)));
+    return;
+  }
+  if let Some(reactions) = p.settle(result.to_owned()) {
+    for reaction in reactions {
+      let r = result.to_owned();
+      event_loop::enqueue(Box::new(move || reaction(r)));
+    }
+  }
+}
+
+/// settling a promise again is ignored, like in JavaScript
+pub fn promise_resolve(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Promise(p)), v) => {
+      settle(p.to_owned(), Ok(v.cloned().unwrap_or(Calcit::Nil)));
+      Ok(Calcit::Nil)
+    }
+    (Some(a), _) => CalcitErr::err_str(format!("&promise:resolve expected a promise, got: {a}")),
+    (None, _) => CalcitErr::err_str("&promise:resolve expected a promise, got nothing"),
+  }
+}
+
+pub fn promise_reject(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Promise(p)), v) => {
+      settle(p.to_owned(), Err(v.cloned().unwrap_or(Calcit::Nil)));
+      Ok(Calcit::Nil)
+    }
+    (Some(a), _) => CalcitErr::err_str(format!("&promise:reject expected a promise, got: {a}")),
+    (None, _) => CalcitErr::err_str("&promise:reject expected a promise, got nothing"),
+  }
+}
+
+/// `&promise:then p f`, or `&promise:then p f on-rejected`, returns a new promise
+pub fn promise_then(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  let on_rejected = match xs.get(2) {
+    None | Some(Calcit::Nil) => None,
+    Some(Calcit::Fn { info, .. }) => Some(info.to_owned()),
+    Some(a) => return CalcitErr::err_str(format!("&promise:then expected a function for rejection, got: {a}")),
+  };
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Promise(p)), Some(Calcit::Fn { info, .. })) => {
+      let next = Arc::new(CalcitPromise::default());
+      let on_fulfilled = info.to_owned();
+      let copied_stack = call_stack.to_owned();
+      let target = next.to_owned();
+      react_later(
+        p,
+        Box::new(move |result| {
+          let handled = match (result, on_rejected) {
+            (Ok(v), _) => runner::run_fn(&[v], &on_fulfilled, &copied_stack),
+            (Err(e), Some(f)) => runner::run_fn(&[e], &f, &copied_stack),
+            (Err(e), None) => return settle(target, Err(e)),
+          };
+          match handled {
+            Ok(v) => settle(target, Ok(v)),
+            Err(e) => settle(target, Err(error_value(&e))),
+          }
+        }),
+      );
+      Ok(Calcit::Promise(next))
+    }
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("&promise:then expected a promise and a function, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("&promise:then expected 2 arguments, got:", xs),
+  }
+}
+
+/// runs the event loop until the promise is settled, rejection is raised as an error.
+/// other values are returned as they are.
+/// it blocks the caller, while callbacks of timers and promises may run inside, so `await` in a callback is re-entrant.
+/// reloading of code is held back until the outermost loop
+pub fn await_promise(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Promise(p)) => {
+      event_loop::run_until(|| p.result().is_some());
+      match p.result() {
+        Some(Ok(v)) => Ok(v),
+        Some(Err(e)) => effects::raise(&[e]),
+        None => CalcitErr::err_str(format!("await on {}, which would never be settled", xs[0])),
+      }
+    }
+    Some(v) => Ok(v.to_owned()),
+    None => CalcitErr::err_str("await expected a promise, got nothing"),
+  }
+}
@@ -0,0 +1,396 @@
+//! wall clock and calendar procs. time is a number of milliseconds since Unix epoch,
+//! offsets are numbers of minutes east of UTC, like `480` for `+08:00`.
+//! calendar is computed with integer arithmetic instead of system time zones,
+//! so that `ts-src/js-datetime.mts` gives the same results.
+//! times are limited to ±8.64e15 ms, the range of `Date` in JavaScript
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use num_bigint::BigInt;
+
+use crate::calcit::{Calcit, CalcitErr};
+
+const MS_PER_MINUTE: i64 = 60_000;
+const MS_PER_DAY: i64 = 86_400_000;
+/// 100,000,000 days before or after Unix epoch
+const MAX_TIME: i64 = 8_640_000_000_000_000;
+
+/// date and time in the calendar of some UTC offset
+struct TimeFields {
+  year: i64,
+  month: i64,
+  day: i64,
+  hour: i64,
+  minute: i64,
+  second: i64,
+  ms: i64,
+  offset: i64,
+}
+
+/// days since 1970-01-01, from <http://howardhinnant.github.io/date_algorithms.html>.
+/// month is in `1..=12`, returns `None` when it overflows
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+  let y = if month <= 2 { year.checked_sub(1)? } else { year };
+  let era = y.div_euclid(400);
+  let yoe = y.rem_euclid(400);
+  let doy = ((153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5)
+    .checked_add(day)?
+    .checked_sub(1)?;
+  let doe = (yoe * 365 + yoe / 4 - yoe / 100).checked_add(doy)?;
+  era.checked_mul(146097)?.checked_add(doe)?.checked_sub(719468)
+}
+
+/// returns year, month, day
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z - era * 146097;
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  (if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+  match month {
+    2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+    2 => 28,
+    4 | 6 | 9 | 11 => 30,
+    _ => 31,
+  }
+}
+
+fn to_fields(time: i64, offset: i64) -> TimeFields {
+  let local = time + offset * MS_PER_MINUTE;
+  let (year, month, day) = civil_from_days(local.div_euclid(MS_PER_DAY));
+  let rest = local.rem_euclid(MS_PER_DAY);
+  TimeFields {
+    year,
+    month,
+    day,
+    hour: rest / 3_600_000,
+    minute: rest / 60_000 % 60,
+    second: rest / 1000 % 60,
+    ms: rest % 1000,
+    offset,
+  }
+}
+
+/// returns `None` when it overflows, fields may be out of their ranges and are carried over
+fn from_fields(t: &TimeFields) -> Option<i64> {
+  let days = days_from_civil(t.year, t.month, t.day)?;
+  let clock = t
+    .hour
+    .checked_mul(60)?
+    .checked_add(t.minute)?
+    .checked_mul(60)?
+    .checked_add(t.second)?
+    .checked_mul(1000)?
+    .checked_add(t.ms)?;
+  days
+    .checked_mul(MS_PER_DAY)?
+    .checked_add(clock)?
+    .checked_sub(t.offset.checked_mul(MS_PER_MINUTE)?)
+}
+
+/// turns computed time into a number, failing when it's out of range
+fn time_result(proc_name: &str, time: Option<i64>) -> Result<Calcit, CalcitErr> {
+  match time {
+    Some(t) if (-MAX_TIME..=MAX_TIME).contains(&t) => Ok(Calcit::Number(t as f64)),
+    _ => CalcitErr::err_str(format!("{proc_name} got time out of range of ±8.64e15 ms")),
+  }
+}
+
+fn read_time(proc_name: &str, x: Option<&Calcit>) -> Result<i64, CalcitErr> {
+  match x {
+    Some(Calcit::Number(n)) if n.is_finite() && n.abs() <= MAX_TIME as f64 => Ok(n.floor() as i64),
+    Some(Calcit::Number(n)) if n.is_finite() => Err(CalcitErr::use_str(format!(
+      "{proc_name} expected time within ±8.64e15 ms, got: {n:e}"
+    ))),
+    Some(a) => Err(CalcitErr::use_str(format!("{proc_name} expected time in milliseconds, got: {a}"))),
+    None => Err(CalcitErr::use_str(format!(
+      "{proc_name} expected time in milliseconds, got nothing"
+    ))),
+  }
+}
+
+/// offset in minutes, defaults to UTC
+fn read_offset(proc_name: &str, x: Option<&Calcit>) -> Result<i64, CalcitErr> {
+  match x {
+    None | Some(Calcit::Nil) => Ok(0),
+    Some(Calcit::Number(n)) if n.fract() == 0.0 && n.abs() < 1440.0 => Ok(*n as i64),
+    Some(a) => Err(CalcitErr::use_str(format!("{proc_name} expected offset in minutes, got: {a}"))),
+  }
+}
+
+/// current Unix time in milliseconds
+pub fn now_ms(_xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match SystemTime::now().duration_since(UNIX_EPOCH) {
+    Ok(d) => Ok(Calcit::Number(d.as_millis() as f64)),
+    Err(e) => CalcitErr::err_str(format!("now-ms failed to read system time: {e}")),
+  }
+}
+
+/// current Unix time in nanoseconds, in bigint since numbers are not precise enough
+pub fn now_ns(_xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match SystemTime::now().duration_since(UNIX_EPOCH) {
+    Ok(d) => Ok(Calcit::BigInt(BigInt::from(d.as_nanos()))),
+    Err(e) => CalcitErr::err_str(format!("now-ns failed to read system time: {e}")),
+  }
+}
+
+fn format_offset(offset: i64) -> String {
+  if offset == 0 {
+    return String::from("Z");
+  }
+  let sign = if offset < 0 { '-' } else { '+' };
+  format!("{sign}{:02}:{:02}", offset.abs() / 60, offset.abs() % 60)
+}
+
+/// years out of `0..=9999` are written in 6 digits with a sign, same as `toISOString` in JavaScript
+fn format_year(year: i64) -> String {
+  if (0..=9999).contains(&year) {
+    format!("{year:04}")
+  } else if year < 0 {
+    format!("-{:06}", -year)
+  } else {
+    format!("+{year:06}")
+  }
+}
+
+/// `format-time ms` or `format-time ms offset`, in RFC 3339 like `2024-02-29T08:30:00.000+08:00`
+pub fn format_time(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let time = read_time("format-time", xs.first())?;
+  let t = to_fields(time, read_offset("format-time", xs.get(1))?);
+  Ok(Calcit::new_str(format!(
+    "{}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}{}",
+    format_year(t.year),
+    t.month,
+    t.day,
+    t.hour,
+    t.minute,
+    t.second,
+    t.ms,
+    format_offset(t.offset)
+  )))
+}
+
+/// reads digits of fixed width
+fn take_digits(s: &[u8], pos: &mut usize, width: usize) -> Option<i64> {
+  let digits = s.get(*pos..*pos + width)?;
+  if !digits.iter().all(u8::is_ascii_digit) {
+    return None;
+  }
+  *pos += width;
+  Some(digits.iter().fold(0, |acc, d| acc * 10 + (d - b'0') as i64))
+}
+
+fn take_byte(s: &[u8], pos: &mut usize, options: &[u8]) -> Option<u8> {
+  let c = *s.get(*pos)?;
+  if options.contains(&c) {
+    *pos += 1;
+    Some(c)
+  } else {
+    None
+  }
+}
+
+/// accepts `2024-02-29`, `2024-02-29T08:30:00Z`, `2024-02-29 08:30:00.123+08:00`.
+/// time without an offset is taken as UTC, digits of fraction after milliseconds are dropped
+fn parse_rfc3339(s: &str) -> Option<TimeFields> {
+  let s = s.trim().as_bytes();
+  let mut pos = 0;
+  let year = match take_byte(s, &mut pos, b"+-") {
+    Some(b'-') => -take_digits(s, &mut pos, 6)?,
+    Some(_) => take_digits(s, &mut pos, 6)?,
+    None => take_digits(s, &mut pos, 4)?,
+  };
+  take_byte(s, &mut pos, b"-")?;
+  let month = take_digits(s, &mut pos, 2)?;
+  take_byte(s, &mut pos, b"-")?;
+  let day = take_digits(s, &mut pos, 2)?;
+  let mut t = TimeFields {
+    year,
+    month,
+    day,
+    hour: 0,
+    minute: 0,
+    second: 0,
+    ms: 0,
+    offset: 0,
+  };
+  if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+    return None;
+  }
+  if pos == s.len() {
+    return Some(t);
+  }
+  take_byte(s, &mut pos, b"Tt ")?;
+  t.hour = take_digits(s, &mut pos, 2)?;
+  take_byte(s, &mut pos, b":")?;
+  t.minute = take_digits(s, &mut pos, 2)?;
+  if take_byte(s, &mut pos, b":").is_some() {
+    t.second = take_digits(s, &mut pos, 2)?;
+    if take_byte(s, &mut pos, b".").is_some() {
+      let start = pos;
+      while pos < s.len() && s[pos].is_ascii_digit() {
+        pos += 1;
+      }
+      if pos == start {
+        return None;
+      }
+      let digits = &s[start..pos];
+      t.ms = (0..3).fold(0, |acc, idx| acc * 10 + digits.get(idx).map_or(0, |d| (d - b'0') as i64));
+    }
+  }
+  if t.hour > 23 || t.minute > 59 || t.second > 59 {
+    return None;
+  }
+  match take_byte(s, &mut pos, b"Zz+-") {
+    None | Some(b'Z' | b'z') => {}
+    Some(sign) => {
+      let hours = take_digits(s, &mut pos, 2)?;
+      take_byte(s, &mut pos, b":")?;
+      let minutes = take_digits(s, &mut pos, 2)?;
+      if hours > 23 || minutes > 59 {
+        return None;
+      }
+      let offset = hours * 60 + minutes;
+      t.offset = if sign == b'-' { -offset } else { offset };
+    }
+  }
+  if pos == s.len() {
+    Some(t)
+  } else {
+    None
+  }
+}
+
+/// `parse-time s`, RFC 3339 string into milliseconds since Unix epoch
+pub fn parse_time(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => match parse_rfc3339(s) {
+      Some(t) => time_result("parse-time", from_fields(&t)),
+      None => CalcitErr::err_str(format!("parse-time expected time in RFC 3339, got: {s}")),
+    },
+    Some(a) => CalcitErr::err_str(format!("parse-time expected a string, got: {a}")),
+    None => CalcitErr::err_str("parse-time expected a string, got nothing"),
+  }
+}
+
+/// `time-fields ms` or `time-fields ms offset`, returns a map of `:year :month :day :hour :minute :second :ms`,
+/// along with `:weekday` from 1 for Monday to 7 for Sunday, and `:offset`
+pub fn time_fields(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let time = read_time("time-fields", xs.first())?;
+  let t = to_fields(time, read_offset("time-fields", xs.get(1))?);
+  // 1970-01-01 is a Thursday
+  let weekday = ((time + t.offset * MS_PER_MINUTE).div_euclid(MS_PER_DAY) + 3).rem_euclid(7) + 1;
+  let mut data: rpds::HashTrieMapSync<Calcit, Calcit> = rpds::HashTrieMap::new_sync();
+  for (k, v) in [
+    ("year", t.year),
+    ("month", t.month),
+    ("day", t.day),
+    ("hour", t.hour),
+    ("minute", t.minute),
+    ("second", t.second),
+    ("ms", t.ms),
+    ("weekday", weekday),
+    ("offset", t.offset),
+  ] {
+    data.insert_mut(Calcit::tag(k), Calcit::Number(v as f64));
+  }
+  Ok(Calcit::Map(data))
+}
+
+/// `time-from-fields fields`, takes a map like the one from `time-fields`, `:year` is required.
+/// missing fields are taken from the start of the year in UTC, fields out of range are carried over
+pub fn time_from_fields(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let fields = match xs.first() {
+    Some(Calcit::Map(m)) => m,
+    Some(a) => return CalcitErr::err_str(format!("time-from-fields expected a map, got: {a}")),
+    None => return CalcitErr::err_str("time-from-fields expected a map, got nothing"),
+  };
+  let read = |name: &str, default: Option<i64>| -> Result<i64, CalcitErr> {
+    match (fields.get(&Calcit::tag(name)), default) {
+      (Some(Calcit::Number(n)), _) if n.fract() == 0.0 => Ok(*n as i64),
+      (None | Some(Calcit::Nil), Some(v)) => Ok(v),
+      (Some(a), _) => Err(CalcitErr::use_str(format!(
+        "time-from-fields expected an integer for :{name}, got: {a}"
+      ))),
+      (None, None) => Err(CalcitErr::use_str(format!("time-from-fields expected :{name} in fields"))),
+    }
+  };
+  let mut t = TimeFields {
+    year: read("year", None)?,
+    month: read("month", Some(1))?,
+    day: read("day", Some(1))?,
+    hour: read("hour", Some(0))?,
+    minute: read("minute", Some(0))?,
+    second: read("second", Some(0))?,
+    ms: read("ms", Some(0))?,
+    offset: read("offset", Some(0))?,
+  };
+  // months out of range turn into years, days are carried by day count
+  let Some((year, month)) = t
+    .month
+    .checked_sub(1)
+    .and_then(|m| Some((t.year.checked_add(m.div_euclid(12))?, m.rem_euclid(12) + 1)))
+  else {
+    return time_result("time-from-fields", None);
+  };
+  t.year = year;
+  t.month = month;
+  time_result("time-from-fields", from_fields(&t))
+}
+
+/// `time-add ms n unit` or `time-add ms n unit offset`, units are `:ms :second :minute :hour :day :week :month :year`.
+/// months and years are added in the calendar of the offset, days at the end are clamped, like Jan 31 to Feb 29
+pub fn time_add(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let time = read_time("time-add", xs.first())?;
+  let n = match xs.get(1) {
+    Some(Calcit::Number(n)) => *n,
+    Some(a) => return CalcitErr::err_str(format!("time-add expected a number to add, got: {a}")),
+    None => return CalcitErr::err_nodes("time-add expected 3 arguments, got:", xs),
+  };
+  let unit = match xs.get(2) {
+    Some(Calcit::Tag(t)) => t.ref_str().to_owned(),
+    Some(a) => return CalcitErr::err_str(format!("time-add expected a unit in tag, got: {a}")),
+    None => return CalcitErr::err_nodes("time-add expected 3 arguments, got:", xs),
+  };
+  let step = match unit.as_str() {
+    "ms" => 1,
+    "second" => 1000,
+    "minute" => MS_PER_MINUTE,
+    "hour" => 3_600_000,
+    "day" => MS_PER_DAY,
+    "week" => 7 * MS_PER_DAY,
+    "month" | "year" => {
+      if n.fract() != 0.0 {
+        return CalcitErr::err_str(format!("time-add expected an integer of {unit}s, got: {n}"));
+      }
+      let mut t = to_fields(time, read_offset("time-add", xs.get(3))?);
+      // `as` saturates on huge numbers, which are caught by checked arithmetic
+      let months = if unit == "year" {
+        (n as i64).checked_mul(12)
+      } else {
+        Some(n as i64)
+      };
+      let Some(total) = months.and_then(|m| (t.year * 12 + t.month - 1).checked_add(m)) else {
+        return time_result("time-add", None);
+      };
+      t.year = total.div_euclid(12);
+      t.month = total.rem_euclid(12) + 1;
+      t.day = t.day.min(days_in_month(t.year, t.month));
+      return time_result("time-add", from_fields(&t));
+    }
+    _ => return CalcitErr::err_str(format!("time-add expected a unit of time, got: :{unit}")),
+  };
+  let result = (time as f64 + n * step as f64).floor();
+  if result.is_finite() && result.abs() <= MAX_TIME as f64 {
+    Ok(Calcit::Number(result))
+  } else {
+    time_result("time-add", None)
+  }
+}
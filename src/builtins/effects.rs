@@ -1,5 +1,4 @@
 use std::env;
-use std::fs;
 use std::process::exit;
 use std::sync::LazyLock;
 use std::sync::RwLock;
@@ -20,7 +19,15 @@ pub enum CliRunningMode {
 static STARTED_INSTANT: LazyLock<RwLock<Instant>> = LazyLock::new(|| RwLock::new(Instant::now()));
 static CLI_RUNNING_MODE: LazyLock<RwLock<CliRunningMode>> = LazyLock::new(|| RwLock::new(CliRunningMode::Eval));
 
+/// `raise message`, `raise message data`, or `raise data` with a value that is not a string
 pub fn raise(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs {
+    [Calcit::Str(s)] => return CalcitErr::err_str(&**s),
+    [data] => return CalcitErr::err_data(data.turn_string(), data.to_owned()),
+    [message, data] => return CalcitErr::err_data(message.turn_string(), data.to_owned()),
+    _ => {}
+  }
+  // more arguments are joined into message
   let mut s = String::from("");
   for (idx, x) in xs.iter().enumerate() {
     if idx > 0 {
@@ -111,25 +118,3 @@ pub fn get_env(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     None => CalcitErr::err_str("get-env expected an argument, got nothing"),
   }
 }
-
-pub fn read_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
-  match xs.first() {
-    Some(Calcit::Str(s)) => match fs::read_to_string(&**s) {
-      Ok(content) => Ok(Calcit::Str(content.into())),
-      Err(e) => CalcitErr::err_str(format!("read-file failed at {}: {e}", &**s)),
-    },
-    Some(a) => CalcitErr::err_str(format!("read-file expected a string, got: {a}")),
-    None => CalcitErr::err_str("read-file expected a filename, got nothing"),
-  }
-}
-
-pub fn write_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
-  match (xs.first(), xs.get(1)) {
-    (Some(Calcit::Str(path)), Some(Calcit::Str(content))) => match fs::write(&**path, &**content) {
-      Ok(_) => Ok(Calcit::Nil),
-      Err(e) => CalcitErr::err_str(format!("write-file failed, {e}")),
-    },
-    (Some(a), Some(b)) => CalcitErr::err_str(format!("write-file expected 2 strings, got: {a} {b}")),
-    (a, b) => CalcitErr::err_str(format!("write-file expected 2 strings, got: {a:?} {b:?}")),
-  }
-}
@@ -0,0 +1,230 @@
+//! filesystem procs for scripting, paths are strings.
+//! errors carry `:path` and `:kind` of OS error in data, so that they can be handled in `try`
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+use walkdir::WalkDir;
+
+use crate::calcit::{Calcit, CalcitErr};
+
+/// error with data like `{} (:path |a.txt) (:kind :not-found)`
+fn io_err(op: &str, path: &str, e: &io::Error) -> Result<Calcit, CalcitErr> {
+  let mut data: rpds::HashTrieMapSync<Calcit, Calcit> = rpds::HashTrieMap::new_sync();
+  data.insert_mut(Calcit::tag("path"), Calcit::new_str(path));
+  data.insert_mut(Calcit::tag("kind"), Calcit::tag(&error_kind_name(e.kind())));
+  CalcitErr::err_data(format!("{op} failed at {path}: {e}"), Calcit::Map(data))
+}
+
+/// `NotFound` turns into `not-found`
+fn error_kind_name(kind: io::ErrorKind) -> String {
+  let mut name = String::new();
+  for (idx, c) in format!("{kind:?}").chars().enumerate() {
+    if c.is_ascii_uppercase() {
+      if idx > 0 {
+        name.push('-');
+      }
+      name.push(c.to_ascii_lowercase());
+    } else {
+      name.push(c);
+    }
+  }
+  name
+}
+
+pub fn read_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => match fs::read_to_string(&**s) {
+      Ok(content) => Ok(Calcit::Str(content.into())),
+      Err(e) => io_err("read-file", s, &e),
+    },
+    Some(a) => CalcitErr::err_str(format!("read-file expected a string, got: {a}")),
+    None => CalcitErr::err_str("read-file expected a filename, got nothing"),
+  }
+}
+
+pub fn write_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(path)), Some(Calcit::Str(content))) => match fs::write(&**path, &**content) {
+      Ok(_) => Ok(Calcit::Nil),
+      Err(e) => io_err("write-file", path, &e),
+    },
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("write-file expected 2 strings, got: {a} {b}")),
+    (a, b) => CalcitErr::err_str(format!("write-file expected 2 strings, got: {a:?} {b:?}")),
+  }
+}
+
+/// appends to the end of file, file is created when missing
+pub fn append_file(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(path)), Some(Calcit::Str(content))) => {
+      let appended = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&**path)
+        .and_then(|mut f| f.write_all(content.as_bytes()));
+      match appended {
+        Ok(_) => Ok(Calcit::Nil),
+        Err(e) => io_err("append-file", path, &e),
+      }
+    }
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("append-file expected 2 strings, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("append-file expected 2 arguments, got:", xs),
+  }
+}
+
+pub fn read_file_buffer(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => match fs::read(&**s) {
+      Ok(content) => Ok(Calcit::Buffer(content)),
+      Err(e) => io_err("read-file-buffer", s, &e),
+    },
+    Some(a) => CalcitErr::err_str(format!("read-file-buffer expected a string, got: {a}")),
+    None => CalcitErr::err_str("read-file-buffer expected a filename, got nothing"),
+  }
+}
+
+pub fn write_file_buffer(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(path)), Some(Calcit::Buffer(content))) => match fs::write(&**path, content) {
+      Ok(_) => Ok(Calcit::Nil),
+      Err(e) => io_err("write-file-buffer", path, &e),
+    },
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("write-file-buffer expected a string and a buffer, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("write-file-buffer expected 2 arguments, got:", xs),
+  }
+}
+
+/// names of entries in a directory, sorted
+pub fn read_dir(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => {
+      let entries = match fs::read_dir(&**s) {
+        Ok(entries) => entries,
+        Err(e) => return io_err("read-dir", s, &e),
+      };
+      let mut names: Vec<String> = vec![];
+      for entry in entries {
+        match entry {
+          Ok(entry) => names.push(entry.file_name().to_string_lossy().into_owned()),
+          Err(e) => return io_err("read-dir", s, &e),
+        }
+      }
+      names.sort();
+      Ok(Calcit::from(names.into_iter().map(Calcit::new_str).collect::<Vec<_>>()))
+    }
+    Some(a) => CalcitErr::err_str(format!("read-dir expected a string, got: {a}")),
+    None => CalcitErr::err_str("read-dir expected a path, got nothing"),
+  }
+}
+
+/// paths of files under a directory recursively, sorted by names in each directory
+pub fn walk_dir(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => {
+      let mut paths: Vec<Calcit> = vec![];
+      for entry in WalkDir::new(&**s).sort_by_file_name() {
+        match entry {
+          Ok(entry) => {
+            if entry.file_type().is_file() {
+              paths.push(Calcit::new_str(entry.path().to_string_lossy()));
+            }
+          }
+          Err(e) => {
+            let path = e
+              .path()
+              .map(|p| p.to_string_lossy().into_owned())
+              .unwrap_or_else(|| (**s).to_owned());
+            return io_err("walk-dir", &path, &io::Error::from(e));
+          }
+        }
+      }
+      Ok(Calcit::from(paths))
+    }
+    Some(a) => CalcitErr::err_str(format!("walk-dir expected a string, got: {a}")),
+    None => CalcitErr::err_str("walk-dir expected a path, got nothing"),
+  }
+}
+
+pub fn path_exists(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => Ok(Calcit::Bool(Path::new(&**s).exists())),
+    Some(a) => CalcitErr::err_str(format!("path-exists? expected a string, got: {a}")),
+    None => CalcitErr::err_str("path-exists? expected a path, got nothing"),
+  }
+}
+
+/// returns a map with `:kind` in `:file` `:dir` `:symlink`, `:size`, `:modified` in milliseconds, and `:readonly`
+pub fn path_metadata(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => {
+      let meta = match fs::symlink_metadata(&**s) {
+        Ok(meta) => meta,
+        Err(e) => return io_err("path-metadata", s, &e),
+      };
+      let kind = if meta.is_symlink() {
+        "symlink"
+      } else if meta.is_dir() {
+        "dir"
+      } else {
+        "file"
+      };
+      let modified = match meta.modified().ok().and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(d) => Calcit::Number(d.as_millis() as f64),
+        None => Calcit::Nil,
+      };
+      let mut data: rpds::HashTrieMapSync<Calcit, Calcit> = rpds::HashTrieMap::new_sync();
+      data.insert_mut(Calcit::tag("kind"), Calcit::tag(kind));
+      data.insert_mut(Calcit::tag("size"), Calcit::Number(meta.len() as f64));
+      data.insert_mut(Calcit::tag("modified"), modified);
+      data.insert_mut(Calcit::tag("readonly"), Calcit::Bool(meta.permissions().readonly()));
+      Ok(Calcit::Map(data))
+    }
+    Some(a) => CalcitErr::err_str(format!("path-metadata expected a string, got: {a}")),
+    None => CalcitErr::err_str("path-metadata expected a path, got nothing"),
+  }
+}
+
+/// creates directory along with missing parents, like `mkdir -p`
+pub fn create_dir(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => match fs::create_dir_all(&**s) {
+      Ok(_) => Ok(Calcit::Nil),
+      Err(e) => io_err("create-dir", s, &e),
+    },
+    Some(a) => CalcitErr::err_str(format!("create-dir expected a string, got: {a}")),
+    None => CalcitErr::err_str("create-dir expected a path, got nothing"),
+  }
+}
+
+/// removes a file, or a directory with its content
+pub fn remove_path(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => {
+      let removed = match fs::symlink_metadata(&**s) {
+        Ok(meta) if meta.is_dir() => fs::remove_dir_all(&**s),
+        Ok(_) => fs::remove_file(&**s),
+        Err(e) => Err(e),
+      };
+      match removed {
+        Ok(_) => Ok(Calcit::Nil),
+        Err(e) => io_err("remove-path", s, &e),
+      }
+    }
+    Some(a) => CalcitErr::err_str(format!("remove-path expected a string, got: {a}")),
+    None => CalcitErr::err_str("remove-path expected a path, got nothing"),
+  }
+}
+
+pub fn rename_path(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(from)), Some(Calcit::Str(to))) => match fs::rename(&**from, &**to) {
+      Ok(_) => Ok(Calcit::Nil),
+      Err(e) => io_err("rename-path", from, &e),
+    },
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("rename-path expected 2 strings, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("rename-path expected 2 arguments, got:", xs),
+  }
+}
@@ -464,7 +464,7 @@ pub fn sort(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitE
           let v = runner::run_fn(&[(*a).to_owned(), (*b).to_owned()], info, call_stack);
           match v {
             Ok(Calcit::Number(x)) if x < 0.0 => Ordering::Less,
-            Ok(Calcit::Number(x)) if x == 0.0 => Ordering::Equal,
+            Ok(Calcit::Number(0.0)) => Ordering::Equal,
             Ok(Calcit::Number(x)) if x > 0.0 => Ordering::Greater,
             Ok(a) => {
               eprintln!("expected number from sort comparator, got: {a}");
@@ -491,7 +491,7 @@ pub fn sort(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitE
           let v = builtins::handle_proc(*proc, &[(*a).to_owned(), (*b).to_owned()], call_stack);
           match v {
             Ok(Calcit::Number(x)) if x < 0.0 => Ordering::Less,
-            Ok(Calcit::Number(x)) if x == 0.0 => Ordering::Equal,
+            Ok(Calcit::Number(0.0)) => Ordering::Equal,
             Ok(Calcit::Number(x)) if x > 0.0 => Ordering::Greater,
             Ok(a) => {
               eprintln!("expected number from sort comparator, got: {a}");
@@ -1,3 +1,6 @@
+use std::cmp::Ordering;
+
+use crate::calcit::cmp_bigint_f64;
 use crate::{Calcit, CalcitErr};
 
 pub fn binary_equal(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
@@ -7,9 +10,21 @@ pub fn binary_equal(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   }
 }
 
+/// numbers and bigints are compared by values, like in JavaScript
+fn cmp_numeric(a: &Calcit, b: &Calcit) -> Option<Ordering> {
+  match (a, b) {
+    (Calcit::Number(x), Calcit::BigInt(y)) => Some(cmp_bigint_f64(y, *x).reverse()),
+    (Calcit::BigInt(x), Calcit::Number(y)) => Some(cmp_bigint_f64(x, *y)),
+    _ => None,
+  }
+}
+
 pub fn binary_less(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   if xs.len() == 2 {
-    Ok(Calcit::Bool(xs[0] < xs[1]))
+    match cmp_numeric(&xs[0], &xs[1]) {
+      Some(o) => Ok(Calcit::Bool(o == Ordering::Less)),
+      None => Ok(Calcit::Bool(xs[0] < xs[1])),
+    }
   } else {
     CalcitErr::err_nodes("&< expected 2 arguments, got:", xs)
   }
@@ -17,7 +32,10 @@ pub fn binary_less(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 
 pub fn binary_greater(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   if xs.len() == 2 {
-    Ok(Calcit::Bool(xs[0] > xs[1]))
+    match cmp_numeric(&xs[0], &xs[1]) {
+      Some(o) => Ok(Calcit::Bool(o == Ordering::Greater)),
+      None => Ok(Calcit::Bool(xs[0] > xs[1])),
+    }
   } else {
     CalcitErr::err_nodes("&> expected 2 arguments, got:", xs)
   }
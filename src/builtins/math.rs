@@ -1,3 +1,6 @@
+use num_bigint::BigInt;
+use num_traits::{FromPrimitive, ToPrimitive, Zero};
+
 use crate::calcit::{Calcit, CalcitErr};
 
 use crate::util::number::{f64_to_i32, is_integer};
@@ -5,7 +8,8 @@ use crate::util::number::{f64_to_i32, is_integer};
 pub fn binary_add(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match (xs.first(), xs.get(1)) {
     (Some(Calcit::Number(a)), Some(Calcit::Number(b))) => Ok(Calcit::Number(a + b)),
-    (Some(a), Some(b)) => CalcitErr::err_str(format!("invalid types for &+: {a} {b}")),
+    (Some(Calcit::BigInt(a)), Some(Calcit::BigInt(b))) => Ok(Calcit::BigInt(a + b)),
+    (Some(a), Some(b)) => invalid_operands("&+", a, b),
     (_, _) if xs.len() != 2 => CalcitErr::err_str("&+ expected 2 arguments"),
     _ => CalcitErr::err_str("invalid arguments"),
   }
@@ -14,7 +18,8 @@ pub fn binary_add(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 pub fn binary_minus(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match (xs.first(), xs.get(1)) {
     (Some(Calcit::Number(a)), Some(Calcit::Number(b))) => Ok(Calcit::Number(a - b)),
-    (Some(a), Some(b)) => CalcitErr::err_str(format!("invalid types for &-: {a} {b}")),
+    (Some(Calcit::BigInt(a)), Some(Calcit::BigInt(b))) => Ok(Calcit::BigInt(a - b)),
+    (Some(a), Some(b)) => invalid_operands("&-", a, b),
     (_, _) if xs.len() != 2 => CalcitErr::err_str("&- expected 2 arguments"),
     _ => CalcitErr::err_str("invalid arguments"),
   }
@@ -23,7 +28,8 @@ pub fn binary_minus(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 pub fn binary_multiply(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match (xs.first(), xs.get(1)) {
     (Some(Calcit::Number(a)), Some(Calcit::Number(b))) => Ok(Calcit::Number(a * b)),
-    (Some(a), Some(b)) => CalcitErr::err_str(format!("invalid types for &*: {a} {b}")),
+    (Some(Calcit::BigInt(a)), Some(Calcit::BigInt(b))) => Ok(Calcit::BigInt(a * b)),
+    (Some(a), Some(b)) => invalid_operands("&*", a, b),
     (_, _) if xs.len() != 2 => CalcitErr::err_str("&* expected 2 arguments"),
     _ => CalcitErr::err_str("invalid arguments"),
   }
@@ -32,12 +38,57 @@ pub fn binary_multiply(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 pub fn binary_divide(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match (xs.first(), xs.get(1)) {
     (Some(Calcit::Number(a)), Some(Calcit::Number(b))) => Ok(Calcit::Number(a / b)),
-    (Some(a), Some(b)) => CalcitErr::err_str(format!("invalid types for &/: {a} {b}")),
+    // truncated like in JavaScript
+    (Some(Calcit::BigInt(a)), Some(Calcit::BigInt(b))) => {
+      if b.is_zero() {
+        CalcitErr::err_str(format!("&/ division by zero: {a} {b}"))
+      } else {
+        Ok(Calcit::BigInt(a / b))
+      }
+    }
+    (Some(a), Some(b)) => invalid_operands("&/", a, b),
     (_, _) if xs.len() != 2 => CalcitErr::err_str("&/ expected 2 arguments"),
     _ => CalcitErr::err_str("invalid arguments"),
   }
 }
 
+/// bigints and numbers are not mixed in arithmetic, same as in JavaScript
+fn invalid_operands(op: &str, a: &Calcit, b: &Calcit) -> Result<Calcit, CalcitErr> {
+  match (a, b) {
+    (Calcit::Number(_), Calcit::BigInt(_)) | (Calcit::BigInt(_), Calcit::Number(_)) => CalcitErr::err_str(format!(
+      "{op} cannot mix bigint and number, convert with `bigint` or `&bigint:to-number`: {a} {b}"
+    )),
+    _ => CalcitErr::err_str(format!("invalid types for {op}: {a} {b}")),
+  }
+}
+
+/// turns an integer number or a string of digits into bigint
+pub fn bigint(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::BigInt(n)) => Ok(Calcit::BigInt(n.to_owned())),
+    Some(Calcit::Number(n)) if is_integer(*n) => match BigInt::from_f64(*n) {
+      Some(v) => Ok(Calcit::BigInt(v)),
+      None => CalcitErr::err_str(format!("bigint cannot convert: {n}")),
+    },
+    Some(Calcit::Number(n)) => CalcitErr::err_str(format!("bigint expected an integer, got: {n}")),
+    Some(Calcit::Str(s)) => match s.trim().parse::<BigInt>() {
+      Ok(v) => Ok(Calcit::BigInt(v)),
+      Err(e) => CalcitErr::err_str(format!("bigint failed to parse {s:?}: {e}")),
+    },
+    Some(a) => CalcitErr::err_str(format!("bigint expected a number or a string, got: {a}")),
+    None => CalcitErr::err_str("bigint expected 1 argument"),
+  }
+}
+
+/// nearest number, precision is lost for large values
+pub fn bigint_to_number(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::BigInt(n)) => Ok(Calcit::Number(n.to_f64().unwrap_or(f64::NAN))),
+    Some(a) => CalcitErr::err_str(format!("&bigint:to-number expected a bigint, got: {a}")),
+    None => CalcitErr::err_str("&bigint:to-number expected 1 argument"),
+  }
+}
+
 pub fn round_ques(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match xs.first() {
     Some(Calcit::Number(n)) => Ok(Calcit::Bool(is_integer(*n))),
@@ -70,6 +121,13 @@ pub fn rem(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       (Err(a), _) => CalcitErr::err_str(a),
       (_, Err(a)) => CalcitErr::err_str(a),
     },
+    (Some(Calcit::BigInt(a)), Some(Calcit::BigInt(b))) => {
+      if b.is_zero() {
+        CalcitErr::err_str(format!("&number:rem division by zero: {a} {b}"))
+      } else {
+        Ok(Calcit::BigInt(a % b))
+      }
+    }
     (Some(a), Some(b)) => CalcitErr::err_str(format!("mod expected 2 numbers, got: {a:?} {b:?}")),
     (a, b) => CalcitErr::err_str(format!("mod expected 2 numbers, got: {a:?} {b:?}")),
   }
@@ -99,6 +157,10 @@ pub fn cos(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
 pub fn pow(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match (xs.first(), xs.get(1)) {
     (Some(Calcit::Number(base)), Some(Calcit::Number(step))) => Ok(Calcit::Number(base.powf(*step))),
+    (Some(Calcit::BigInt(base)), Some(Calcit::BigInt(step))) => match step.to_u32() {
+      Some(v) => Ok(Calcit::BigInt(base.pow(v))),
+      None => CalcitErr::err_str(format!("pow expected a non-negative bigint exponent, got: {step}")),
+    },
     (Some(a), Some(b)) => CalcitErr::err_str(format!("pow expected 2 numbers, got: {a:?} {b:?}")),
     (a, b) => CalcitErr::err_str(format!("pow expected 2 numbers, got: {a:?} {b:?}")),
   }
@@ -125,6 +187,10 @@ pub fn bit_shr(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       (Err(e), _) => CalcitErr::err_str(format!("bit-shr expect int as initial value: {e}")),
       (_, Err(e)) => CalcitErr::err_str(format!("bit-shr expect int as step: {e}")),
     },
+    (Some(Calcit::BigInt(n)), Some(Calcit::BigInt(m))) => match m.to_usize() {
+      Some(step) => Ok(Calcit::BigInt(n >> step)),
+      None => CalcitErr::err_str(format!("bit-shr expected a non-negative step, got: {m}")),
+    },
     (Some(a), Some(b)) => CalcitErr::err_str(format!("bit-shr expected 2 numbers, got: {a} {b}")),
     (a, b) => CalcitErr::err_str(format!("bit-shr expected 2 number: {a:?} {b:?}")),
   }
@@ -137,6 +203,10 @@ pub fn bit_shl(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       (Err(e), _) => CalcitErr::err_str(format!("bit-shl expect int as initial value: {e}")),
       (_, Err(e)) => CalcitErr::err_str(format!("bit-shl expect int as step: {e}")),
     },
+    (Some(Calcit::BigInt(n)), Some(Calcit::BigInt(m))) => match m.to_usize() {
+      Some(step) => Ok(Calcit::BigInt(n << step)),
+      None => CalcitErr::err_str(format!("bit-shl expected a non-negative step, got: {m}")),
+    },
     (Some(a), Some(b)) => CalcitErr::err_str(format!("bit-shl expected 2 numbers, got: {a} {b}")),
     (a, b) => CalcitErr::err_str(format!("bit-shl expected 2 number: {a:?} {b:?}")),
   }
@@ -149,6 +219,7 @@ pub fn bit_and(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       (Err(e), _) => CalcitErr::err_str(format!("bit-and expect int as initial value: {e}")),
       (_, Err(e)) => CalcitErr::err_str(format!("bit-and expect int as step: {e}")),
     },
+    (Some(Calcit::BigInt(n)), Some(Calcit::BigInt(m))) => Ok(Calcit::BigInt(n & m)),
     (Some(a), Some(b)) => CalcitErr::err_str(format!("bit-and expected 2 numbers, got: {a} {b}")),
     (a, b) => CalcitErr::err_str(format!("bit-and expected 2 number: {a:?} {b:?}")),
   }
@@ -161,6 +232,7 @@ pub fn bit_or(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       (Err(e), _) => CalcitErr::err_str(format!("bit-or expect int as initial value: {e}")),
       (_, Err(e)) => CalcitErr::err_str(format!("bit-or expect int as step: {e}")),
     },
+    (Some(Calcit::BigInt(n)), Some(Calcit::BigInt(m))) => Ok(Calcit::BigInt(n | m)),
     (Some(a), Some(b)) => CalcitErr::err_str(format!("bit-or expected 2 numbers, got: {a} {b}")),
     (a, b) => CalcitErr::err_str(format!("bit-or expected 2 number: {a:?} {b:?}")),
   }
@@ -173,6 +245,7 @@ pub fn bit_xor(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       (Err(e), _) => CalcitErr::err_str(format!("bit-xor expect int as initial value: {e}")),
       (_, Err(e)) => CalcitErr::err_str(format!("bit-xor expect int as step: {e}")),
     },
+    (Some(Calcit::BigInt(n)), Some(Calcit::BigInt(m))) => Ok(Calcit::BigInt(n ^ m)),
     (Some(a), Some(b)) => CalcitErr::err_str(format!("bit-xor expected 2 numbers, got: {a} {b}")),
     (a, b) => CalcitErr::err_str(format!("bit-xor expected 2 number: {a:?} {b:?}")),
   }
@@ -184,6 +257,7 @@ pub fn bit_not(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       Ok(value) => Ok(Calcit::Number(!value as f64)),
       Err(e) => CalcitErr::err_str(format!("bit-not expect int as initial value: {e}")),
     },
+    Some(Calcit::BigInt(n)) => Ok(Calcit::BigInt(!n)),
     Some(a) => CalcitErr::err_str(format!("bit-not expected a number: {a}")),
     a => CalcitErr::err_str(format!("bit-not expected 1 number: {a:?}")),
   }
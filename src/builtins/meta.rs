@@ -10,6 +10,7 @@ use crate::{
     cirru::{self, cirru_to_calcit},
     data_to_calcit,
     edn::{self, edn_to_calcit},
+    json::{self, JsonWriterOptions},
   },
   runner,
   util::number::f64_to_usize,
@@ -38,11 +39,13 @@ pub fn type_of(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     Calcit::Nil => Ok(Calcit::tag("nil")),
     Calcit::Bool(..) => Ok(Calcit::tag("bool")),
     Calcit::Number(..) => Ok(Calcit::tag("number")),
+    Calcit::BigInt(..) => Ok(Calcit::tag("bigint")),
     Calcit::Symbol { .. } => Ok(Calcit::tag("symbol")),
     Calcit::Tag(..) => Ok(Calcit::tag("tag")),
     Calcit::Str(..) => Ok(Calcit::tag("string")),
     Calcit::Thunk(..) => Ok(Calcit::tag("thunk")), // internal
     Calcit::Ref(..) => Ok(Calcit::tag("ref")),
+    Calcit::Promise(..) => Ok(Calcit::tag("promise")),
     Calcit::Tuple { .. } => Ok(Calcit::tag("tuple")),
     Calcit::Buffer(..) => Ok(Calcit::tag("buffer")),
     Calcit::CirruQuote(..) => Ok(Calcit::tag("cirru-quote")),
@@ -222,6 +225,51 @@ pub fn format_cirru_edn(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   }
 }
 
+/// reads a bool from options map, missing options are false
+fn read_option(options: Option<&Calcit>, name: &str) -> Result<bool, CalcitErr> {
+  match options {
+    None | Some(Calcit::Nil) => Ok(false),
+    Some(Calcit::Map(xs)) => match xs.get(&Calcit::tag(name)) {
+      None | Some(Calcit::Nil) => Ok(false),
+      Some(Calcit::Bool(b)) => Ok(*b),
+      Some(a) => Err(CalcitErr::use_str(format!("expected a bool for option :{name}, got: {a}"))),
+    },
+    Some(a) => Err(CalcitErr::use_str(format!("expected options in a map, got: {a}"))),
+  }
+}
+
+/// objects are turned into maps with string keys, or tag keys with `:tag-keys` option
+pub fn parse_json(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Str(s)) => {
+      let tag_keys = read_option(xs.get(1), "tag-keys")?;
+      match serde_json::from_str::<serde_json::Value>(s) {
+        Ok(v) => Ok(json::json_to_calcit(&v, tag_keys)),
+        Err(e) => CalcitErr::err_str(format!("parse-json failed, {e}")),
+      }
+    }
+    Some(a) => CalcitErr::err_str(format!("parse-json expected a string, got: {a}")),
+    None => CalcitErr::err_str("parse-json expected 1 argument"),
+  }
+}
+
+/// options `:pretty` and `:sort-keys`
+pub fn format_json(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(a) => {
+      let options = JsonWriterOptions {
+        pretty: read_option(xs.get(1), "pretty")?,
+        sort_keys: read_option(xs.get(1), "sort-keys")?,
+      };
+      match json::format_json(a, options) {
+        Ok(s) => Ok(Calcit::new_str(s)),
+        Err(e) => CalcitErr::err_str(format!("format-json failed, {e}")),
+      }
+    }
+    None => CalcitErr::err_str("format-json expected 1 argument"),
+  }
+}
+
 pub fn cirru_quote_to_list(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   if xs.len() != 1 {
     return CalcitErr::err_nodes("&cirru-quote:to-list expected 1 argument, got:", xs);
@@ -0,0 +1,322 @@
+//! running external commands. options in a map, with `:cwd`, `:env` in a map of strings,
+//! and `:buffer` for taking output in buffers instead of strings.
+//! spawned commands are referred to by ids, like timers
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, Command, ExitStatus, Stdio};
+use std::sync::{mpsc, Arc, LazyLock, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::calcit::{Calcit, CalcitErr, CalcitTuple};
+use crate::call_stack::CallStackList;
+use crate::runner::{self, event_loop};
+
+struct CommandOptions {
+  cwd: Option<Arc<str>>,
+  env: Vec<(Arc<str>, Arc<str>)>,
+  buffer: bool,
+  /// keep stdin open for `write-command-stdin`, only for `spawn-command`
+  stdin: bool,
+}
+
+/// a spawned command, removed after it exits
+struct RunningCommand {
+  child: Arc<Mutex<Child>>,
+  stdin: Option<ChildStdin>,
+}
+
+#[derive(Default)]
+struct CommandsState {
+  running: HashMap<usize, RunningCommand>,
+  next_id: usize,
+}
+
+static COMMANDS: LazyLock<Mutex<CommandsState>> = LazyLock::new(|| Mutex::new(CommandsState::default()));
+
+fn read_options(proc_name: &str, options: Option<&Calcit>) -> Result<CommandOptions, CalcitErr> {
+  let mut result = CommandOptions {
+    cwd: None,
+    env: vec![],
+    buffer: false,
+    stdin: false,
+  };
+  let options = match options {
+    None | Some(Calcit::Nil) => return Ok(result),
+    Some(Calcit::Map(xs)) => xs,
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected options in a map, got: {a}"))),
+  };
+  match options.get(&Calcit::tag("cwd")) {
+    None | Some(Calcit::Nil) => {}
+    Some(Calcit::Str(s)) => result.cwd = Some(s.to_owned()),
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected a string for :cwd, got: {a}"))),
+  }
+  match options.get(&Calcit::tag("env")) {
+    None | Some(Calcit::Nil) => {}
+    Some(Calcit::Map(xs)) => {
+      for (k, v) in xs {
+        match (k, v) {
+          (Calcit::Str(k), Calcit::Str(v)) => result.env.push((k.to_owned(), v.to_owned())),
+          (a, b) => return Err(CalcitErr::use_str(format!("{proc_name} expected strings in :env, got: {a} {b}"))),
+        }
+      }
+    }
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected a map for :env, got: {a}"))),
+  }
+  match options.get(&Calcit::tag("buffer")) {
+    None | Some(Calcit::Nil) => {}
+    Some(Calcit::Bool(b)) => result.buffer = *b,
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected a bool for :buffer, got: {a}"))),
+  }
+  match options.get(&Calcit::tag("stdin")) {
+    None | Some(Calcit::Nil) => {}
+    Some(Calcit::Bool(b)) => result.stdin = *b,
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected a bool for :stdin, got: {a}"))),
+  }
+  Ok(result)
+}
+
+/// command with args and options applied, environment variables are inherited
+fn build_command(proc_name: &str, xs: &[Calcit], options: Option<&Calcit>) -> Result<(Command, CommandOptions), CalcitErr> {
+  let program = match xs.first() {
+    Some(Calcit::Str(s)) => s,
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected a command in string, got: {a}"))),
+    None => return Err(CalcitErr::use_str(format!("{proc_name} expected a command, got nothing"))),
+  };
+  let mut command = Command::new(&**program);
+  match xs.get(1) {
+    None | Some(Calcit::Nil) => {}
+    Some(Calcit::List(args)) => {
+      for arg in args.iter() {
+        match arg {
+          Calcit::Str(s) => command.arg(&**s),
+          a => return Err(CalcitErr::use_str(format!("{proc_name} expected args in strings, got: {a}"))),
+        };
+      }
+    }
+    Some(a) => return Err(CalcitErr::use_str(format!("{proc_name} expected args in a list, got: {a}"))),
+  }
+  let options = read_options(proc_name, options)?;
+  if let Some(cwd) = &options.cwd {
+    command.current_dir(&**cwd);
+  }
+  for (k, v) in &options.env {
+    command.env(&**k, &**v);
+  }
+  Ok((command, options))
+}
+
+fn exit_code(status: &ExitStatus) -> Calcit {
+  match status.code() {
+    Some(code) => Calcit::Number(code as f64),
+    // terminated by a signal
+    None => Calcit::Nil,
+  }
+}
+
+fn output_value(bytes: Vec<u8>, buffer: bool) -> Calcit {
+  if buffer {
+    Calcit::Buffer(bytes)
+  } else {
+    Calcit::new_str(String::from_utf8_lossy(&bytes))
+  }
+}
+
+/// `run-command cmd args options`, waits for the command to finish,
+/// returns a map of `:code`, `:stdout` and `:stderr`. `:code` is nil when killed by a signal
+pub fn run_command(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let (mut command, options) = build_command("run-command", xs, xs.get(2))?;
+  let output = command
+    .stdin(Stdio::null())
+    .output()
+    .map_err(|e| CalcitErr::use_str(format!("run-command failed to start {}: {e}", xs[0])))?;
+  let mut data: rpds::HashTrieMapSync<Calcit, Calcit> = rpds::HashTrieMap::new_sync();
+  data.insert_mut(Calcit::tag("code"), exit_code(&output.status));
+  data.insert_mut(Calcit::tag("stdout"), output_value(output.stdout, options.buffer));
+  data.insert_mut(Calcit::tag("stderr"), output_value(output.stderr, options.buffer));
+  Ok(Calcit::Map(data))
+}
+
+enum StreamEvent {
+  Line(&'static str, Calcit),
+  Failed(String),
+}
+
+/// reads lines of output in a thread, lines are sent without line endings
+fn stream_lines<R: Read + Send + 'static>(
+  reader: R,
+  kind: &'static str,
+  buffer: bool,
+  sender: mpsc::Sender<StreamEvent>,
+) -> thread::JoinHandle<()> {
+  thread::spawn(move || {
+    let mut reader = BufReader::new(reader);
+    loop {
+      let mut line: Vec<u8> = vec![];
+      match reader.read_until(b'\n', &mut line) {
+        Ok(0) => break,
+        Ok(_) => {
+          if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+              line.pop();
+            }
+          }
+          if sender.send(StreamEvent::Line(kind, output_value(line, buffer))).is_err() {
+            break;
+          }
+        }
+        Err(e) => {
+          let _ = sender.send(StreamEvent::Failed(format!("failed to read {kind}: {e}")));
+          break;
+        }
+      }
+    }
+  })
+}
+
+fn event_tuple(kind: &str, value: Calcit) -> Calcit {
+  Calcit::Tuple(CalcitTuple {
+    tag: Arc::new(Calcit::tag(kind)),
+    extra: vec![value],
+    class: None,
+  })
+}
+
+/// `spawn-command cmd args options f` or `spawn-command cmd args f`, runs in another thread and returns id of the command.
+/// `f` is called in the event loop with `:: :stdout line`, `:: :stderr line` for each line, and `:: :exit code` at the end.
+/// program waits for the command before exiting
+pub fn spawn_command(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  let (options, f) = match xs.len() {
+    3 => (None, &xs[2]),
+    4 => (Some(&xs[2]), &xs[3]),
+    _ => return CalcitErr::err_nodes("spawn-command expected 3 or 4 arguments, got:", xs),
+  };
+  let info = match f {
+    Calcit::Fn { info, .. } => info.to_owned(),
+    a => return CalcitErr::err_str(format!("spawn-command expected a callback function, got: {a}")),
+  };
+  let (mut command, options) = build_command("spawn-command", xs, options)?;
+  let mut child = command
+    .stdin(if options.stdin { Stdio::piped() } else { Stdio::null() })
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| CalcitErr::use_str(format!("spawn-command failed to start {}: {e}", xs[0])))?;
+
+  let (sender, receiver) = mpsc::channel::<StreamEvent>();
+  let mut readers = vec![];
+  if let Some(stdout) = child.stdout.take() {
+    readers.push(stream_lines(stdout, "stdout", options.buffer, sender.to_owned()));
+  }
+  if let Some(stderr) = child.stderr.take() {
+    readers.push(stream_lines(stderr, "stderr", options.buffer, sender.to_owned()));
+  }
+  drop(sender);
+
+  let stdin = child.stdin.take();
+  let child = Arc::new(Mutex::new(child));
+  let id = {
+    let mut commands = COMMANDS.lock().expect("lock commands");
+    commands.next_id += 1;
+    let id = commands.next_id;
+    let running = RunningCommand {
+      child: child.to_owned(),
+      stdin,
+    };
+    commands.running.insert(id, running);
+    id
+  };
+
+  runner::track::track_task_add();
+  let copied_stack = call_stack.to_owned();
+  thread::spawn(move || {
+    let call = |event: Calcit| {
+      let info = info.to_owned();
+      let copied_stack = copied_stack.to_owned();
+      event_loop::enqueue(Box::new(move || {
+        if let Err(e) = runner::run_fn(&[event], &info, &copied_stack) {
+          event_loop::report_error("spawn-command callback", &e);
+        }
+      }));
+    };
+    // callbacks are sent to the event loop in order, ends when both outputs are closed
+    for event in receiver {
+      match event {
+        StreamEvent::Line(kind, line) => call(event_tuple(kind, line)),
+        StreamEvent::Failed(message) => eprintln!("[Error] spawn-command {message}"),
+      }
+    }
+    for reader in readers {
+      let _ = reader.join();
+    }
+    // polled, so the lock is not held and `kill-command` still works when output is closed early
+    let status = loop {
+      let polled = child.lock().expect("lock child").try_wait();
+      match polled {
+        Ok(Some(status)) => break Ok(status),
+        Ok(None) => thread::sleep(Duration::from_millis(10)),
+        Err(e) => break Err(e),
+      }
+    };
+    COMMANDS.lock().expect("lock commands").running.remove(&id);
+    match status {
+      Ok(status) => call(event_tuple("exit", exit_code(&status))),
+      Err(e) => eprintln!("[Error] spawn-command failed to wait for command: {e}"),
+    }
+    runner::track::track_task_release();
+  });
+
+  Ok(Calcit::Number(id as f64))
+}
+
+fn read_command_id(proc_name: &str, xs: &[Calcit]) -> Result<usize, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Number(id)) => Ok(*id as usize),
+    Some(a) => Err(CalcitErr::use_str(format!("{proc_name} expected a command id, got: {a}"))),
+    None => Err(CalcitErr::use_str(format!("{proc_name} expected a command id, got nothing"))),
+  }
+}
+
+/// `kill-command id`, kills a spawned command, `:exit` is still sent to callback. finished commands are ignored
+pub fn kill_command(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let id = read_command_id("kill-command", xs)?;
+  let child = match COMMANDS.lock().expect("lock commands").running.get(&id) {
+    Some(running) => running.child.to_owned(),
+    None => return Ok(Calcit::Nil),
+  };
+  let ret = child.lock().expect("lock child").kill();
+  ret.map_err(|e| CalcitErr::use_str(format!("kill-command failed to kill command {id}: {e}")))?;
+  Ok(Calcit::Nil)
+}
+
+/// `write-command-stdin id data`, writes a string or a buffer to stdin of a command spawned with `:stdin true`
+pub fn write_command_stdin(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let id = read_command_id("write-command-stdin", xs)?;
+  let data: &[u8] = match xs.get(1) {
+    Some(Calcit::Str(s)) => s.as_bytes(),
+    Some(Calcit::Buffer(buf)) => buf,
+    Some(a) => return CalcitErr::err_str(format!("write-command-stdin expected a string or a buffer, got: {a}")),
+    None => return CalcitErr::err_nodes("write-command-stdin expected 2 arguments, got:", xs),
+  };
+  let mut commands = COMMANDS.lock().expect("lock commands");
+  let stdin = match commands.running.get_mut(&id).and_then(|running| running.stdin.as_mut()) {
+    Some(stdin) => stdin,
+    None => return CalcitErr::err_str(format!("write-command-stdin found no open stdin of command {id}")),
+  };
+  stdin
+    .write_all(data)
+    .and_then(|_| stdin.flush())
+    .map_err(|e| CalcitErr::use_str(format!("write-command-stdin failed to write to command {id}: {e}")))?;
+  Ok(Calcit::Nil)
+}
+
+/// `close-command-stdin id`, so that commands reading stdin get the end of input
+pub fn close_command_stdin(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let id = read_command_id("close-command-stdin", xs)?;
+  if let Some(running) = COMMANDS.lock().expect("lock commands").running.get_mut(&id) {
+    running.stdin = None;
+  }
+  Ok(Calcit::Nil)
+}
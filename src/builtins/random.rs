@@ -0,0 +1,141 @@
+//! random numbers from mulberry32, which only takes 32-bit integer operations,
+//! so that `ts-src/calcit.procs.mts` produces the same sequence for the same seed.
+//! a generator from `rand-gen` is a tuple `:: :rand-gen ref` with the state in the ref, and it can be passed as the first argument of each proc.
+//! the ref is not reachable from user code, so refs from `atom` are never taken as generators.
+//! without a generator, a global one seeded from current time is used
+
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::builtins::{quick_build_atom, ValueAndListeners};
+use crate::calcit::{Calcit, CalcitErr, CalcitTuple};
+
+/// tag of tuples from `rand-gen`
+const GENERATOR_TAG: &str = "rand-gen";
+
+static GLOBAL_STATE: LazyLock<Mutex<u32>> = LazyLock::new(|| {
+  let seed = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_nanos() as u32)
+    .unwrap_or(0);
+  Mutex::new(seed)
+});
+
+/// returns a number in `[0, 1)` and moves the state forward
+fn mulberry32(state: &mut u32) -> f64 {
+  *state = state.wrapping_add(0x6D2B79F5);
+  let mut t = *state;
+  t = (t ^ (t >> 15)).wrapping_mul(t | 1);
+  t ^= t.wrapping_add((t ^ (t >> 7)).wrapping_mul(t | 61));
+  (t ^ (t >> 14)) as f64 / 4294967296.0
+}
+
+/// seeds are truncated into 32 bits, same as `>>> 0` in JavaScript
+fn seed_state(proc_name: &str, seed: f64) -> Result<u32, CalcitErr> {
+  if seed.is_finite() && seed.fract() == 0.0 {
+    // integers beyond i64 range also wrap like ToUint32 does
+    Ok(seed.rem_euclid(4294967296.0) as u32)
+  } else {
+    Err(CalcitErr::use_str(format!("{proc_name} expected an integer seed, got: {seed}")))
+  }
+}
+
+/// generator in the first argument is taken out, rest of arguments are returned
+fn split_generator(xs: &[Calcit]) -> (Option<&Mutex<ValueAndListeners>>, &[Calcit]) {
+  match xs.first() {
+    Some(Calcit::Tuple(CalcitTuple { tag, extra, .. })) => match (&**tag, extra.as_slice()) {
+      (Calcit::Tag(t), [Calcit::Ref(_path, locked_pair)]) if t.ref_str() == GENERATOR_TAG => (Some(locked_pair), &xs[1..]),
+      _ => (None, xs),
+    },
+    _ => (None, xs),
+  }
+}
+
+fn next_float(proc_name: &str, generator: Option<&Mutex<ValueAndListeners>>) -> Result<f64, CalcitErr> {
+  match generator {
+    Some(locked_pair) => {
+      let mut pair = locked_pair.lock().expect("read generator");
+      let mut state = match pair.value {
+        Calcit::Number(n) => seed_state(proc_name, n)?,
+        ref a => {
+          return Err(CalcitErr::use_str(format!(
+            "{proc_name} expected a generator from rand-gen, got: {a}"
+          )))
+        }
+      };
+      let x = mulberry32(&mut state);
+      pair.value = Calcit::Number(state as f64);
+      Ok(x)
+    }
+    None => Ok(mulberry32(&mut GLOBAL_STATE.lock().expect("read random state"))),
+  }
+}
+
+/// index in `[0, size)`
+fn next_index(proc_name: &str, generator: Option<&Mutex<ValueAndListeners>>, size: usize) -> Result<usize, CalcitErr> {
+  Ok((next_float(proc_name, generator)? * size as f64).floor() as usize)
+}
+
+/// `rand-gen seed`, a generator giving the same sequence on both backends for the same seed
+pub fn rand_gen(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match xs.first() {
+    Some(Calcit::Number(n)) => Ok(Calcit::Tuple(CalcitTuple {
+      tag: Arc::new(Calcit::tag(GENERATOR_TAG)),
+      extra: vec![quick_build_atom(Calcit::Number(seed_state("rand-gen", *n)? as f64))],
+      class: None,
+    })),
+    Some(a) => CalcitErr::err_str(format!("rand-gen expected a number as seed, got: {a}")),
+    None => CalcitErr::err_str("rand-gen expected a seed, got nothing"),
+  }
+}
+
+/// `rand` gives a number in `[0, 1)`, `rand n` in `[0, n)`
+pub fn rand(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let (generator, args) = split_generator(xs);
+  match args.first() {
+    None => Ok(Calcit::Number(next_float("rand", generator)?)),
+    Some(Calcit::Number(n)) => Ok(Calcit::Number(next_float("rand", generator)? * n)),
+    Some(a) => CalcitErr::err_str(format!("rand expected a number, got: {a}")),
+  }
+}
+
+/// `rand-int n` gives an integer in `[0, n)`
+pub fn rand_int(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let (generator, args) = split_generator(xs);
+  match args.first() {
+    Some(Calcit::Number(n)) => Ok(Calcit::Number((next_float("rand-int", generator)? * n).floor())),
+    Some(a) => CalcitErr::err_str(format!("rand-int expected a number, got: {a}")),
+    None => CalcitErr::err_str("rand-int expected a number, got nothing"),
+  }
+}
+
+/// random item from a list, nil for an empty list
+pub fn rand_nth(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let (generator, args) = split_generator(xs);
+  match args.first() {
+    Some(Calcit::List(ys)) if ys.is_empty() => Ok(Calcit::Nil),
+    Some(Calcit::List(ys)) => {
+      let idx = next_index("rand-nth", generator, ys.len())?;
+      Ok(ys[idx].to_owned())
+    }
+    Some(a) => CalcitErr::err_str(format!("rand-nth expected a list, got: {a}")),
+    None => CalcitErr::err_str("rand-nth expected a list, got nothing"),
+  }
+}
+
+/// Fisher-Yates shuffle, swapping from the end of the list
+pub fn shuffle(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let (generator, args) = split_generator(xs);
+  match args.first() {
+    Some(Calcit::List(ys)) => {
+      let mut ys = ys.to_vec();
+      for i in (1..ys.len()).rev() {
+        let j = next_index("shuffle", generator, i + 1)?;
+        ys.swap(i, j);
+      }
+      Ok(Calcit::from(ys))
+    }
+    Some(a) => CalcitErr::err_str(format!("shuffle expected a list, got: {a}")),
+    None => CalcitErr::err_str("shuffle expected a list, got nothing"),
+  }
+}
@@ -3,49 +3,148 @@
 //! - defined with `atom`, which is barely a piece of local mutable state
 
 use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::sync::atomic::AtomicUsize;
 use std::sync::{Arc, LazyLock, Mutex};
 
-use cirru_edn::EdnTag;
+use cirru_edn::{Edn, EdnMapView, EdnTag};
 
-use crate::calcit::{Calcit, CalcitErr, CalcitImport, CalcitList, CalcitScope};
-use crate::{call_stack::CallStackList, runner};
+use crate::calcit::{Calcit, CalcitArgLabel, CalcitErr, CalcitFn, CalcitFnArgs, CalcitImport, CalcitList, CalcitScope};
+use crate::data::edn::{calcit_to_edn, edn_to_calcit};
+use crate::{builtins, call_stack::CallStackList, runner};
 
-pub(crate) type ValueAndListeners = (Calcit, HashMap<EdnTag, Calcit>);
+/// value of a ref, with watchers kept in the order they were added
+#[derive(Debug)]
+pub struct ValueAndListeners {
+  pub value: Calcit,
+  pub listeners: Vec<(EdnTag, Calcit)>,
+  /// called with `new prev` before writing, a falsy result rejects the change
+  pub validator: Option<Calcit>,
+  /// increased on every write, to detect changes from other threads during `&atom:swap!`
+  pub version: usize,
+}
+
+impl ValueAndListeners {
+  pub fn new(value: Calcit) -> Self {
+    ValueAndListeners {
+      value,
+      listeners: vec![],
+      validator: None,
+      version: 0,
+    }
+  }
+}
 
 type RefListeners = HashMap<Arc<str>, Arc<Mutex<ValueAndListeners>>>;
 
+/// persisting may happen from Ctrl-C handler while exiting, writes are done one at a time
+static PERSISTING: Mutex<()> = Mutex::new(());
+
 static REFS_DICT: LazyLock<Mutex<RefListeners>> = LazyLock::new(|| Mutex::new(HashMap::new()));
 
-fn modify_ref(locked_pair: Arc<Mutex<ValueAndListeners>>, v: Calcit, call_stack: &CallStackList) -> Result<(), CalcitErr> {
-  let (listeners, prev) = {
-    let mut pair = locked_pair.lock().expect("read ref");
-    let prev = pair.0.to_owned();
+/// listeners taking 3 arguments receive a map of `:key`, `:path` and `:op` after `new prev`
+fn takes_change_info(info: &CalcitFn) -> bool {
+  match &*info.args {
+    CalcitFnArgs::Args(xs) => xs.len() == 3,
+    CalcitFnArgs::MarkedArgs(xs) => xs.len() == 3 && xs.iter().all(|x| matches!(x, CalcitArgLabel::Idx(_))),
+  }
+}
+
+fn change_info(key: &EdnTag, path: &str, op: &str) -> Calcit {
+  let mut data: rpds::HashTrieMapSync<Calcit, Calcit> = rpds::HashTrieMap::new_sync();
+  data.insert_mut(Calcit::tag("key"), Calcit::Tag(key.to_owned()));
+  data.insert_mut(Calcit::tag("path"), Calcit::new_str(path));
+  data.insert_mut(Calcit::tag("op"), Calcit::tag(op));
+  Calcit::Map(data)
+}
+
+fn run_validator(
+  op: &str,
+  path: &str,
+  validator: &Calcit,
+  v: &Calcit,
+  prev: &Calcit,
+  call_stack: &CallStackList,
+) -> Result<(), CalcitErr> {
+  match validator {
+    Calcit::Fn { info, .. } => match runner::run_fn(&[v.to_owned(), prev.to_owned()], info, call_stack)? {
+      Calcit::Nil | Calcit::Bool(false) => Err(CalcitErr::use_msg_stack(
+        format!("{op}! rejected by validator of {path}, got: {v}"),
+        call_stack,
+      )),
+      _ => Ok(()),
+    },
+    a => Err(CalcitErr::use_msg_stack_location(
+      format!("expected fn as validator of {path}, got: {a}"),
+      call_stack,
+      a.get_location(),
+    )),
+  }
+}
+
+/// computes next value from current one and writes it when no other writes happened in between,
+/// otherwise computes again, so `next` and the validator may run more than once under contention.
+/// this is deliberate: holding the lock while running them would deadlock when they read the ref.
+/// validator runs before writing, all listeners run in order after writing,
+/// and the first failure among listeners is returned afterwards. returns the value being written
+fn modify_ref(
+  op: &str,
+  path: &str,
+  locked_pair: &Mutex<ValueAndListeners>,
+  next: impl Fn(&Calcit) -> Result<Calcit, CalcitErr>,
+  call_stack: &CallStackList,
+) -> Result<Calcit, CalcitErr> {
+  loop {
+    // lock is not held when running functions, they may read the ref
+    let (prev, version, validator) = {
+      let pair = locked_pair.lock().expect("read ref");
+      (pair.value.to_owned(), pair.version, pair.validator.to_owned())
+    };
+    let v = next(&prev)?;
     if prev == v {
       // not need to modify
-      return Ok(());
+      return Ok(v);
     }
-    let listeners = pair.1.to_owned();
-    v.clone_into(&mut pair.0);
-
-    (listeners, prev)
-  };
-
-  for f in listeners.values() {
-    match f {
-      Calcit::Fn { info, .. } => {
-        runner::run_fn(&[v.to_owned(), prev.to_owned()], info, call_stack)?;
+    if let Some(f) = &validator {
+      run_validator(op, path, f, &v, &prev, call_stack)?;
+    }
+    let listeners = {
+      let mut pair = locked_pair.lock().expect("write ref");
+      if pair.version != version {
+        // modified by another thread, try again with the latest value
+        continue;
       }
-      a => {
-        return Err(CalcitErr::use_msg_stack_location(
-          format!("expected fn to trigger after `reset!`, got: {a}"),
+      v.clone_into(&mut pair.value);
+      pair.version += 1;
+      pair.listeners.to_owned()
+    };
+
+    let mut failure: Option<CalcitErr> = None;
+    for (k, f) in &listeners {
+      let result = match f {
+        Calcit::Fn { info, .. } => {
+          if takes_change_info(info) {
+            runner::run_fn(&[v.to_owned(), prev.to_owned(), change_info(k, path, op)], info, call_stack)
+          } else {
+            runner::run_fn(&[v.to_owned(), prev.to_owned()], info, call_stack)
+          }
+        }
+        a => Err(CalcitErr::use_msg_stack_location(
+          format!("expected fn to trigger after `{op}!`, got: {a}"),
           call_stack,
           a.get_location(),
-        ))
+        )),
+      };
+      if let Err(e) = result {
+        failure.get_or_insert(e);
       }
     }
+    return match failure {
+      Some(e) => Err(e),
+      None => Ok(v),
+    };
   }
-  Ok(())
 }
 
 /// syntax to prevent expr re-evaluating
@@ -70,7 +169,7 @@ pub fn defatom(expr: &CalcitList, scope: &CalcitScope, file_ns: &str, call_stack
         Some(v) => Ok(Calcit::Ref(path_info, v.to_owned())),
         None => {
           let v = runner::evaluate_expr(code, scope, file_ns, call_stack)?;
-          let pair_value = Arc::new(Mutex::new((v, HashMap::new())));
+          let pair_value = Arc::new(Mutex::new(ValueAndListeners::new(v)));
           let mut dict = REFS_DICT.lock().expect("read refs");
           dict.insert(path_info.to_owned(), pair_value.to_owned());
           Ok(Calcit::Ref(path_info, pair_value))
@@ -97,7 +196,7 @@ pub fn defatom(expr: &CalcitList, scope: &CalcitScope, file_ns: &str, call_stack
         Some(v) => Ok(Calcit::Ref(path_info, v.to_owned())),
         None => {
           let v = runner::evaluate_expr(code, scope, file_ns, call_stack)?;
-          let pair_value = Arc::new(Mutex::new((v, HashMap::new())));
+          let pair_value = Arc::new(Mutex::new(ValueAndListeners::new(v)));
           let mut dict = REFS_DICT.lock().expect("read refs");
           dict.insert(path_info.to_owned(), pair_value.to_owned());
           Ok(Calcit::Ref(path_info, pair_value))
@@ -132,7 +231,7 @@ pub fn quick_build_atom(v: Calcit) -> Calcit {
   let path_info: Arc<str> = path.into();
   // println!("atom {:?}", path_info);
 
-  let pair_value = Arc::new(Mutex::new((v, HashMap::new())));
+  let pair_value = Arc::new(Mutex::new(ValueAndListeners::new(v)));
   Calcit::Ref(path_info, pair_value)
 }
 
@@ -142,7 +241,7 @@ pub fn atom_deref(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     Some(Calcit::Ref(_path, locked_pair)) => {
       // println!("deref import {:?}", _path);
       let pair = (**locked_pair).lock().expect("read pair from block");
-      Ok(pair.0.to_owned())
+      Ok(pair.value.to_owned())
     }
     Some(a) => CalcitErr::err_str(format!("deref expected a ref, got: {a}")),
     _ => CalcitErr::err_str("deref expected 1 argument, got nothing"),
@@ -158,9 +257,9 @@ pub fn reset_bang(expr: &CalcitList, scope: &CalcitScope, file_ns: &str, call_st
   let target = runner::evaluate_expr(&expr[0], scope, file_ns, call_stack)?;
   let new_value = runner::evaluate_expr(&expr[1], scope, file_ns, call_stack)?;
   match (target, &new_value) {
-    (Calcit::Ref(_path, locked_pair), v) => {
-      // println!("reset defatom {:?} {}", _path, v);
-      modify_ref(locked_pair, v.to_owned(), call_stack)?;
+    (Calcit::Ref(path, locked_pair), v) => {
+      // println!("reset defatom {:?} {}", path, v);
+      modify_ref("reset", &path, &locked_pair, |_| Ok(v.to_owned()), call_stack)?;
       Ok(Calcit::Nil)
     }
     // if reset! called before deref, we need to trigger the thunk
@@ -168,9 +267,9 @@ pub fn reset_bang(expr: &CalcitList, scope: &CalcitScope, file_ns: &str, call_st
       Calcit::Symbol { .. } | Calcit::Import(CalcitImport { .. }) => {
         let ret = thunk.evaluated(scope, call_stack)?;
         match (ret, &new_value) {
-          (Calcit::Ref(_path, locked_pair), v) => {
-            // println!("reset defatom {:?} {}", _path, v);
-            modify_ref(locked_pair, v.to_owned(), call_stack)?;
+          (Calcit::Ref(path, locked_pair), v) => {
+            // println!("reset defatom {:?} {}", path, v);
+            modify_ref("reset", &path, &locked_pair, |_| Ok(v.to_owned()), call_stack)?;
             Ok(Calcit::Nil)
           }
           (a, _) => Err(CalcitErr::use_msg_stack_location(
@@ -194,10 +293,10 @@ pub fn add_watch(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
   match (xs.first(), xs.get(1), xs.get(2)) {
     (Some(Calcit::Ref(_path, locked_pair)), Some(Calcit::Tag(k)), Some(f @ Calcit::Fn { .. })) => {
       let mut pair = locked_pair.lock().expect("trying to modify locked pair");
-      match pair.1.get(k) {
+      match pair.listeners.iter().find(|(key, _)| key == k) {
         Some(_) => CalcitErr::err_str(format!("add-watch failed, listener with key `{k}` existed")),
         None => {
-          pair.1.insert(k.to_owned(), f.to_owned());
+          pair.listeners.push((k.to_owned(), f.to_owned()));
           Ok(Calcit::Nil)
         }
       }
@@ -216,10 +315,10 @@ pub fn remove_watch(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     (Some(Calcit::Ref(_path, locked_pair)), Some(Calcit::Tag(k))) => {
       let mut pair = locked_pair.lock().expect("trying to modify locked pair");
 
-      match pair.1.get(k) {
+      match pair.listeners.iter().position(|(key, _)| key == k) {
         None => CalcitErr::err_str(format!("remove-watch failed, listener with key `{k}` missing")),
-        Some(_) => {
-          pair.1.remove(k);
+        Some(idx) => {
+          pair.listeners.remove(idx);
           Ok(Calcit::Nil)
         }
       }
@@ -228,3 +327,194 @@ pub fn remove_watch(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     (a, b) => CalcitErr::err_str(format!("remove-watch expected 2 arguments, got: {a:?} {b:?}")),
   }
 }
+
+/// `set-validator! ref f`, `f` is called with `new prev` before each change and rejects it by returning false or nil.
+/// `set-validator! ref nil` removes the validator
+pub fn set_validator(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Ref(_path, locked_pair)), Some(f @ Calcit::Fn { .. })) => {
+      locked_pair.lock().expect("trying to modify locked pair").validator = Some(f.to_owned());
+      Ok(Calcit::Nil)
+    }
+    (Some(Calcit::Ref(_path, locked_pair)), Some(Calcit::Nil)) => {
+      locked_pair.lock().expect("trying to modify locked pair").validator = None;
+      Ok(Calcit::Nil)
+    }
+    (Some(Calcit::Ref(..)), Some(a)) => CalcitErr::err_str(format!("set-validator! expected a fn or nil, got: {a}")),
+    (Some(a), Some(_)) => CalcitErr::err_str(format!("set-validator! expected a ref, got: {a}")),
+    (_, _) => CalcitErr::err_nodes("set-validator! expected 2 arguments, got:", xs),
+  }
+}
+
+/// `&atom:swap! ref f & args`, used by `swap!`. `f` may run more than once when other threads write to the ref,
+/// so that no updates are lost. returns the new value
+pub fn atom_swap(xs: &[Calcit], call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Ref(path, locked_pair)), Some(f)) => {
+      let next = |prev: &Calcit| {
+        let mut args = Vec::with_capacity(xs.len() - 1);
+        args.push(prev.to_owned());
+        args.extend_from_slice(&xs[2..]);
+        match f {
+          Calcit::Fn { info, .. } => runner::run_fn(&args, info, call_stack),
+          Calcit::Proc(proc) => builtins::handle_proc(*proc, &args, call_stack),
+          a => CalcitErr::err_str(format!("&atom:swap! expected a function, got: {a}")),
+        }
+      };
+      modify_ref("swap", path, locked_pair, next, call_stack)
+    }
+    (Some(a), Some(_)) => CalcitErr::err_str(format!("&atom:swap! expected a ref, got: {a}")),
+    (_, _) => CalcitErr::err_nodes("&atom:swap! expected a ref and a function, got:", xs),
+  }
+}
+
+/// writes values of `defatom`s into a Cirru EDN file, keyed by `ns/def`, all of them when `selected` is `None`.
+/// values that EDN does not support are skipped with a warning. returns number of saved refs.
+/// content goes to a temporary file first, so an interrupted write does not leave a broken file
+pub fn persist_refs(path: &Path, selected: Option<&[Arc<str>]>) -> Result<usize, String> {
+  let refs: Vec<(Arc<str>, Arc<Mutex<ValueAndListeners>>)> = {
+    let dict = REFS_DICT.lock().expect("read refs");
+    match selected {
+      Some(names) => names
+        .iter()
+        .map(|name| match dict.get(name) {
+          Some(pair) => Ok((name.to_owned(), pair.to_owned())),
+          None => Err(format!("unknown defatom to persist: {name}")),
+        })
+        .collect::<Result<_, _>>()?,
+      None => dict.iter().map(|(k, v)| (k.to_owned(), v.to_owned())).collect(),
+    }
+  };
+  let mut data = EdnMapView::default();
+  for (name, pair) in &refs {
+    let value = pair.lock().expect("read ref").value.to_owned();
+    match calcit_to_edn(&value) {
+      Ok(v) => data.insert(Edn::str(&**name), v),
+      Err(e) => eprintln!("[Warn] skipped persisting {name}, {e}"),
+    }
+  }
+  let size = data.0.len();
+  let content = cirru_edn::format(&Edn::Map(data), true)?;
+  let file_name = path
+    .file_name()
+    .ok_or_else(|| format!("invalid path to persist: {}", path.display()))?;
+  let tmp_path = path.with_file_name(format!(".{}.tmp", file_name.to_string_lossy()));
+  let _persisting = PERSISTING.lock().expect("lock persisting");
+  fs::write(&tmp_path, content).map_err(|e| format!("failed to write {}: {e}", tmp_path.display()))?;
+  fs::rename(&tmp_path, path).map_err(|e| format!("failed to write {}: {e}", path.display()))?;
+  Ok(size)
+}
+
+/// loads values written by `persist_refs`, so that `defatom` takes them instead of evaluating initial values.
+/// refs already defined are updated without calling watchers. returns number of loaded refs
+pub fn load_persisted_refs(path: &Path) -> Result<usize, String> {
+  let content = fs::read_to_string(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+  let pairs: Vec<(Edn, Edn)> = match cirru_edn::parse(&content)? {
+    Edn::Map(EdnMapView(xs)) => xs.into_iter().collect(),
+    a => return Err(format!("expected a map of persisted refs in {}, got: {a}", path.display())),
+  };
+  let mut dict = REFS_DICT.lock().expect("read refs");
+  for (k, v) in &pairs {
+    let name: Arc<str> = match k {
+      Edn::Str(s) => s.to_owned(),
+      a => return Err(format!("expected `ns/def` in string for persisted ref, got: {a}")),
+    };
+    let value = edn_to_calcit(v, &Calcit::Nil);
+    match dict.get(&name) {
+      Some(pair) => {
+        let mut pair = pair.lock().expect("write ref");
+        pair.value = value;
+        pair.version += 1;
+      }
+      None => {
+        dict.insert(name, Arc::new(Mutex::new(ValueAndListeners::new(value))));
+      }
+    }
+  }
+  Ok(pairs.len())
+}
+
+/// `persist-atoms! path` or `persist-atoms! path names`, names of defatoms are like `|app.main/*store`
+pub fn persist_atoms(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  let selected: Option<Vec<Arc<str>>> = match xs.get(1) {
+    None | Some(Calcit::Nil) => None,
+    Some(Calcit::List(names)) => {
+      let mut ys = vec![];
+      for name in names.iter() {
+        match name {
+          Calcit::Str(s) => ys.push(s.to_owned()),
+          a => return CalcitErr::err_str(format!("persist-atoms! expected names in strings, got: {a}")),
+        }
+      }
+      Some(ys)
+    }
+    Some(a) => return CalcitErr::err_str(format!("persist-atoms! expected a list of names, got: {a}")),
+  };
+  match xs.first() {
+    Some(Calcit::Str(path)) => match persist_refs(Path::new(&**path), selected.as_deref()) {
+      Ok(size) => Ok(Calcit::Number(size as f64)),
+      Err(e) => CalcitErr::err_str(format!("persist-atoms! failed, {e}")),
+    },
+    Some(a) => CalcitErr::err_str(format!("persist-atoms! expected a file path, got: {a}")),
+    None => CalcitErr::err_str("persist-atoms! expected a file path, got nothing"),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::calcit::CalcitSymbolInfo;
+
+  /// evaluates `defatom {name} {init}` in namespace `test.persist`
+  fn defatom_in_test(name: &str, init: f64) -> Result<Calcit, CalcitErr> {
+    let symbol = Calcit::Symbol {
+      sym: Arc::from(name),
+      info: Arc::new(CalcitSymbolInfo {
+        at_ns: Arc::from("test.persist"),
+        at_def: Arc::from(name),
+      }),
+      location: None,
+    };
+    let expr = CalcitList::from(&[symbol, Calcit::Number(init)][..]);
+    defatom(&expr, &CalcitScope::default(), "test.persist", &CallStackList::default())
+  }
+
+  fn read_ref(r: &Calcit) -> (Calcit, usize) {
+    match r {
+      Calcit::Ref(_, pair) => {
+        let pair = pair.lock().expect("read ref");
+        (pair.value.to_owned(), pair.version)
+      }
+      a => panic!("expected a ref, got: {a}"),
+    }
+  }
+
+  #[test]
+  fn defatom_takes_persisted_values() -> Result<(), String> {
+    let dir = std::env::temp_dir().join(format!("calcit-persist-{}", std::process::id()));
+    fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    let path = dir.join("atoms.cirru");
+
+    let defined = defatom_in_test("*defined", 1.0).map_err(|e| e.to_string())?;
+    assert_eq!(read_ref(&defined), (Calcit::Number(1.0), 0));
+    fs::write(&path, "{} (|test.persist/*defined 3) (|test.persist/*loaded $ [] 4 5)").map_err(|e| e.to_string())?;
+    assert_eq!(load_persisted_refs(&path)?, 2);
+
+    // an existing ref is updated in place
+    assert_eq!(read_ref(&defined), (Calcit::Number(3.0), 1));
+    let again = defatom_in_test("*defined", 1.0).map_err(|e| e.to_string())?;
+    assert_eq!(read_ref(&again).0, Calcit::Number(3.0));
+    // initial value is not evaluated for a loaded ref
+    let loaded = defatom_in_test("*loaded", 0.0).map_err(|e| e.to_string())?;
+    assert_eq!(
+      read_ref(&loaded).0,
+      Calcit::from(CalcitList::from(&[Calcit::Number(4.0), Calcit::Number(5.0)][..]))
+    );
+
+    let selected = [Arc::from("test.persist/*loaded")];
+    assert_eq!(persist_refs(&path, Some(&selected))?, 1);
+    let content = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+    assert_eq!(cirru_edn::parse(&content)?.view_map()?.0.len(), 1);
+    fs::remove_dir_all(dir).map_err(|e| e.to_string())
+  }
+}
@@ -0,0 +1,197 @@
+//! regular expressions, patterns are compiled once and cached.
+//! replacements follow syntax of JavaScript, so that code behaves the same after `cr js`
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use regex::Regex;
+
+use crate::calcit::{Calcit, CalcitErr};
+
+/// patterns are usually literals in code, cache is cleared when it grows too large
+const CACHE_LIMIT: usize = 256;
+
+static REGEX_CACHE: LazyLock<Mutex<HashMap<String, Regex>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn compile(pattern: &str) -> Result<Regex, CalcitErr> {
+  let mut cache = REGEX_CACHE.lock().expect("open regex cache");
+  if let Some(re) = cache.get(pattern) {
+    return Ok(re.to_owned());
+  }
+  let re = Regex::new(&js_classes(pattern)).map_err(|e| CalcitErr::use_str(format!("invalid regex {pattern:?}: {e}")))?;
+  if cache.len() >= CACHE_LIMIT {
+    cache.clear();
+  }
+  cache.insert(pattern.to_owned(), re.to_owned());
+  Ok(re)
+}
+
+/// whitespace of `\s` in JavaScript, slightly different from Unicode `White_Space` used by Rust
+const JS_SPACES: &str = r"\t\n\x0B\x0C\r \u{A0}\u{1680}\u{2000}-\u{200A}\u{2028}\u{2029}\u{202F}\u{205F}\u{3000}\u{FEFF}";
+
+/// `\d`, `\w` and `\b` are ASCII in JavaScript regexes while Unicode-aware in Rust, and `\s` differs slightly,
+/// so they are rewritten. classes are written as nested classes, which also works inside brackets
+fn js_classes(pattern: &str) -> String {
+  let mut ret = String::with_capacity(pattern.len());
+  let mut chars = pattern.chars();
+  let mut in_class = false;
+  while let Some(c) = chars.next() {
+    match c {
+      '\\' => match chars.next() {
+        Some('d') => ret.push_str("[0-9]"),
+        Some('D') => ret.push_str("[^0-9]"),
+        Some('w') => ret.push_str("[0-9A-Za-z_]"),
+        Some('W') => ret.push_str("[^0-9A-Za-z_]"),
+        Some('s') => ret.push_str(&format!("[{JS_SPACES}]")),
+        Some('S') => ret.push_str(&format!("[^{JS_SPACES}]")),
+        Some('b') if !in_class => ret.push_str("(?-u:\\b)"),
+        Some('B') if !in_class => ret.push_str("(?-u:\\B)"),
+        Some(next) => {
+          ret.push(c);
+          ret.push(next);
+        }
+        None => ret.push(c),
+      },
+      '[' => {
+        in_class = true;
+        ret.push(c);
+      }
+      ']' => {
+        in_class = false;
+        ret.push(c);
+      }
+      _ => ret.push(c),
+    }
+  }
+  ret
+}
+
+/// whole string matches the pattern
+pub fn re_matches(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(s)), Some(Calcit::Str(pattern))) => {
+      let re = compile(&format!("^(?:{pattern})$"))?;
+      Ok(Calcit::Bool(re.is_match(s)))
+    }
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("re-matches expected 2 strings, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("re-matches expected 2 arguments, got:", xs),
+  }
+}
+
+/// first match in a list of whole match and capture groups, unmatched groups are nil
+pub fn re_find(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(s)), Some(Calcit::Str(pattern))) => match compile(pattern)?.captures(s) {
+      Some(caps) => Ok(Calcit::from(
+        caps
+          .iter()
+          .map(|group| match group {
+            Some(m) => Calcit::new_str(m.as_str()),
+            None => Calcit::Nil,
+          })
+          .collect::<Vec<_>>(),
+      )),
+      None => Ok(Calcit::Nil),
+    },
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("re-find expected 2 strings, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("re-find expected 2 arguments, got:", xs),
+  }
+}
+
+/// all non-overlapping matches
+pub fn re_find_all(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(s)), Some(Calcit::Str(pattern))) => Ok(Calcit::from(
+      compile(pattern)?
+        .find_iter(s)
+        .map(|m| Calcit::new_str(m.as_str()))
+        .collect::<Vec<_>>(),
+    )),
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("re-find-all expected 2 strings, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("re-find-all expected 2 arguments, got:", xs),
+  }
+}
+
+/// replaces all matches, `$1` `$<name>` `$&` and `$$` in replacement are expanded
+pub fn re_replace(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1), xs.get(2)) {
+    (Some(Calcit::Str(s)), Some(Calcit::Str(pattern)), Some(Calcit::Str(replacement))) => {
+      let re = compile(pattern)?;
+      let expanded = to_rust_replacement(replacement, re.captures_len());
+      Ok(Calcit::new_str(re.replace_all(s, expanded.as_str())))
+    }
+    (Some(a), Some(b), Some(c)) => CalcitErr::err_str(format!("re-replace expected 3 strings, got: {a} {b} {c}")),
+    (_, _, _) => CalcitErr::err_nodes("re-replace expected 3 arguments, got:", xs),
+  }
+}
+
+/// splits by matches, capture groups are not included in result
+pub fn re_split(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
+  match (xs.first(), xs.get(1)) {
+    (Some(Calcit::Str(s)), Some(Calcit::Str(pattern))) => {
+      Ok(Calcit::from(compile(pattern)?.split(s).map(Calcit::new_str).collect::<Vec<_>>()))
+    }
+    (Some(a), Some(b)) => CalcitErr::err_str(format!("re-split expected 2 strings, got: {a} {b}")),
+    (_, _) => CalcitErr::err_nodes("re-split expected 2 arguments, got:", xs),
+  }
+}
+
+/// translates replacement in JavaScript syntax into syntax of `regex` crate.
+/// like JavaScript, `$12` refers to group 12 only when it exists, otherwise it's group 1 followed by `2`
+fn to_rust_replacement(replacement: &str, groups: usize) -> String {
+  let chars: Vec<char> = replacement.chars().collect();
+  let mut result = String::with_capacity(replacement.len());
+  let mut idx = 0;
+  while idx < chars.len() {
+    let c = chars[idx];
+    if c != '$' {
+      result.push(c);
+      idx += 1;
+      continue;
+    }
+    match chars.get(idx + 1) {
+      Some('$') => {
+        result.push_str("$$");
+        idx += 2;
+      }
+      Some('&') => {
+        result.push_str("${0}");
+        idx += 2;
+      }
+      Some('<') => match chars[idx + 2..].iter().position(|x| *x == '>') {
+        Some(size) => {
+          let name: String = chars[idx + 2..idx + 2 + size].iter().collect();
+          result.push_str(&format!("${{{name}}}"));
+          idx += size + 3;
+        }
+        None => {
+          result.push_str("$$");
+          idx += 1;
+        }
+      },
+      Some(d) if d.is_ascii_digit() => {
+        let one = d.to_digit(10).unwrap_or(0) as usize;
+        let two = chars.get(idx + 2).and_then(|x| x.to_digit(10)).map(|x| one * 10 + x as usize);
+        match two {
+          Some(n) if n > 0 && n < groups => {
+            result.push_str(&format!("${{{n}}}"));
+            idx += 3;
+          }
+          _ if one > 0 && one < groups => {
+            result.push_str(&format!("${{{one}}}"));
+            idx += 2;
+          }
+          _ => {
+            result.push_str("$$");
+            idx += 1;
+          }
+        }
+      }
+      _ => {
+        result.push_str("$$");
+        idx += 1;
+      }
+    }
+  }
+  result
+}
@@ -53,6 +53,7 @@ pub fn turn_string(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
     Some(Calcit::Tag(s)) => Ok(Calcit::Str(s.arc_str())),
     Some(Calcit::Symbol { sym, .. }) => Ok(Calcit::Str(sym.to_owned())),
     Some(Calcit::Number(n)) => Ok(Calcit::Str(n.to_string().into())),
+    Some(Calcit::BigInt(n)) => Ok(Calcit::Str(n.to_string().into())),
     Some(a) => CalcitErr::err_str(format!("turn-string cannot turn this to string: {a}")),
     None => CalcitErr::err_str("turn-string expected 1 argument, got nothing"),
   }
@@ -81,6 +82,11 @@ pub fn format_number(xs: &[Calcit]) -> Result<Calcit, CalcitErr> {
       let size = f64_to_usize(*x)?;
       Ok(Calcit::Str(format!("{n:.size$}").into()))
     }
+    // no fractional part in bigints, zeros are padded
+    (Some(Calcit::BigInt(n)), Some(Calcit::Number(x))) => match f64_to_usize(*x)? {
+      0 => Ok(Calcit::Str(n.to_string().into())),
+      size => Ok(Calcit::Str(format!("{n}.{}", "0".repeat(size)).into())),
+    },
     (Some(a), Some(b)) => CalcitErr::err_str(format!("&number:format expected numbers, got: {a} {b}")),
     (_, _) => CalcitErr::err_str("&number:format expected 2 arguments"),
   }
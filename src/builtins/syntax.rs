@@ -403,7 +403,7 @@ pub fn call_try(expr: &CalcitList, scope: &CalcitScope, file_ns: &str, call_stac
       Ok(v) => Ok(v.to_owned()),
       Err(failure) => {
         let f = runner::evaluate_expr(&expr[1], scope, file_ns, call_stack)?;
-        let err_data = Calcit::Str(failure.msg.to_owned().into());
+        let err_data = failure.to_value();
         match f {
           Calcit::Fn { info, .. } => runner::run_fn(&[err_data], &info, call_stack),
           Calcit::Proc(proc) => builtins::handle_proc(proc, &[err_data], call_stack),
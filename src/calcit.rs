@@ -2,6 +2,7 @@ mod fns;
 mod list;
 mod local;
 mod proc_name;
+mod promise;
 mod record;
 mod symbol;
 mod syntax_name;
@@ -23,11 +24,14 @@ use cirru_edn::EdnAnyRef;
 use cirru_edn::{Edn, EdnTag};
 use cirru_parser::Cirru;
 use im_ternary_tree::TernaryTreeList;
+use num_bigint::BigInt;
+use num_traits::FromPrimitive;
 
 pub use fns::{CalcitArgLabel, CalcitFn, CalcitFnArgs, CalcitMacro, CalcitScope};
 pub use list::CalcitList;
 pub use local::CalcitLocal;
 pub use proc_name::CalcitProc;
+pub use promise::{CalcitPromise, PromiseReaction, PromiseState};
 pub use record::CalcitRecord;
 pub use symbol::{CalcitImport, CalcitSymbolInfo, ImportInfo};
 pub use syntax_name::CalcitSyntax;
@@ -46,6 +50,8 @@ pub enum Calcit {
   Nil,
   Bool(bool),
   Number(f64),
+  /// integer of arbitrary precision, written like `12n` as in JavaScript
+  BigInt(BigInt),
   Symbol {
     sym: Arc<str>,
     info: Arc<CalcitSymbolInfo>,
@@ -66,6 +72,8 @@ pub enum Calcit {
   Thunk(CalcitThunk), // code, value
   /// atom, holding a path to its state, data inside remains during hot code swapping
   Ref(Arc<str>, Arc<Mutex<ValueAndListeners>>),
+  /// settled later by the event loop, awaited with `await`
+  Promise(Arc<CalcitPromise>),
   /// more tagged union type, more like an internal structure
   Tuple(CalcitTuple),
   /// binary data, to be used by FFIs
@@ -112,6 +120,7 @@ impl fmt::Display for Calcit {
       Calcit::Nil => f.write_str("nil"),
       Calcit::Bool(v) => f.write_str(&format!("{v}")),
       Calcit::Number(n) => f.write_str(&format!("{n}")),
+      Calcit::BigInt(n) => f.write_str(&format!("{n}n")),
       Calcit::Symbol { sym, .. } => f.write_str(&format!("'{sym}")),
       Calcit::Local(CalcitLocal { sym, .. }) => f.write_str(&format!("'{sym}")),
       Calcit::Import(CalcitImport { ns, def, .. }) => f.write_str(&format!("{ns}/{def}")),
@@ -139,6 +148,7 @@ impl fmt::Display for Calcit {
       },
       Calcit::CirruQuote(code) => f.write_str(&format!("(&cirru-quote {code})")),
       Calcit::Ref(name, _locked_pair) => f.write_str(&format!("(&ref {name} ...)")),
+      Calcit::Promise(p) => f.write_str(&format!("(&promise {} ...)", p.id)),
       Calcit::Tuple(CalcitTuple { tag, extra, class }) => {
         if let Some(record) = class {
           f.write_str("(%:: ")?;
@@ -343,6 +353,10 @@ impl Hash for Calcit {
         // TODO https://stackoverflow.com/q/39638363/883571
         (*n as usize).hash(_state)
       }
+      Calcit::BigInt(n) => {
+        "bigint:".hash(_state);
+        n.hash(_state)
+      }
       Calcit::Symbol { sym, .. } => {
         "symbol:".hash(_state);
         sym.hash(_state);
@@ -377,6 +391,10 @@ impl Hash for Calcit {
         "ref:".hash(_state);
         name.hash(_state);
       }
+      Calcit::Promise(p) => {
+        "promise:".hash(_state);
+        p.id.hash(_state);
+      }
       Calcit::Tuple(CalcitTuple { tag, extra, .. }) => {
         "tuple:".hash(_state);
         tag.hash(_state);
@@ -478,9 +496,16 @@ impl Ord for Calcit {
           Equal
         }
       }
+      // compared by value, number goes first when values are equal
+      (Calcit::Number(a), Calcit::BigInt(b)) => cmp_bigint_f64(b, *a).reverse().then(Less),
+      (Calcit::BigInt(a), Calcit::Number(b)) => cmp_bigint_f64(a, *b).then(Greater),
       (Calcit::Number(_), _) => Less,
       (_, Calcit::Number(_)) => Greater,
 
+      (Calcit::BigInt(a), Calcit::BigInt(b)) => a.cmp(b),
+      (Calcit::BigInt(_), _) => Less,
+      (_, Calcit::BigInt(_)) => Greater,
+
       (Calcit::Symbol { sym: a, .. }, Calcit::Symbol { sym: b, .. }) => a.cmp(b),
       (Calcit::Symbol { .. }, _) => Less,
       (_, Calcit::Symbol { .. }) => Greater,
@@ -519,6 +544,10 @@ impl Ord for Calcit {
       (Calcit::Ref(_, _), _) => Less,
       (_, Calcit::Ref(_, _)) => Greater,
 
+      (Calcit::Promise(a), Calcit::Promise(b)) => a.id.cmp(&b.id),
+      (Calcit::Promise(_), _) => Less,
+      (_, Calcit::Promise(_)) => Greater,
+
       (
         Calcit::Tuple(CalcitTuple {
           tag: a0, extra: extra0, ..
@@ -603,6 +632,24 @@ impl Ord for Calcit {
   }
 }
 
+/// compares values of a bigint and a number, NaN is treated as equal like in `Ord` of numbers
+pub fn cmp_bigint_f64(a: &BigInt, b: f64) -> Ordering {
+  if b.is_nan() {
+    Equal
+  } else if b == f64::INFINITY {
+    Less
+  } else if b == f64::NEG_INFINITY {
+    Greater
+  } else {
+    let floor = BigInt::from_f64(b.floor()).expect("finite float to bigint");
+    match a.cmp(&floor) {
+      // `a` is an integer, so it's smaller when `b` has a fractional part
+      Equal if b > b.floor() => Less,
+      o => o,
+    }
+  }
+}
+
 impl PartialOrd for Calcit {
   fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
     Some(self.cmp(other))
@@ -617,6 +664,7 @@ impl PartialEq for Calcit {
       (Calcit::Nil, Calcit::Nil) => true,
       (Calcit::Bool(a), Calcit::Bool(b)) => a == b,
       (Calcit::Number(a), Calcit::Number(b)) => a == b,
+      (Calcit::BigInt(a), Calcit::BigInt(b)) => a == b,
       (Calcit::Symbol { sym: a, .. }, Calcit::Symbol { sym: b, .. }) => a == b,
       (Calcit::Local(CalcitLocal { sym: a, .. }), Calcit::Local(CalcitLocal { sym: b, .. })) => a == b,
 
@@ -632,6 +680,7 @@ impl PartialEq for Calcit {
       (Calcit::Str(a), Calcit::Str(b)) => a == b,
       (Calcit::Thunk(a), Calcit::Thunk(b)) => a == b,
       (Calcit::Ref(a, _), Calcit::Ref(b, _)) => a == b,
+      (Calcit::Promise(a), Calcit::Promise(b)) => a.id == b.id,
       (Calcit::Tuple(a), Calcit::Tuple(b)) => a == b,
       (Calcit::Buffer(b), Calcit::Buffer(d)) => b == d,
       (Calcit::CirruQuote(b), Calcit::CirruQuote(d)) => b == d,
@@ -673,6 +722,8 @@ pub const CORE_NS: &str = "calcit.core";
 pub const BUILTIN_CLASSES_ENTRY: &str = "&init-builtin-classes!";
 pub const GEN_NS: &str = "calcit.gen";
 pub const GENERATED_DEF: &str = "gen%";
+/// name of the record passed to handler of `try`
+pub const ERROR_RECORD: &str = "calcit-error";
 
 impl Calcit {
   /// data converting, not displaying
@@ -680,6 +731,7 @@ impl Calcit {
     match self {
       Calcit::Nil => String::from(""),
       Calcit::Str(s) => (**s).to_owned(),
+      Calcit::BigInt(n) => n.to_string(),
       _ => format!("{self}"),
     }
   }
@@ -714,6 +766,27 @@ impl Calcit {
     }
   }
 
+  /// lists carry no locations, so it's derived from symbols inside, skipping code from core or generated.
+  /// a child is only trusted when its coord ends with its index, which is not the case for code moved by macros
+  pub fn expr_location(&self) -> Option<NodeLocation> {
+    match self {
+      Calcit::List(xs) => xs.iter().enumerate().find_map(|(idx, x)| {
+        let l = match x {
+          Calcit::List(_) => x.expr_location()?,
+          _ => x.get_location().filter(|l| &*l.ns != CORE_NS && &*l.def != GENERATED_DEF)?,
+        };
+        match l.coord.split_last() {
+          // whole def form is not an expression
+          Some((last, parent)) if *last as usize == idx && !parent.is_empty() => {
+            Some(NodeLocation::new(l.ns, l.def, Arc::new(parent.to_vec())))
+          }
+          _ => None,
+        }
+      }),
+      _ => None,
+    }
+  }
+
   /// during evaluation, maybe skip evaluation since evaluated data is already in the value
   pub fn is_expr_evaluated(&self) -> bool {
     !matches!(
@@ -746,6 +819,8 @@ pub fn gen_core_id() -> Arc<str> {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CalcitErr {
   pub msg: String,
+  /// value passed to `raise`, carried to handler of `try`
+  pub data: Option<Arc<Calcit>>,
   pub warnings: Vec<LocatedWarning>,
   pub location: Option<Arc<NodeLocation>>,
   pub stack: CallStackList,
@@ -767,6 +842,7 @@ impl From<String> for CalcitErr {
   fn from(msg: String) -> Self {
     CalcitErr {
       msg,
+      data: None,
       warnings: vec![],
       stack: CallStackList::default(),
       location: None,
@@ -778,6 +854,7 @@ impl CalcitErr {
   pub fn use_str<T: Into<String>>(msg: T) -> Self {
     CalcitErr {
       msg: msg.into(),
+      data: None,
       warnings: vec![],
       stack: CallStackList::default(),
       location: None,
@@ -786,6 +863,7 @@ impl CalcitErr {
   pub fn err_str<T: Into<String>>(msg: T) -> Result<Calcit, Self> {
     Err(CalcitErr {
       msg: msg.into(),
+      data: None,
       warnings: vec![],
       stack: CallStackList::default(),
       location: None,
@@ -795,6 +873,7 @@ impl CalcitErr {
   pub fn err_nodes<T: Into<String>>(msg: T, nodes: &[Calcit]) -> Result<Calcit, Self> {
     Err(CalcitErr {
       msg: format!("{} {}", msg.into(), CalcitList::from(nodes)),
+      data: None,
       warnings: vec![],
       stack: CallStackList::default(),
       location: None,
@@ -803,6 +882,7 @@ impl CalcitErr {
   pub fn err_str_location<T: Into<String>>(msg: T, location: Option<Arc<NodeLocation>>) -> Result<Calcit, Self> {
     Err(CalcitErr {
       msg: msg.into(),
+      data: None,
       warnings: vec![],
       stack: CallStackList::default(),
       location,
@@ -811,6 +891,7 @@ impl CalcitErr {
   pub fn use_msg_stack<T: Into<String>>(msg: T, stack: &CallStackList) -> Self {
     CalcitErr {
       msg: msg.into(),
+      data: None,
       warnings: vec![],
       stack: stack.to_owned(),
       location: None,
@@ -819,11 +900,59 @@ impl CalcitErr {
   pub fn use_msg_stack_location<T: Into<String>>(msg: T, stack: &CallStackList, location: Option<NodeLocation>) -> Self {
     CalcitErr {
       msg: msg.into(),
+      data: None,
       warnings: vec![],
       stack: stack.to_owned(),
       location: location.map(Arc::new),
     }
   }
+  /// error raised from user code, with data attached
+  pub fn err_data<T: Into<String>>(msg: T, data: Calcit) -> Result<Calcit, Self> {
+    Err(CalcitErr {
+      msg: msg.into(),
+      data: Some(Arc::new(data)),
+      warnings: vec![],
+      stack: CallStackList::default(),
+      location: None,
+    })
+  }
+  /// record passed to handler of `try`, with fields `:data`, `:location`, `:message` and `:stack`.
+  /// location is guessed from call stack when not attached
+  pub fn to_value(&self) -> Calcit {
+    let location = match &self.location {
+      Some(l) => Some((**l).to_owned()),
+      None => self.stack.guess_location(),
+    };
+    let location = match location {
+      Some(l) => crate::data::edn::edn_to_calcit(&Edn::from(l), &Calcit::Nil),
+      None => Calcit::Nil,
+    };
+    let mut stack: Vec<Calcit> = Vec::with_capacity(self.stack.len());
+    for s in &self.stack.0 {
+      stack.push(Calcit::Map(
+        rpds::HashTrieMap::new_sync()
+          .insert(Calcit::tag("def"), Calcit::new_str(format!("{}/{}", s.ns, s.def)))
+          .insert(Calcit::tag("kind"), Calcit::tag(&s.kind.to_string())),
+      ));
+    }
+    Calcit::Record(CalcitRecord {
+      name: EdnTag::from(ERROR_RECORD),
+      // sorted, since fields are looked up with binary search
+      fields: Arc::new(vec![
+        EdnTag::from("data"),
+        EdnTag::from("location"),
+        EdnTag::from("message"),
+        EdnTag::from("stack"),
+      ]),
+      values: Arc::new(vec![
+        self.data.as_deref().map(ToOwned::to_owned).unwrap_or(Calcit::Nil),
+        location,
+        Calcit::new_str(self.msg.as_str()),
+        Calcit::from(stack),
+      ]),
+      class: None,
+    })
+  }
 }
 
 /// location of node in Snapshot
@@ -897,6 +1026,14 @@ impl LocatedWarning {
       println!("{warn}");
     }
   }
+
+  pub fn message(&self) -> &str {
+    &self.0
+  }
+
+  pub fn location(&self) -> &NodeLocation {
+    &self.1
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -103,6 +103,22 @@ impl CalcitScope {
     self.0 = self.0.push(ScopePair { key, value })
   }
 
+  /// variables with names, latest first, shadowed ones are skipped
+  pub fn get_pairs(&self) -> Vec<(String, Calcit)> {
+    let mut keys: Vec<u16> = vec![];
+    let mut pairs: Vec<(String, Calcit)> = vec![];
+    let size = self.0.len();
+    for i in 0..size {
+      if let Some(pair) = self.0.get(size - 1 - i) {
+        if !keys.contains(&pair.key) {
+          keys.push(pair.key);
+          pairs.push((CalcitLocal::read_name(pair.key), pair.value.to_owned()));
+        }
+      }
+    }
+    pairs
+  }
+
   pub fn get_names(&self) -> String {
     let mut vars = String::new();
     for (i, k) in self.0.into_iter().enumerate() {
@@ -1,7 +1,7 @@
-use strum_macros::{AsRefStr, EnumString};
+use strum_macros::{AsRefStr, EnumIter, EnumString};
 
 /// represent builtin functions for performance reasons.
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, EnumString, strum_macros::Display, AsRefStr)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, EnumString, EnumIter, strum_macros::Display, AsRefStr)]
 pub enum CalcitProc {
   // meta
   #[strum(serialize = "type-of")]
@@ -70,6 +70,36 @@ pub enum CalcitProc {
   ReadFile,
   #[strum(serialize = "write-file")]
   WriteFile,
+  #[strum(serialize = "append-file")]
+  AppendFile,
+  #[strum(serialize = "read-file-buffer")]
+  ReadFileBuffer,
+  #[strum(serialize = "write-file-buffer")]
+  WriteFileBuffer,
+  #[strum(serialize = "read-dir")]
+  ReadDir,
+  #[strum(serialize = "walk-dir")]
+  WalkDir,
+  #[strum(serialize = "path-exists?")]
+  PathExists,
+  #[strum(serialize = "path-metadata")]
+  PathMetadata,
+  #[strum(serialize = "create-dir")]
+  CreateDir,
+  #[strum(serialize = "remove-path")]
+  RemovePath,
+  #[strum(serialize = "rename-path")]
+  RenamePath,
+  #[strum(serialize = "run-command")]
+  RunCommand,
+  #[strum(serialize = "spawn-command")]
+  SpawnCommand,
+  #[strum(serialize = "kill-command")]
+  KillCommand,
+  #[strum(serialize = "write-command-stdin")]
+  WriteCommandStdin,
+  #[strum(serialize = "close-command-stdin")]
+  CloseCommandStdin,
   /// to detect syntax `&`
   #[strum(serialize = "is-spreading-mark?")]
   IsSpreadingMark,
@@ -84,11 +114,47 @@ pub enum CalcitProc {
   ParseCirruEdn,
   #[strum(serialize = "format-cirru-edn")]
   FormatCirruEdn,
+  #[strum(serialize = "parse-json")]
+  ParseJson,
+  #[strum(serialize = "format-json")]
+  FormatJson,
   #[strum(serialize = "&cirru-quote:to-list")]
   NativeCirruQuoteToList,
   // time
   #[strum(serialize = "cpu-time")]
   CpuTime,
+  #[strum(serialize = "now-ms")]
+  NowMs,
+  #[strum(serialize = "now-ns")]
+  NowNs,
+  #[strum(serialize = "parse-time")]
+  ParseTime,
+  #[strum(serialize = "format-time")]
+  FormatTime,
+  #[strum(serialize = "time-fields")]
+  TimeFields,
+  #[strum(serialize = "time-from-fields")]
+  TimeFromFields,
+  #[strum(serialize = "time-add")]
+  TimeAdd,
+  // timers and promises
+  #[strum(serialize = "set-timeout")]
+  SetTimeout,
+  #[strum(serialize = "set-interval")]
+  SetInterval,
+  #[strum(serialize = "clear-timer")]
+  ClearTimer,
+  #[strum(serialize = "promise")]
+  Promise,
+  #[strum(serialize = "&promise:resolve")]
+  NativePromiseResolve,
+  #[strum(serialize = "&promise:reject")]
+  NativePromiseReject,
+  #[strum(serialize = "&promise:then")]
+  NativePromiseThen,
+  /// compiles to `await` in JavaScript
+  #[strum(serialize = "await")]
+  Await,
   // logics
   #[strum(serialize = "&=")]
   NativeEquals,
@@ -145,6 +211,20 @@ pub enum CalcitProc {
   BitXor,
   #[strum(serialize = "bit-not")]
   BitNot,
+  #[strum(serialize = "bigint")]
+  Bigint,
+  #[strum(serialize = "&bigint:to-number")]
+  NativeBigintToNumber,
+  #[strum(serialize = "rand-gen")]
+  RandGen,
+  #[strum(serialize = "rand")]
+  Rand,
+  #[strum(serialize = "rand-int")]
+  RandInt,
+  #[strum(serialize = "rand-nth")]
+  RandNth,
+  #[strum(serialize = "shuffle")]
+  Shuffle,
   // strings
   #[strum(serialize = "&str:concat")]
   NativeStrConcat,
@@ -200,6 +280,17 @@ pub enum CalcitProc {
   NativeStrPadLeft,
   #[strum(serialize = "&str:pad-right")]
   NativeStrPadRight,
+  // regular expressions
+  #[strum(serialize = "re-matches")]
+  ReMatches,
+  #[strum(serialize = "re-find")]
+  ReFind,
+  #[strum(serialize = "re-find-all")]
+  ReFindAll,
+  #[strum(serialize = "re-replace")]
+  ReReplace,
+  #[strum(serialize = "re-split")]
+  ReSplit,
   // lists
   #[strum(serialize = "[]")]
   List,
@@ -312,10 +403,16 @@ pub enum CalcitProc {
   Atom,
   #[strum(serialize = "&atom:deref")]
   AtomDeref,
+  #[strum(serialize = "&atom:swap!")]
+  AtomSwap,
   #[strum(serialize = "add-watch")]
   AddWatch,
   #[strum(serialize = "remove-watch")]
   RemoveWatch,
+  #[strum(serialize = "set-validator!")]
+  SetValidator,
+  #[strum(serialize = "persist-atoms!")]
+  PersistAtoms,
   // records
   #[strum(serialize = "new-record")]
   NewRecord,
@@ -0,0 +1,77 @@
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::Calcit;
+
+static PROMISE_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// called once when a promise is settled, with value or rejected reason
+pub type PromiseReaction = Box<dyn FnOnce(Result<Calcit, Calcit>) + Send>;
+
+pub enum PromiseState {
+  Pending(Vec<PromiseReaction>),
+  Fulfilled(Calcit),
+  Rejected(Calcit),
+}
+
+/// value to be settled later, like `Promise` in JavaScript. reactions are scheduled by the event loop
+pub struct CalcitPromise {
+  pub id: usize,
+  pub state: Mutex<PromiseState>,
+}
+
+impl fmt::Debug for CalcitPromise {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "CalcitPromise({})", self.id)
+  }
+}
+
+impl Default for CalcitPromise {
+  fn default() -> Self {
+    CalcitPromise {
+      id: PROMISE_ID.fetch_add(1, Ordering::SeqCst),
+      state: Mutex::new(PromiseState::Pending(vec![])),
+    }
+  }
+}
+
+impl CalcitPromise {
+  /// returns reactions waiting for the result, or `None` if already settled
+  pub fn settle(&self, result: Result<Calcit, Calcit>) -> Option<Vec<PromiseReaction>> {
+    let mut state = self.state.lock().expect("lock promise");
+    if !matches!(*state, PromiseState::Pending(_)) {
+      return None;
+    }
+    let next = match result {
+      Ok(v) => PromiseState::Fulfilled(v),
+      Err(e) => PromiseState::Rejected(e),
+    };
+    match std::mem::replace(&mut *state, next) {
+      PromiseState::Pending(reactions) => Some(reactions),
+      _ => unreachable!("checked pending"),
+    }
+  }
+
+  /// result when settled
+  pub fn result(&self) -> Option<Result<Calcit, Calcit>> {
+    match &*self.state.lock().expect("lock promise") {
+      PromiseState::Pending(_) => None,
+      PromiseState::Fulfilled(v) => Some(Ok(v.to_owned())),
+      PromiseState::Rejected(e) => Some(Err(e.to_owned())),
+    }
+  }
+
+  /// keeps the reaction when pending, otherwise returns it along with the result
+  pub fn react(&self, reaction: PromiseReaction) -> Option<(PromiseReaction, Result<Calcit, Calcit>)> {
+    let mut state = self.state.lock().expect("lock promise");
+    match &mut *state {
+      PromiseState::Pending(reactions) => {
+        reactions.push(reaction);
+        None
+      }
+      PromiseState::Fulfilled(v) => Some((reaction, Ok(v.to_owned()))),
+      PromiseState::Rejected(e) => Some((reaction, Err(e.to_owned()))),
+    }
+  }
+}
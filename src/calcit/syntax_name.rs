@@ -1,7 +1,7 @@
-use strum_macros::{AsRefStr, EnumString};
+use strum_macros::{AsRefStr, EnumIter, EnumString};
 
 /// core syntax inside Calcit
-#[derive(Debug, Clone, PartialEq, EnumString, strum_macros::Display, AsRefStr, PartialOrd, Eq, Ord)]
+#[derive(Debug, Clone, PartialEq, EnumString, EnumIter, strum_macros::Display, AsRefStr, PartialOrd, Eq, Ord)]
 pub enum CalcitSyntax {
   #[strum(serialize = "defn")]
   Defn,
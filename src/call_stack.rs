@@ -1,5 +1,4 @@
-use crate::calcit::Calcit;
-use crate::calcit::NodeLocation;
+use crate::calcit::{Calcit, NodeLocation, CORE_NS, GENERATED_DEF};
 use crate::data::cirru;
 use crate::data::edn;
 use cirru_edn::Edn;
@@ -93,6 +92,21 @@ impl CallStackList {
       self.to_owned()
     }
   }
+
+  /// location is not always attached to errors, try finding the innermost user code from stack
+  pub fn guess_location(&self) -> Option<NodeLocation> {
+    self.0.iter().filter(|s| &*s.ns != CORE_NS).find_map(|s| find_location(&s.code))
+  }
+}
+
+fn find_location(code: &Calcit) -> Option<NodeLocation> {
+  match code {
+    Calcit::List(xs) => xs.iter().find_map(find_location),
+    Calcit::Symbol { .. } | Calcit::Local(..) => code
+      .get_location()
+      .filter(|l| &*l.ns != CORE_NS && &*l.def != GENERATED_DEF && !l.coord.is_empty()),
+    _ => None,
+  }
 }
 
 // show simplified version of stack
@@ -13,6 +13,9 @@ pub struct ToplevelCalcit {
   /// disable stack trace for errors
   #[argh(switch)]
   pub disable_stack: bool,
+  /// record time spent in functions, writes collapsed stacks for flamegraph
+  #[argh(switch)]
+  pub profile: bool,
   /// skip arity check in js codegen
   #[argh(switch)]
   pub skip_arity_check: bool,
@@ -34,6 +37,9 @@ pub struct ToplevelCalcit {
   #[argh(option)]
   /// specify a path to watch assets changes
   pub watch_dir: Option<String>,
+  /// file of Cirru EDN keeping values of `defatom`s, loaded before running and written on exit
+  #[argh(option)]
+  pub persist_atoms: Option<String>,
   /// input source file, defaults to "compact.cirru"
   #[argh(positional, default = "String::from(\"compact.cirru\")")]
   pub input: String,
@@ -48,6 +54,18 @@ pub enum CalcitCommand {
   EmitIr(EmitIrCommand),
   /// evaluate snippet
   Eval(EvalCommand),
+  /// start an interactive REPL
+  Repl(ReplCommand),
+  /// run tests defined with `deftest`
+  Test(TestCommand),
+  /// start a language server over stdio
+  Lsp(LspCommand),
+  /// run program once with a step debugger
+  Debug(DebugCommand),
+  /// format snapshot file and source files
+  Fmt(FmtCommand),
+  /// check program with lint rules
+  Lint(LintCommand),
 }
 
 /// emit JavaScript rather than interpreting
@@ -57,6 +75,15 @@ pub struct EmitJsCommand {
   /// skip watching mode, just run once
   #[argh(switch, short = '1')]
   pub once: bool,
+  /// write source maps of `.mjs.map` next to generated files
+  #[argh(switch)]
+  pub source_map: bool,
+  /// emit every def, including those not reachable from entries
+  #[argh(switch)]
+  pub no_tree_shake: bool,
+  /// print namespaces and defs dropped by tree shaking
+  #[argh(switch)]
+  pub verbose: bool,
 }
 
 /// emit Cirru EDN representation of program to program-ir.cirru
@@ -79,3 +106,56 @@ pub struct EvalCommand {
   #[argh(option)]
   pub dep: Vec<String>,
 }
+
+/// start an interactive REPL with program loaded
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "repl")]
+pub struct ReplCommand {
+  /// namespace to evaluate expressions in, defaults to ns of `init_fn`
+  #[argh(option)]
+  pub ns: Option<String>,
+}
+
+/// run tests defined with `deftest`
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "test")]
+pub struct TestCommand {
+  /// only run tests whose `ns/def` contains this pattern
+  #[argh(option)]
+  pub filter: Option<String>,
+  /// write report to a file, `*.xml` for JUnit format, `*.cirru` for Cirru EDN
+  #[argh(option)]
+  pub report: Option<String>,
+}
+
+/// start a language server over stdio
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "lsp")]
+pub struct LspCommand {}
+
+/// run program once with a step debugger, pauses at first expression when no breakpoints given
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "debug")]
+pub struct DebugCommand {
+  /// breakpoint in `ns/def`, or `ns/def 1-2` for an expression at the coord
+  #[argh(option, long = "break")]
+  pub breakpoints: Vec<String>,
+}
+
+/// format snapshot file, and source files in `src/` next to it
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "fmt")]
+pub struct FmtCommand {
+  /// only check, exits with error when some file would change
+  #[argh(switch)]
+  pub check: bool,
+}
+
+/// check namespaces of the package with lint rules, configured in `:lint` of configs
+#[derive(FromArgs, PartialEq, Debug, Clone)]
+#[argh(subcommand, name = "lint")]
+pub struct LintCommand {
+  /// write warnings to a file in Cirru EDN, instead of printing them as text
+  #[argh(option)]
+  pub report: Option<String>,
+}
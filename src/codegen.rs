@@ -8,6 +8,12 @@ static CODEGEN_MODE: AtomicBool = AtomicBool::new(true);
 
 static CODEGEN_SKIP_ARITY_CHECK: AtomicBool = AtomicBool::new(false);
 
+static CODEGEN_SOURCE_MAP: AtomicBool = AtomicBool::new(false);
+
+static CODEGEN_TREE_SHAKE: AtomicBool = AtomicBool::new(true);
+
+static CODEGEN_VERBOSE: AtomicBool = AtomicBool::new(false);
+
 pub const COMPILE_ERRORS_FILE: &str = "calcit.build-errors";
 
 pub fn codegen_mode() -> bool {
@@ -31,3 +37,33 @@ pub fn set_code_gen_skip_arity_check(b: bool) {
 pub fn skip_arity_check() -> bool {
   CODEGEN_SKIP_ARITY_CHECK.load(std::sync::atomic::Ordering::Relaxed)
 }
+
+/// whether to write `.mjs.map` files in js codegen
+pub fn set_code_gen_source_map(b: bool) {
+  CODEGEN_SOURCE_MAP.store(b, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// read global flag for source maps
+pub fn source_map_enabled() -> bool {
+  CODEGEN_SOURCE_MAP.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// defaults to `true`, turned off to emit every def in program
+pub fn set_code_gen_tree_shake(b: bool) {
+  CODEGEN_TREE_SHAKE.store(b, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// read global flag for tree shaking
+pub fn tree_shake_enabled() -> bool {
+  CODEGEN_TREE_SHAKE.load(std::sync::atomic::Ordering::Relaxed)
+}
+
+/// whether to print details of js codegen, like what tree shaking dropped
+pub fn set_code_gen_verbose(b: bool) {
+  CODEGEN_VERBOSE.store(b, std::sync::atomic::Ordering::Relaxed)
+}
+
+/// read global flag for verbose logs
+pub fn verbose_enabled() -> bool {
+  CODEGEN_VERBOSE.load(std::sync::atomic::Ordering::Relaxed)
+}
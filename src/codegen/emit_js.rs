@@ -2,6 +2,8 @@ pub mod gen_stack;
 mod internal_states;
 use std::fmt::Write;
 mod snippets;
+mod source_map;
+mod tree_shake;
 
 use cirru_parser::Cirru;
 use im_ternary_tree::TernaryTreeList;
@@ -18,11 +20,12 @@ use crate::builtins::meta::{js_gensym, reset_js_gensym_index};
 use crate::builtins::syntax::get_raw_args_fn;
 use crate::builtins::{is_js_syntax_procs, is_proc_name};
 use crate::calcit::{self, CalcitArgLabel, CalcitFnArgs, CalcitImport, CalcitList, CalcitLocal, CalcitProc, MethodKind};
-use crate::calcit::{Calcit, CalcitSyntax, ImportInfo};
+use crate::calcit::{Calcit, CalcitSyntax, ImportInfo, NodeLocation};
 use crate::call_stack::StackKind;
-use crate::codegen::skip_arity_check;
+use crate::codegen::{skip_arity_check, source_map_enabled, tree_shake_enabled, verbose_enabled};
 use crate::program;
 use crate::util::string::{has_ns_part, matches_js_var, wrap_js_str};
+use crate::ProgramEntries;
 
 struct ImportsDict(HashSet<CalcitImport>);
 
@@ -154,6 +157,7 @@ fn quote_to_js(xs: &Calcit, var_prefix: &str, tags: &RefCell<HashSet<EdnTag>>) -
     Calcit::Str(s) => Ok(escape_cirru_str(s)),
     Calcit::Bool(b) => Ok(b.to_string()),
     Calcit::Number(n) => Ok(n.to_string()),
+    Calcit::BigInt(n) => Ok(format!("{n}n")),
     Calcit::Nil => Ok(String::from("null")),
     // mainly for methods, which are recognized during reading
     Calcit::Proc(p) => Ok(format!("new {var_prefix}CalcitSymbol({})", escape_cirru_str(p.as_ref()))),
@@ -215,7 +219,12 @@ fn to_js_code(
 ) -> Result<String, String> {
   // println!("to js code handle: {} {:?}", xs, xs);
   if let Calcit::List(ys) = xs {
-    gen_call_code(ys, ns, local_defs, xs, file_imports, tags, return_label)
+    let code = gen_call_code(ys, ns, local_defs, xs, file_imports, tags, return_label)?;
+    if source_map_enabled() {
+      Ok(source_map::mark_expr(xs, code))
+    } else {
+      Ok(code)
+    }
   } else {
     let ret = match xs {
       Calcit::Symbol { sym, info, .. } => {
@@ -252,6 +261,7 @@ fn to_js_code(
           }
         }
       }
+      Calcit::Local(CalcitLocal { sym, .. }) if source_map_enabled() => Ok(source_map::mark_expr(xs, escape_var(sym))),
       Calcit::Local(CalcitLocal { sym, .. }) => Ok(escape_var(sym)),
       Calcit::Proc(s) => {
         let proc_prefix = get_proc_prefix(ns);
@@ -279,6 +289,7 @@ fn to_js_code(
       Calcit::Str(s) => Ok(escape_cirru_str(s)),
       Calcit::Bool(b) => Ok(b.to_string()),
       Calcit::Number(n) => Ok(n.to_string()),
+      Calcit::BigInt(n) => Ok(format!("{n}n")),
       Calcit::Nil => Ok(String::from("null")),
       Calcit::Tag(s) => {
         let mut tags = tags.borrow_mut();
@@ -400,7 +411,7 @@ fn gen_call_code(
             let handler = to_js_code(handler, ns, local_defs, file_imports, tags, None)?;
 
             gen_stack::pop_call_stack();
-            let code = snippets::tmpl_try(err_var, try_code, handler, next_return_label);
+            let code = snippets::tmpl_try(err_var, try_code, handler, next_return_label, get_proc_prefix(ns));
             match return_label {
               Some(_) => Ok(code),
               None => Ok(snippets::tmpl_fn_wrapper(code)),
@@ -427,15 +438,11 @@ fn gen_call_code(
         Some(m) => {
           let message: String = to_js_code(m, ns, local_defs, file_imports, tags, None)?;
           let has_await = detect_await(&body);
-          let data_code = match body.get(1) {
-            Some(d) => to_js_code(d, ns, local_defs, file_imports, tags, None)?,
-            None => String::from("null"),
+          let args_code = match body.get(1) {
+            Some(d) => format!("{message}, {}", to_js_code(d, ns, local_defs, file_imports, tags, None)?),
+            None => message,
           };
-          let err_var = js_gensym("err");
-          let ret = format!(
-            "let {} = new Error({});\n {}.data = {};\n throw {};",
-            err_var, message, err_var, data_code, err_var
-          );
+          let ret = format!("throw {}_calcit_make_error({args_code});", get_proc_prefix(ns));
           // println!("inside raise: {:?} {}", return_label, xs);
           match return_label {
             Some(_) => Ok(ret),
@@ -445,6 +452,13 @@ fn gen_call_code(
         None => Err(format!("raise expected 1~2 arguments, got: {}", body)),
       }
     }
+    Calcit::Proc(CalcitProc::Await) => match body.first() {
+      Some(v) => Ok(format!(
+        "{return_code}(await {})",
+        to_js_code(v, ns, local_defs, file_imports, tags, None)?
+      )),
+      None => Err(format!("await expected a promise, got nothing, {xs}")),
+    },
     Calcit::Proc(_) => {
       let args_code = gen_args_code(&body, ns, local_defs, file_imports, tags)?;
       Ok(format!(
@@ -668,10 +682,11 @@ fn detect_await(xs: &CalcitList) -> bool {
           return true;
         }
       }
-      Calcit::Symbol { sym, .. } => {
-        if &**sym == "js-await" {
-          return true;
-        }
+      Calcit::Symbol { sym, .. } if &**sym == "js-await" => {
+        return true;
+      }
+      Calcit::Proc(CalcitProc::Await) => {
+        return true;
       }
       _ => {}
     }
@@ -1216,7 +1231,9 @@ fn write_file_if_changed(filename: &Path, content: &str) -> Result<bool, String>
   Ok(true)
 }
 
-pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
+/// only defs reachable from `init_fn` and `reload_fn` are emitted, unless tree shaking is turned off
+pub fn emit_js(entries: &ProgramEntries, emit_path: &str) -> Result<(), String> {
+  let entry_ns: &str = &entries.init_ns;
   let code_emit_path = Path::new(emit_path);
   if !code_emit_path.exists() {
     let _ = fs::create_dir(code_emit_path);
@@ -1225,8 +1242,40 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
   let mut unchanged_ns: HashSet<Arc<str>> = HashSet::new();
 
   let program = program::clone_evaled_program();
+  let roots = [
+    (entries.init_ns.to_owned(), entries.init_def.to_owned()),
+    (entries.reload_ns.to_owned(), entries.reload_def.to_owned()),
+  ];
+  let reachable = tree_shake_enabled().then(|| tree_shake::reachable_defs(&program, &roots));
+  let mut dropped_ns: Vec<Arc<str>> = vec![];
+  let mut dropped_defs: Vec<(Arc<str>, usize, usize)> = vec![];
+  let mut emitted_ns: HashSet<Arc<str>> = HashSet::new();
+
   for (ns, file) in program.iter() {
     // println!("\nstart handling: {}\n", ns);
+    let file: HashMap<Arc<str>, Calcit> = match reachable.as_ref().map(|r| r.get(&ns)) {
+      None => file.to_hashmap(),
+      Some(Some(kept)) => {
+        // macros are already expanded, not counted
+        let all: HashMap<Arc<str>, Calcit> = file
+          .to_hashmap()
+          .into_iter()
+          .filter(|(_, v)| !matches!(v, Calcit::Macro { .. }))
+          .collect();
+        let total = all.len();
+        let xs: HashMap<Arc<str>, Calcit> = all.into_iter().filter(|(def, _)| kept.contains(def)).collect();
+        if xs.len() < total {
+          dropped_defs.push((ns.to_owned(), total - xs.len(), total));
+        }
+        xs
+      }
+      Some(None) => {
+        dropped_ns.push(ns.to_owned());
+        continue;
+      }
+    };
+    emitted_ns.insert(ns.to_owned());
+
     // side-effects, reset tracking state
 
     let file_imports: RefCell<ImportsDict> = RefCell::new(ImportsDict::new());
@@ -1255,6 +1304,7 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
 
     // reset index each file
     reset_js_gensym_index();
+    source_map::reset_markers();
 
     let core_lib = to_js_import_name("calcit.core", true);
 
@@ -1276,7 +1326,7 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
       def_names.insert(def.to_owned());
     }
 
-    let deps_in_order = sort_by_deps(&file.to_hashmap());
+    let deps_in_order = sort_by_deps(&file);
     // println!("deps order: {:?}", deps_in_order);
 
     for def in deps_in_order {
@@ -1291,7 +1341,7 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
         }
       }
 
-      let f = file.lookup(&def).unwrap().0.to_owned();
+      let f = file[&def].to_owned();
 
       match &f {
         // probably not work here
@@ -1305,28 +1355,30 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
             local_defs: &def_names,
             file_imports: &file_imports,
           };
-          defs_code.push_str(&gen_js_func(
-            &def,
-            &info.args,
-            &info.body,
-            &passed_defs,
-            true,
-            &collected_tags,
-            &ns,
-          )?);
+          let code = gen_js_func(&def, &info.args, &info.body, &passed_defs, true, &collected_tags, &ns)?;
+          if source_map_enabled() {
+            let location = NodeLocation::new(ns.to_owned(), def.to_owned(), Arc::new(vec![]));
+            defs_code.push_str(&source_map::mark_def(location, code));
+          } else {
+            defs_code.push_str(&code);
+          }
           gen_stack::pop_call_stack();
         }
         Calcit::Thunk(thunk) => {
           // TODO need topological sorting for accuracy
           // values are called directly, put them after fns
           gen_stack::push_call_stack(&ns, &def, StackKind::Codegen, thunk.get_code().to_owned(), &[]);
-          writeln!(
-            vals_code,
-            "\nexport var {} = {};",
+          let code = format!(
+            "\nexport var {} = {};\n",
             escape_var(&def),
             to_js_code(thunk.get_code(), &ns, &def_names, &file_imports, &collected_tags, None)?
-          )
-          .expect("write");
+          );
+          if source_map_enabled() {
+            let location = NodeLocation::new(ns.to_owned(), def.to_owned(), Arc::new(vec![]));
+            vals_code.push_str(&source_map::mark_def(location, code));
+          } else {
+            vals_code.push_str(&code);
+          }
           gen_stack::pop_call_stack()
         }
         // macro are not traced in codegen since already expanded
@@ -1334,7 +1386,7 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
         Calcit::Syntax(_, _) => {
           // should he handled inside compiler
         }
-        Calcit::Bool(_) | Calcit::Number(_) => {
+        Calcit::Bool(_) | Calcit::Number(_) | Calcit::BigInt(_) => {
           println!("[Warn] expected thunk, got macro. skipped `{ns}/{def} {f}`")
         }
         _ => {
@@ -1408,11 +1460,16 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
     tags_code.push_str(&snippets::tmpl_tags_init(&tag_arr, tag_prefix));
     tags_code.push('\n');
 
-    let js_file_path = code_emit_path.join(to_mjs_filename(&ns));
-    let wrote_new = write_file_if_changed(
-      &js_file_path,
-      &format!("{}{}\n{}\n\n{}\n{}", import_code, tags_code, defs_code, vals_code, direct_code),
-    )?;
+    let js_filename = to_mjs_filename(&ns);
+    let js_file_path = code_emit_path.join(&js_filename);
+    let mut content = format!("{}{}\n{}\n\n{}\n{}", import_code, tags_code, defs_code, vals_code, direct_code);
+    if source_map_enabled() {
+      let (code, map) = source_map::extract_source_map(&content, &ns, &js_filename);
+      content = code;
+      // written separately, so it's kept in sync when js file is unchanged
+      write_file_if_changed(&code_emit_path.join(format!("{js_filename}.map")), &map)?;
+    }
+    let wrote_new = write_file_if_changed(&js_file_path, &content)?;
     if wrote_new {
       println!("emitted: {}", js_file_path.to_str().expect("exptract path"));
     } else {
@@ -1423,12 +1480,58 @@ pub fn emit_js(entry_ns: &str, emit_path: &str) -> Result<(), String> {
   if !unchanged_ns.is_empty() {
     println!("\n... and {} files not changed.", unchanged_ns.len());
   }
+  // dropped namespaces, and those not evaluated at all, may have files from earlier compilations
+  let source_ns: Vec<Arc<str>> = program::PROGRAM_CODE_DATA
+    .read()
+    .expect("read program code")
+    .keys()
+    .cloned()
+    .collect();
+  for ns in source_ns {
+    if !emitted_ns.contains(&ns) {
+      remove_stale_files(code_emit_path, &ns)?;
+    }
+  }
+  if verbose_enabled() {
+    report_tree_shaking(&mut dropped_ns, &mut dropped_defs);
+  }
 
   let _ = internal_states::finish_compilation();
 
   Ok(())
 }
 
+/// files emitted for a namespace which is no longer used, kept files would still be loadable
+fn remove_stale_files(code_emit_path: &Path, ns: &str) -> Result<(), String> {
+  internal_states::remove_ns_cache(ns);
+  let js_filename = to_mjs_filename(ns);
+  for filename in [js_filename.to_owned(), format!("{js_filename}.map")] {
+    let path = code_emit_path.join(filename);
+    if path.exists() {
+      fs::remove_file(&path).map_err(|e| format!("failed to remove {}: {e}", path.display()))?;
+      println!("removed: {}", path.display());
+    }
+  }
+  Ok(())
+}
+
+/// namespaces and defs not reachable from entries, which are not emitted
+fn report_tree_shaking(dropped_ns: &mut [Arc<str>], dropped_defs: &mut [(Arc<str>, usize, usize)]) {
+  if dropped_ns.is_empty() && dropped_defs.is_empty() {
+    return;
+  }
+  dropped_ns.sort();
+  dropped_defs.sort();
+  let count: usize = dropped_defs.iter().map(|(_, n, _)| n).sum();
+  println!("\ntree shaking dropped {} namespaces and {count} defs:", dropped_ns.len());
+  for ns in dropped_ns.iter() {
+    println!("  {ns}");
+  }
+  for (ns, n, total) in dropped_defs.iter() {
+    println!("  {ns}, {n} of {total} defs");
+  }
+}
+
 fn is_js_unavailable_procs(name: &str) -> bool {
   matches!(
     name,
@@ -8,9 +8,10 @@ use std::sync::{Arc, LazyLock, RwLock};
 // track if it's the first compilation
 static FIRST_COMPILATION: AtomicBool = AtomicBool::new(true);
 
+type ProgramCaches = HashMap<Arc<str>, HashSet<Arc<str>>>;
+
 // caches program data for detecting incremental changes of libs
-static GLOBAL_PREVIOUS_PROGRAM_CACHES: LazyLock<RwLock<HashMap<Arc<str>, HashSet<Arc<str>>>>> =
-  LazyLock::new(|| RwLock::new(HashMap::new()));
+static GLOBAL_PREVIOUS_PROGRAM_CACHES: LazyLock<RwLock<ProgramCaches>> = LazyLock::new(|| RwLock::new(HashMap::new()));
 
 pub fn lookup_prev_ns_cache(ns: &str) -> Option<HashSet<Arc<str>>> {
   let previous_program_caches = &GLOBAL_PREVIOUS_PROGRAM_CACHES.read().expect("load cache");
@@ -22,6 +23,11 @@ pub fn write_as_ns_cache(ns: &str, v: HashSet<Arc<str>>) {
   (*previous_program_caches).insert(ns.to_owned().into(), v);
 }
 
+pub fn remove_ns_cache(ns: &str) {
+  let mut previous_program_caches = GLOBAL_PREVIOUS_PROGRAM_CACHES.write().expect("write cache");
+  (*previous_program_caches).remove(ns);
+}
+
 pub fn is_first_compilation() -> bool {
   FIRST_COMPILATION.load(Ordering::Relaxed)
 }
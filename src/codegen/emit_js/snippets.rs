@@ -2,14 +2,14 @@ use crate::{builtins::meta::js_gensym, codegen::emit_js::get_proc_prefix};
 
 pub const CALCIT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-pub fn tmpl_try(err_var: String, body: String, handler: String, return_code: &str) -> String {
+pub fn tmpl_try(err_var: String, body: String, handler: String, return_code: &str, proc_prefix: &str) -> String {
   format!(
     "try {{
   {}
 }} catch ({}) {{
-  {} ({})({})
+  {} ({})({}_calcit_error_value({}))
 }}",
-    body, err_var, return_code, handler, err_var,
+    body, err_var, return_code, handler, proc_prefix, err_var,
   )
 }
 
@@ -0,0 +1,180 @@
+//! source maps are built from markers placed in generated code, since code is generated by concatenating strings.
+//! original source of a namespace is a listing of expressions, one line for each location, like `app.main/f 3-1  (+ a b)`
+
+use std::cell::RefCell;
+
+use serde_json::json;
+
+use crate::calcit::{Calcit, NodeLocation, CORE_NS, GENERATED_DEF};
+use crate::program;
+
+const MARKER_START: char = '\u{1}';
+const MARKER_END: char = '\u{2}';
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+/// expressions in listing are truncated
+const SNIPPET_SIZE: usize = 100;
+
+thread_local! {
+  /// locations of markers in the file being generated
+  static LOCATIONS: RefCell<Vec<NodeLocation>> = const { RefCell::new(vec![]) };
+}
+
+/// clear locations before generating a file
+pub fn reset_markers() {
+  LOCATIONS.with_borrow_mut(|xs| xs.clear());
+}
+
+/// prepend a marker of location to generated code
+pub fn mark(location: NodeLocation, code: String) -> String {
+  let idx = LOCATIONS.with_borrow_mut(|xs| {
+    xs.push(location);
+    xs.len() - 1
+  });
+  format!("{MARKER_START}{idx}{MARKER_END}{code}")
+}
+
+/// mark code of a list or a local, when location is known, core and generated code are skipped
+pub fn mark_expr(xs: &Calcit, code: String) -> String {
+  let location = match xs {
+    Calcit::List(_) => xs.expr_location(),
+    _ => xs.get_location().filter(|l| &*l.ns != CORE_NS && &*l.def != GENERATED_DEF),
+  };
+  match location {
+    Some(l) => mark(l, code),
+    None => code,
+  }
+}
+
+/// place marker after leading newlines, so that it points to the line of code
+pub fn mark_def(location: NodeLocation, code: String) -> String {
+  let trimmed = code.trim_start_matches('\n');
+  let newlines = &code[..code.len() - trimmed.len()];
+  format!("{newlines}{}", mark(location, trimmed.to_owned()))
+}
+
+/// remove markers from code, returns code and content of `.mjs.map` file
+pub fn extract_source_map(content: &str, ns: &str, js_filename: &str) -> (String, String) {
+  let locations = LOCATIONS.with_borrow(|xs| xs.to_owned());
+
+  let mut code = String::with_capacity(content.len());
+  // generated line, column, and index of location
+  let mut segments: Vec<(usize, usize, usize)> = vec![];
+  let mut line = 0;
+  let mut column = 0;
+  let mut chars = content.chars().peekable();
+  while let Some(c) = chars.next() {
+    if c == MARKER_START {
+      let mut digits = String::new();
+      while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+        digits.push(d);
+      }
+      match (chars.next_if_eq(&MARKER_END), digits.parse::<usize>()) {
+        (Some(_), Ok(idx)) if idx < locations.len() => {
+          segments.push((line, column, idx));
+          continue;
+        }
+        (end, _) => {
+          // not a marker, keep as it is
+          code.push(c);
+          code.push_str(&digits);
+          column += 1 + digits.len();
+          if let Some(e) = end {
+            code.push(e);
+            column += 1;
+          }
+          continue;
+        }
+      }
+    }
+    code.push(c);
+    if c == '\n' {
+      line += 1;
+      column = 0;
+    } else {
+      // columns are counted in UTF-16 like browsers do
+      column += c.len_utf16();
+    }
+  }
+
+  // one line for each location in listing, sorted by def and coord
+  let mut listed: Vec<&NodeLocation> = locations.iter().collect();
+  listed.sort_by(|a, b| (&a.def, &a.coord).cmp(&(&b.def, &b.coord)));
+  listed.dedup();
+  let listing: Vec<String> = listed.iter().map(|l| listing_line(l)).collect();
+
+  let mut mappings = String::new();
+  let mut prev_line = 0;
+  let mut prev_column = 0;
+  let mut prev_original_line = 0;
+  for (line, column, idx) in segments {
+    if line > prev_line {
+      mappings.push_str(&";".repeat(line - prev_line));
+      prev_line = line;
+      prev_column = 0;
+    } else if !mappings.is_empty() && !mappings.ends_with(';') {
+      mappings.push(',');
+    }
+    let original_line = listed.iter().position(|l| **l == locations[idx]).unwrap_or(0) as i64;
+    encode_vlq(&mut mappings, column as i64 - prev_column);
+    encode_vlq(&mut mappings, 0);
+    encode_vlq(&mut mappings, original_line - prev_original_line);
+    encode_vlq(&mut mappings, 0);
+    prev_column = column as i64;
+    prev_original_line = original_line;
+  }
+
+  code.push_str(&format!("\n//# sourceMappingURL={js_filename}.map\n"));
+  let map = json!({
+    "version": 3,
+    "file": js_filename,
+    "sources": [format!("{ns}.cirru")],
+    "sourcesContent": [listing.join("\n")],
+    "names": [],
+    "mappings": mappings,
+  });
+  (code, map.to_string())
+}
+
+/// location with the code written there
+fn listing_line(location: &NodeLocation) -> String {
+  let mut line = format!("{}/{}", location.ns, location.def);
+  if !location.coord.is_empty() {
+    line.push_str(&format!(
+      " {}",
+      location.coord.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("-")
+    ));
+  }
+  if let Some(snippet) = code_at(location) {
+    let snippet: String = snippet.chars().take(SNIPPET_SIZE).collect();
+    line.push_str("  ");
+    line.push_str(&snippet);
+  }
+  line
+}
+
+fn code_at(location: &NodeLocation) -> Option<String> {
+  let mut code = program::lookup_def_code(&location.ns, &location.def)?;
+  for idx in location.coord.iter() {
+    code = match code {
+      Calcit::List(xs) => xs.get(*idx as usize)?.to_owned(),
+      _ => return None,
+    };
+  }
+  Some(code.lisp_str())
+}
+
+/// base64 VLQ, sign is placed at the lowest bit
+fn encode_vlq(buf: &mut String, n: i64) {
+  let mut v = if n < 0 { ((-n) << 1) | 1 } else { n << 1 };
+  loop {
+    let mut digit = (v & 0b11111) as usize;
+    v >>= 5;
+    if v > 0 {
+      digit |= 0b100000;
+    }
+    buf.push(BASE64_CHARS[digit] as char);
+    if v == 0 {
+      break;
+    }
+  }
+}
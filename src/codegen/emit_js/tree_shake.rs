@@ -0,0 +1,64 @@
+//! finds defs reachable from entries, so unused code of core and libraries is not emitted
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::calcit::{Calcit, CalcitImport, CalcitThunk, CORE_NS};
+use crate::program::ProgramEvaledData;
+
+/// defs in core used by generated code without being referenced, see `tmpl_classes_registering`
+const CORE_ROOTS: [&str; 7] = [
+  "&core-list-class",
+  "&core-map-class",
+  "&core-number-class",
+  "&core-set-class",
+  "&core-string-class",
+  "&core-nil-class",
+  "&core-fn-class",
+];
+
+/// defs reachable from roots by following imports in code, grouped by namespace
+pub fn reachable_defs(program: &ProgramEvaledData, roots: &[(Arc<str>, Arc<str>)]) -> HashMap<Arc<str>, HashSet<Arc<str>>> {
+  let mut reached: HashMap<Arc<str>, HashSet<Arc<str>>> = HashMap::new();
+  let mut pending: Vec<(Arc<str>, Arc<str>)> = roots.to_vec();
+  for def in CORE_ROOTS {
+    pending.push((Arc::from(CORE_NS), Arc::from(def)));
+  }
+
+  while let Some((ns, def)) = pending.pop() {
+    let value = match program.lookup(&ns).and_then(|(file, _)| file.lookup(&def)) {
+      Some((v, _)) => v,
+      // npm packages and procs are not in program
+      None => continue,
+    };
+    if !reached.entry(ns.to_owned()).or_default().insert(def.to_owned()) {
+      continue;
+    }
+    collect_refs(value, program, &mut pending);
+  }
+  reached
+}
+
+fn collect_refs(code: &Calcit, program: &ProgramEvaledData, pending: &mut Vec<(Arc<str>, Arc<str>)>) {
+  match code {
+    Calcit::Import(CalcitImport { ns, def, .. }) => pending.push((ns.to_owned(), def.to_owned())),
+    // unresolved symbols are emitted as names in the file, or names from core
+    Calcit::Symbol { sym, info, .. } if program.lookup(&info.at_ns).and_then(|(file, _)| file.lookup(sym)).is_some() => {
+      pending.push((info.at_ns.to_owned(), sym.to_owned()));
+    }
+    Calcit::List(xs) => {
+      for x in xs.iter() {
+        collect_refs(x, program, pending);
+      }
+    }
+    Calcit::Fn { info, .. } => {
+      for x in &info.body {
+        collect_refs(x, program, pending);
+      }
+    }
+    Calcit::Thunk(CalcitThunk::Code { code, .. }) | Calcit::Thunk(CalcitThunk::Evaled { code, .. }) => {
+      collect_refs(code, program, pending);
+    }
+    _ => {}
+  }
+}
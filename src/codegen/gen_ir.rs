@@ -86,6 +86,7 @@ pub fn emit_ir(init_fn: &str, reload_fn: &str, emit_path: &str) -> Result<(), St
 pub(crate) fn dump_code(code: &Calcit) -> Edn {
   match code {
     Calcit::Number(n) => Edn::Number(*n),
+    Calcit::BigInt(n) => Edn::map_from_iter([(Edn::tag("kind"), Edn::tag("bigint")), (Edn::tag("val"), Edn::str(n.to_string()))]),
     Calcit::Nil => Edn::Nil,
     Calcit::Str(s) => Edn::Str((**s).into()),
     Calcit::Bool(b) => Edn::Bool(b.to_owned()),
@@ -7,6 +7,7 @@ use crate::{
 
 pub mod cirru;
 pub mod edn;
+pub mod json;
 
 pub fn data_to_calcit(x: &Calcit, ns: &str, at_def: &str) -> Result<Calcit, String> {
   match x {
@@ -14,6 +15,7 @@ pub fn data_to_calcit(x: &Calcit, ns: &str, at_def: &str) -> Result<Calcit, Stri
     Calcit::Proc(p) => Ok(Calcit::Proc(p.to_owned())),
     Calcit::Bool(b) => Ok(Calcit::Bool(*b)),
     Calcit::Number(n) => Ok(Calcit::Number(*n)),
+    Calcit::BigInt(n) => Ok(Calcit::BigInt(n.to_owned())),
     Calcit::Str(s) => Ok(Calcit::Str(s.to_owned())),
     Calcit::Tag(k) => Ok(Calcit::Tag(k.to_owned())),
     Calcit::CirruQuote(_) => Ok(Calcit::from(CalcitList::from(&[
@@ -86,6 +88,7 @@ pub fn data_to_calcit(x: &Calcit, ns: &str, at_def: &str) -> Result<Calcit, Stri
       Ok(Calcit::from(ys))
     }
     Calcit::Ref(_, _) => Err(format!("data_to_calcit not implemented for ref: {}", x)),
+    Calcit::Promise(_) => Err(format!("data_to_calcit not implemented for promise: {}", x)),
     Calcit::Thunk(thunk) => Ok(thunk.get_code().to_owned()),
     Calcit::Buffer(_) => Err(format!("data_to_calcit not implemented for buffer: {}", x)),
     Calcit::Recur(_xs) => Err(format!("data_to_calcit not implemented for recur: {}", x)),
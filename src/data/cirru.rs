@@ -1,6 +1,7 @@
 use std::{sync::Arc, vec};
 
 use cirru_parser::Cirru;
+use num_bigint::BigInt;
 
 use crate::calcit::{Calcit, CalcitImport, CalcitList, CalcitLocal, CalcitProc, CalcitSyntax, MethodKind};
 
@@ -91,6 +92,8 @@ pub fn code_to_calcit(xs: &Cirru, ns: &str, def: &str, coord: Vec<u8>) -> Result
             Ok(Calcit::Proc(p))
           } else if let Ok(f) = s.parse::<f64>() {
             Ok(Calcit::Number(f))
+          } else if let Some(n) = s.strip_suffix('n').and_then(|digits| digits.parse::<BigInt>().ok()) {
+            Ok(Calcit::BigInt(n))
           } else {
             Ok(Calcit::Symbol {
               sym: (**s).into(),
@@ -151,6 +154,7 @@ pub fn calcit_data_to_cirru(xs: &Calcit) -> Result<Cirru, String> {
     Calcit::Nil => Ok(Cirru::leaf("nil")),
     Calcit::Bool(b) => Ok(Cirru::Leaf(b.to_string().into())),
     Calcit::Number(n) => Ok(Cirru::Leaf(n.to_string().into())),
+    Calcit::BigInt(n) => Ok(Cirru::Leaf(format!("{n}n").into())),
     Calcit::Str(s) => Ok(Cirru::Leaf((**s).into())),
     Calcit::List(ys) => {
       let mut zs: Vec<Cirru> = Vec::with_capacity(ys.len());
@@ -174,6 +178,7 @@ pub fn calcit_to_cirru(x: &Calcit) -> Result<Cirru, String> {
     Calcit::Bool(true) => Ok(Cirru::leaf("true")),
     Calcit::Bool(false) => Ok(Cirru::leaf("false")),
     Calcit::Number(n) => Ok(Cirru::Leaf(n.to_string().into())),
+    Calcit::BigInt(n) => Ok(Cirru::Leaf(format!("{n}n").into())),
     Calcit::Str(s) => Ok(Cirru::leaf(format!("|{s}"))),
     Calcit::Symbol { sym, .. } => Ok(Cirru::Leaf(sym.to_owned())),
     Calcit::Local(CalcitLocal { sym, .. }) => Ok(Cirru::Leaf(sym.to_owned())),
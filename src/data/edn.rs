@@ -5,6 +5,7 @@ use crate::calcit::{Calcit, CalcitRecord};
 use crate::{calcit::MethodKind, data::cirru};
 
 use cirru_edn::{Edn, EdnListView, EdnMapView, EdnRecordView, EdnSetView, EdnTag, EdnTupleView};
+use num_bigint::BigInt;
 
 // values does not fit are just represented with specical indicates
 pub fn calcit_to_edn(x: &Calcit) -> Result<Edn, String> {
@@ -13,6 +14,8 @@ pub fn calcit_to_edn(x: &Calcit) -> Result<Edn, String> {
     Calcit::Bool(b) => Ok(Edn::Bool(*b)),
     Calcit::Str(s) => Ok(Edn::Str((**s).into())),
     Calcit::Number(n) => Ok(Edn::Number(*n)),
+    // EDN has no integers, written in a tuple like `:: 'bigint |12`
+    Calcit::BigInt(n) => Ok(Edn::tuple(Edn::sym("bigint"), vec![Edn::str(n.to_string())])),
     Calcit::Tag(s) => Ok(Edn::Tag(s.to_owned())),
     Calcit::Symbol { sym, .. } => Ok(Edn::Symbol((**sym).into())),
     Calcit::Local(CalcitLocal { sym, .. }) => Ok(Edn::Symbol((**sym).into())),
@@ -101,12 +104,20 @@ pub fn calcit_to_edn(x: &Calcit) -> Result<Edn, String> {
     Calcit::AnyRef(r) => Ok(Edn::AnyRef(r.to_owned())),
     Calcit::Ref(_p, pair) => {
       let pair = pair.lock().expect("read ref");
-      Ok(Edn::Atom(Box::new(calcit_to_edn(&pair.0)?)))
+      Ok(Edn::Atom(Box::new(calcit_to_edn(&pair.value)?)))
     }
     a => Err(format!("not able to generate EDN: {a:?}")), // TODO more types to handle
   }
 }
 
+/// bigint written like `:: 'bigint |12`
+fn edn_bigint(tag: &Edn, extra: &[Edn]) -> Option<BigInt> {
+  match (tag, extra) {
+    (Edn::Symbol(s), [Edn::Str(digits)]) if &**s == "bigint" => digits.parse().ok(),
+    _ => None,
+  }
+}
+
 pub fn edn_to_calcit(x: &Edn, options: &Calcit) -> Calcit {
   match x {
     Edn::Nil => Calcit::Nil,
@@ -123,11 +134,14 @@ pub fn edn_to_calcit(x: &Edn, options: &Calcit) -> Calcit {
     Edn::Tag(s) => Calcit::Tag(s.to_owned()),
     Edn::Str(s) => Calcit::Str((**s).into()),
     Edn::Quote(nodes) => Calcit::CirruQuote(nodes.to_owned()),
-    Edn::Tuple(EdnTupleView { tag, extra }) => Calcit::Tuple(CalcitTuple {
-      tag: Arc::new(edn_to_calcit(tag, options)),
-      extra: extra.iter().map(|x| edn_to_calcit(x, options)).collect(),
-      class: None,
-    }),
+    Edn::Tuple(EdnTupleView { tag, extra }) => match edn_bigint(tag, extra) {
+      Some(n) => Calcit::BigInt(n),
+      None => Calcit::Tuple(CalcitTuple {
+        tag: Arc::new(edn_to_calcit(tag, options)),
+        extra: extra.iter().map(|x| edn_to_calcit(x, options)).collect(),
+        class: None,
+      }),
+    },
     Edn::List(EdnListView(xs)) => {
       let mut ys: Vec<Calcit> = vec![];
       for x in xs {
@@ -0,0 +1,198 @@
+//! JSON for exchanging data, objects are maps with string keys, or tag keys when asked.
+//! writer is done by hand to keep key order optional and output same as JavaScript
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::calcit::{Calcit, CalcitRecord};
+
+/// options for writing JSON
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonWriterOptions {
+  /// indent with 2 spaces, like `JSON.stringify(x, null, 2)`
+  pub pretty: bool,
+  /// sort keys of objects, so that output is stable
+  pub sort_keys: bool,
+}
+
+pub fn json_to_calcit(x: &Value, tag_keys: bool) -> Calcit {
+  match x {
+    Value::Null => Calcit::Nil,
+    Value::Bool(b) => Calcit::Bool(*b),
+    Value::Number(n) => Calcit::Number(n.as_f64().unwrap_or(f64::NAN)),
+    Value::String(s) => Calcit::new_str(s.to_owned()),
+    Value::Array(xs) => Calcit::from(xs.iter().map(|x| json_to_calcit(x, tag_keys)).collect::<Vec<_>>()),
+    Value::Object(xs) => {
+      let mut ys: rpds::HashTrieMapSync<Calcit, Calcit> = rpds::HashTrieMap::new_sync();
+      for (k, v) in xs {
+        let key = if tag_keys { Calcit::tag(k) } else { Calcit::new_str(k.to_owned()) };
+        ys.insert_mut(key, json_to_calcit(v, tag_keys));
+      }
+      Calcit::Map(ys)
+    }
+  }
+}
+
+pub fn format_json(x: &Calcit, options: JsonWriterOptions) -> Result<String, String> {
+  let mut buf = String::new();
+  write_json(&mut buf, x, options, 0)?;
+  Ok(buf)
+}
+
+fn write_json(buf: &mut String, x: &Calcit, options: JsonWriterOptions, depth: usize) -> Result<(), String> {
+  match x {
+    Calcit::Nil => buf.push_str("null"),
+    Calcit::Bool(b) => buf.push_str(&b.to_string()),
+    Calcit::Number(n) => {
+      if !n.is_finite() {
+        return Err(format!("JSON does not support number: {n}"));
+      }
+      buf.push_str(&js_number_str(*n))
+    }
+    Calcit::Str(s) => buf.push_str(&json_str(s)),
+    Calcit::Tag(t) => buf.push_str(&json_str(t.ref_str())),
+    Calcit::List(xs) => {
+      let items: Vec<&Calcit> = xs.iter().collect();
+      write_array(buf, &items, options, depth)?
+    }
+    Calcit::Set(xs) => {
+      // sets are unordered, sorted for stable output
+      let mut items: Vec<&Calcit> = xs.iter().collect();
+      items.sort();
+      write_array(buf, &items, options, depth)?
+    }
+    Calcit::Map(xs) => {
+      let mut pairs: Vec<(String, &Calcit)> = Vec::with_capacity(xs.size());
+      let mut seen: HashSet<String> = HashSet::with_capacity(xs.size());
+      for (k, v) in xs {
+        let key = json_key(k)?;
+        // a string key and a tag key of the same name would be written twice
+        if !seen.insert(key.to_owned()) {
+          return Err(format!("JSON object has duplicated key from a string and a tag: {key:?}"));
+        }
+        pairs.push((key, v));
+      }
+      write_object(buf, pairs, options, depth)?
+    }
+    Calcit::Record(CalcitRecord { fields, values, .. }) => {
+      let pairs = fields.iter().map(|f| f.to_string()).zip(values.iter()).collect();
+      write_object(buf, pairs, options, depth)?
+    }
+    Calcit::BigInt(n) => return Err(format!("JSON does not support bigint, turn it into a string first: {n}n")),
+    a => return Err(format!("JSON does not support value: {a}")),
+  }
+  Ok(())
+}
+
+fn json_key(x: &Calcit) -> Result<String, String> {
+  match x {
+    Calcit::Str(s) => Ok((**s).to_owned()),
+    Calcit::Tag(t) => Ok(t.to_string()),
+    a => Err(format!("JSON object keys should be strings or tags, got: {a}")),
+  }
+}
+
+fn write_array(buf: &mut String, xs: &[&Calcit], options: JsonWriterOptions, depth: usize) -> Result<(), String> {
+  if xs.is_empty() {
+    buf.push_str("[]");
+    return Ok(());
+  }
+  buf.push('[');
+  for (idx, x) in xs.iter().enumerate() {
+    if idx > 0 {
+      buf.push(',');
+    }
+    write_indent(buf, options, depth + 1);
+    write_json(buf, x, options, depth + 1)?;
+  }
+  write_indent(buf, options, depth);
+  buf.push(']');
+  Ok(())
+}
+
+fn write_object(buf: &mut String, mut pairs: Vec<(String, &Calcit)>, options: JsonWriterOptions, depth: usize) -> Result<(), String> {
+  if pairs.is_empty() {
+    buf.push_str("{}");
+    return Ok(());
+  }
+  if options.sort_keys {
+    // compared in UTF-16 code units, same as strings in JavaScript
+    pairs.sort_by(|a, b| a.0.encode_utf16().cmp(b.0.encode_utf16()));
+  }
+  buf.push('{');
+  for (idx, (k, v)) in pairs.iter().enumerate() {
+    if idx > 0 {
+      buf.push(',');
+    }
+    write_indent(buf, options, depth + 1);
+    buf.push_str(&json_str(k));
+    buf.push_str(if options.pretty { ": " } else { ":" });
+    write_json(buf, v, options, depth + 1)?;
+  }
+  write_indent(buf, options, depth);
+  buf.push('}');
+  Ok(())
+}
+
+fn write_indent(buf: &mut String, options: JsonWriterOptions, depth: usize) {
+  if options.pretty {
+    buf.push('\n');
+    buf.push_str(&"  ".repeat(depth));
+  }
+}
+
+/// number written like `Number.prototype.toString` in JavaScript, for example `1e+21` and `1e-7`
+fn js_number_str(n: f64) -> String {
+  // JavaScript writes `-0` as `0`
+  if n == 0.0 {
+    return String::from("0");
+  }
+  // shortest digits that round-trip, same digits as JavaScript picks
+  let sci = format!("{:e}", n.abs());
+  let (mantissa, exp) = sci.split_once('e').expect("scientific notation");
+  let digits: String = mantissa.chars().filter(|c| *c != '.').collect();
+  let k = digits.len() as i32;
+  // position of decimal point, relative to start of digits
+  let point = exp.parse::<i32>().expect("exponent") + 1;
+  let body = if k <= point && point <= 21 {
+    format!("{digits}{}", "0".repeat((point - k) as usize))
+  } else if 0 < point && point <= 21 {
+    format!("{}.{}", &digits[..point as usize], &digits[point as usize..])
+  } else if -6 < point && point <= 0 {
+    format!("0.{}{digits}", "0".repeat(-point as usize))
+  } else {
+    let sign = if point > 0 { '+' } else { '-' };
+    let e = (point - 1).abs();
+    if k == 1 {
+      format!("{digits}e{sign}{e}")
+    } else {
+      format!("{}.{}e{sign}{e}", &digits[..1], &digits[1..])
+    }
+  };
+  if n < 0.0 {
+    format!("-{body}")
+  } else {
+    body
+  }
+}
+
+/// string literal in JSON, escaped like `JSON.stringify` does
+fn json_str(s: &str) -> String {
+  let mut result = String::from('"');
+  for c in s.chars() {
+    match c {
+      '"' => result.push_str("\\\""),
+      '\\' => result.push_str("\\\\"),
+      '\n' => result.push_str("\\n"),
+      '\r' => result.push_str("\\r"),
+      '\t' => result.push_str("\\t"),
+      '\u{8}' => result.push_str("\\b"),
+      '\u{c}' => result.push_str("\\f"),
+      c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+      c => result.push(c),
+    }
+  }
+  result.push('"');
+  result
+}
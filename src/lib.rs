@@ -13,8 +13,9 @@ pub mod util;
 use calcit::LocatedWarning;
 use call_stack::CallStackList;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 pub use calcit::{Calcit, CalcitErr};
@@ -56,6 +57,7 @@ pub fn run_program(init_ns: Arc<str>, init_def: Arc<str>, params: &[Calcit]) ->
   if !warnings.is_empty() {
     return Err(CalcitErr {
       msg: format!("Found {} warnings, runner blocked", warnings.len()),
+      data: None,
       warnings: warnings.to_owned(),
       stack: CallStackList::default(),
       location: None,
@@ -79,21 +81,122 @@ pub fn run_program(init_ns: Arc<str>, init_def: Arc<str>, params: &[Calcit]) ->
   }
 }
 
-pub fn load_module(path: &str, base_dir: &Path, module_folder: &Path) -> Result<snapshot::Snapshot, String> {
+/// shared modules folder at `~/.config/calcit/modules/`, used when a project does not set `:modules-dir`
+pub fn global_module_folder() -> Result<PathBuf, String> {
+  dirs::home_dir()
+    .map(|buf| buf.join(".config/calcit/modules/"))
+    .ok_or_else(|| String::from("failed to load $HOME"))
+}
+
+/// modules folder of a project, from `:modules-dir` of `deps.cirru` in `base_dir`, relative to that file.
+/// falls back to the shared modules folder
+pub fn find_module_folder(base_dir: &Path) -> Result<PathBuf, String> {
+  let deps_file = base_dir.join("deps.cirru");
+  if deps_file.exists() {
+    let content = fs::read_to_string(&deps_file).map_err(|e| format!("failed to read {}: {e}", deps_file.display()))?;
+    if let Some(dir) = read_modules_dir(&cirru_edn::parse(&content)?.view_map()?)? {
+      return Ok(base_dir.join(&*dir));
+    }
+  }
+  global_module_folder()
+}
+
+/// `:modules-dir` from data of `deps.cirru`, `None` when not set
+pub fn read_modules_dir(data: &cirru_edn::EdnMapView) -> Result<Option<Arc<str>>, String> {
+  match data.get_or_nil("modules-dir") {
+    cirru_edn::Edn::Nil => Ok(None),
+    cirru_edn::Edn::Str(s) => Ok(Some(s)),
+    v => Err(format!("expected a string for :modules-dir, got: {v}")),
+  }
+}
+
+/// sha256 of a file in hex
+pub fn file_sha256(path: &Path) -> Result<String, String> {
+  use sha2::{Digest, Sha256};
+  let content = fs::read(path).map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+  Ok(hex::encode(Sha256::digest(&content)))
+}
+
+/// sha256 of modules recorded by `caps` in `:integrity` of `deps.lock.cirru` in `base_dir`,
+/// keyed by paths inside modules folder, e.g. `memof/compact.cirru`. `None` when there's no lockfile
+pub fn read_module_hashes(base_dir: &Path) -> Result<Option<HashMap<String, String>>, String> {
+  let lock_file = base_dir.join("deps.lock.cirru");
+  let mut hashes = HashMap::new();
+  if !lock_file.exists() {
+    return Ok(None);
+  }
+  let content = fs::read_to_string(&lock_file).map_err(|e| format!("failed to read {}: {e}", lock_file.display()))?;
+  let data = cirru_edn::parse(&content)?;
+  match data.view_map()?.get_or_nil("integrity") {
+    cirru_edn::Edn::Nil => {}
+    v => {
+      for (k, v) in &v.view_map()?.0 {
+        match (k, v) {
+          (cirru_edn::Edn::Str(k), cirru_edn::Edn::Str(v)) => {
+            hashes.insert(k.to_string(), v.to_string());
+          }
+          _ => return Err(format!("invalid integrity entry in {}: {k} {v}", lock_file.display())),
+        }
+      }
+    }
+  }
+  Ok(Some(hashes))
+}
+
+/// a module from modules folder should have the content recorded in lockfile.
+/// checks are skipped without a lockfile, modules missing in lockfile are warned,
+/// except for local folders linked by `caps` since they change during development
+pub fn verify_module_hash(path: &str, module_folder: &Path, hashes: Option<&HashMap<String, String>>) -> Result<(), String> {
+  let Some(hashes) = hashes else {
+    return Ok(());
+  };
+  if path.starts_with("./") || path.starts_with('/') {
+    return Ok(());
+  }
+  let file_path = if path.ends_with('/') {
+    format!("{path}compact.cirru")
+  } else {
+    path.to_owned()
+  };
+  if let Some(expected) = hashes.get(&file_path) {
+    let actual = file_sha256(&module_folder.join(&file_path))?;
+    if &actual != expected {
+      return Err(format!(
+        "module {file_path} does not match deps.lock.cirru, expected sha256 {expected}, got {actual}. remove it from {} and run `caps` to install again",
+        module_folder.display()
+      ));
+    }
+  } else {
+    let folder = file_path.split('/').next().unwrap_or(&file_path);
+    let linked = fs::symlink_metadata(module_folder.join(folder)).is_ok_and(|m| m.file_type().is_symlink());
+    if !linked {
+      eprintln!("[Warn] module {file_path} is not recorded in deps.lock.cirru, run `caps` to record its sha256");
+    }
+  }
+  Ok(())
+}
+
+/// resolve path of a module declared in `:modules`,
+/// `./` for relative paths, `/` for absolute paths, otherwise from modules folder
+pub fn resolve_module_path(path: &str, base_dir: &Path, module_folder: &Path) -> PathBuf {
   let mut file_path = String::from(path);
   if file_path.ends_with('/') {
     file_path.push_str("compact.cirru");
   }
 
-  let fullpath = if file_path.starts_with("./") {
+  if file_path.starts_with("./") {
     base_dir.join(&file_path).as_path().to_owned()
   } else if file_path.starts_with('/') {
     Path::new(&file_path).to_owned()
   } else {
     module_folder.join(&file_path).as_path().to_owned()
-  };
+  }
+}
 
-  println!("loading: {}", file_path.as_str());
+pub fn load_module(path: &str, base_dir: &Path, module_folder: &Path) -> Result<snapshot::Snapshot, String> {
+  let fullpath = resolve_module_path(path, base_dir, module_folder);
+
+  println!("loading: {path}{}", if path.ends_with('/') { "compact.cirru" } else { "" });
 
   let mut content = fs::read_to_string(&fullpath).unwrap_or_else(|_| panic!("expected Cirru snapshot {fullpath:?}"));
   strip_shebang(&mut content);
@@ -102,3 +205,44 @@ pub fn load_module(path: &str, base_dir: &Path, module_folder: &Path) -> Result<
   let snapshot = snapshot::load_snapshot_data(&data, &fullpath.display().to_string())?;
   Ok(snapshot)
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn verify_modules_with_lockfile() -> Result<(), String> {
+    let root = std::env::temp_dir().join(format!("calcit-module-hashes-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&root);
+    let modules = root.join("modules");
+    fs::create_dir_all(modules.join("memof")).map_err(|e| e.to_string())?;
+    fs::create_dir_all(modules.join("unrecorded")).map_err(|e| e.to_string())?;
+    fs::write(modules.join("memof/compact.cirru"), "{}").map_err(|e| e.to_string())?;
+    fs::write(modules.join("unrecorded/compact.cirru"), "{}").map_err(|e| e.to_string())?;
+
+    // no lockfile, no checks
+    assert_eq!(read_module_hashes(&root)?, None);
+    verify_module_hash("memof/", &modules, None)?;
+
+    let hash = file_sha256(&modules.join("memof/compact.cirru"))?;
+    let lock = format!("{{}} (:integrity $ {{}} (|memof/compact.cirru |{hash}))");
+    fs::write(root.join("deps.lock.cirru"), lock).map_err(|e| e.to_string())?;
+    let hashes = read_module_hashes(&root)?.expect("hashes from lockfile");
+    assert_eq!(hashes.get("memof/compact.cirru"), Some(&hash));
+    verify_module_hash("memof/", &modules, Some(&hashes))?;
+    verify_module_hash("memof/compact.cirru", &modules, Some(&hashes))?;
+    // only warned
+    verify_module_hash("unrecorded/", &modules, Some(&hashes))?;
+    #[cfg(unix)]
+    {
+      std::os::unix::fs::symlink(modules.join("unrecorded"), modules.join("linked")).map_err(|e| e.to_string())?;
+      verify_module_hash("linked/", &modules, Some(&hashes))?;
+    }
+
+    fs::write(modules.join("memof/compact.cirru"), "{} (:package |memof)").map_err(|e| e.to_string())?;
+    let e = verify_module_hash("memof/", &modules, Some(&hashes)).unwrap_err();
+    assert!(e.contains("does not match deps.lock.cirru"), "{e}");
+
+    fs::remove_dir_all(root).map_err(|e| e.to_string())
+  }
+}
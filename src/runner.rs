@@ -1,4 +1,7 @@
+pub mod debug;
+pub mod event_loop;
 pub mod preprocess;
+pub mod profile;
 pub mod track;
 
 use std::sync::Arc;
@@ -20,10 +23,12 @@ pub fn evaluate_expr(expr: &Calcit, scope: &CalcitScope, file_ns: &str, call_sta
     Calcit::Nil
     | Calcit::Bool(_)
     | Calcit::Number(_)
+    | Calcit::BigInt(_)
     | Calcit::Registered(_)
     | Calcit::Tag(_)
     | Calcit::Str(_)
     | Calcit::Ref(..)
+    | Calcit::Promise(..)
     | Calcit::Tuple { .. }
     | Calcit::Buffer(..)
     | Calcit::CirruQuote(..)
@@ -46,6 +51,9 @@ pub fn evaluate_expr(expr: &Calcit, scope: &CalcitScope, file_ns: &str, call_sta
       Some(x) => {
         // println!("eval expr: {}", expr.lisp_str());
         // println!("eval expr x: {}", x);
+        if debug::is_debugging() {
+          debug::on_expr(expr, scope, call_stack);
+        }
 
         if x.is_expr_evaluated() {
           call_expr(x, xs, scope, file_ns, call_stack, false)
@@ -80,9 +88,11 @@ pub fn call_expr(
       } else {
         evaluate_args(rest_nodes, scope, file_ns, call_stack)?
       };
+      let _profile = profile::enter(CORE_NS, p.as_ref());
       builtins::handle_proc(*p, &values, call_stack)
     }
     Calcit::Syntax(s, def_ns) => {
+      let _profile = profile::enter(def_ns, s.as_ref());
       if using_stack() {
         let next_stack = call_stack.extend(def_ns, s.as_ref(), StackKind::Syntax, &Calcit::from(xs), &rest_nodes.to_vec());
         builtins::handle_syntax(s, &rest_nodes, scope, file_ns, &next_stack).map_err(|e| {
@@ -105,6 +115,7 @@ pub fn call_expr(
         } else {
           evaluate_args(rest_nodes, scope, file_ns, call_stack)?
         };
+        let _profile = profile::enter(file_ns, name);
         if using_stack() {
           let next_stack = call_stack.extend(file_ns, name, StackKind::Method, &Calcit::Nil, &values);
           builtins::meta::invoke_method(name, &values, &next_stack)
@@ -134,6 +145,7 @@ pub fn call_expr(
         &Calcit::from(xs.to_owned()).lisp_str()
       );
 
+      let _profile = profile::enter(&info.def_ns, &info.name);
       let next_stack = if using_stack() {
         call_stack.extend(&info.def_ns, &info.name, StackKind::Macro, &Calcit::from(xs), &rest_nodes.to_vec())
       } else {
@@ -342,6 +354,7 @@ pub fn eval_symbol_from_program(sym: &str, ns: &str, call_stack: &CallStackList)
 }
 
 pub fn run_fn(values: &[Calcit], info: &CalcitFn, call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  let _profile = profile::enter(&info.def_ns, &info.name);
   let mut body_scope = (*info.scope).to_owned();
   match &*info.args {
     CalcitFnArgs::Args(args) => {
@@ -354,6 +367,9 @@ pub fn run_fn(values: &[Calcit], info: &CalcitFn, call_stack: &CallStackList) ->
     }
     CalcitFnArgs::MarkedArgs(args) => bind_marked_args(&mut body_scope, args, values, call_stack)?,
   }
+  if debug::is_debugging() {
+    debug::on_fn_enter(info, &body_scope, call_stack);
+  }
 
   let v = evaluate_lines(&info.body.to_vec(), &body_scope, &info.def_ns, call_stack)?;
 
@@ -383,6 +399,7 @@ pub fn run_fn(values: &[Calcit], info: &CalcitFn, call_stack: &CallStackList) ->
 
 /// quick path for `run_fn` which takes ownership of values
 pub fn run_fn_owned(values: Vec<Calcit>, info: &CalcitFn, call_stack: &CallStackList) -> Result<Calcit, CalcitErr> {
+  let _profile = profile::enter(&info.def_ns, &info.name);
   let mut body_scope = (*info.scope).to_owned();
   match &*info.args {
     CalcitFnArgs::Args(args) => {
@@ -395,6 +412,9 @@ pub fn run_fn_owned(values: Vec<Calcit>, info: &CalcitFn, call_stack: &CallStack
     }
     CalcitFnArgs::MarkedArgs(args) => bind_marked_args(&mut body_scope, args, &values, call_stack)?,
   }
+  if debug::is_debugging() {
+    debug::on_fn_enter(info, &body_scope, call_stack);
+  }
 
   let v = evaluate_lines(&info.body, &body_scope, &info.def_ns, call_stack)?;
 
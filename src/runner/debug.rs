@@ -0,0 +1,217 @@
+//! pausing evaluation at breakpoints and steps, the interface is provided by a handler from the binary
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+
+use crate::calcit::{Calcit, CalcitFn, CalcitScope, NodeLocation, CORE_NS};
+use crate::call_stack::{CallStackList, StackKind};
+
+static DEBUGGING: AtomicBool = AtomicBool::new(false);
+static DEBUG_STATE: LazyLock<Mutex<DebugState>> = LazyLock::new(|| Mutex::new(DebugState::default()));
+
+/// called when evaluation pauses, returns how to resume
+pub type DebugHandler = fn(&DebugPause) -> StepAction;
+
+/// pause at a def when it's called, or at an expression of it with coord
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+  pub ns: Arc<str>,
+  pub def: Arc<str>,
+  pub coord: Option<Vec<u8>>,
+}
+
+impl Breakpoint {
+  /// parse from `ns/def` or `ns/def 1-2-0`, same as how locations are displayed
+  pub fn parse(s: &str) -> Result<Self, String> {
+    let (ns_def, coord) = match s.trim().split_once(' ') {
+      Some((a, b)) => (a, Some(b.trim())),
+      None => (s.trim(), None),
+    };
+    let (ns, def) = match ns_def.rsplit_once('/') {
+      Some((ns, def)) if !ns.is_empty() && !def.is_empty() => (ns, def),
+      _ => return Err(format!("expected breakpoint in `ns/def` or `ns/def 1-2`, got: {s}")),
+    };
+    let coord = match coord {
+      Some(c) => {
+        let mut xs: Vec<u8> = vec![];
+        for x in c.split('-') {
+          xs.push(x.parse::<u8>().map_err(|e| format!("invalid coord `{c}` in breakpoint: {e}"))?);
+        }
+        Some(xs)
+      }
+      None => None,
+    };
+    Ok(Breakpoint {
+      ns: Arc::from(ns),
+      def: Arc::from(def),
+      coord,
+    })
+  }
+
+  /// breakpoint without coord matches entering of the function
+  fn matches(&self, location: &NodeLocation) -> bool {
+    self.ns == location.ns && self.def == location.def && self.coord.as_deref().unwrap_or_default() == &**location.coord
+  }
+}
+
+impl fmt::Display for Breakpoint {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}/{}", self.ns, self.def)?;
+    if let Some(coord) = &self.coord {
+      write!(f, " {}", coord.iter().map(|x| x.to_string()).collect::<Vec<_>>().join("-"))?;
+    }
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepAction {
+  Continue,
+  /// pause at next expression
+  StepInto,
+  /// pause at next expression outside current one
+  StepOver,
+  /// pause after current function returns
+  StepOut,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+  Breakpoint,
+  Step,
+}
+
+/// what's visible to the handler during a pause
+pub struct DebugPause<'a> {
+  pub reason: PauseReason,
+  pub location: NodeLocation,
+  /// expression about to be evaluated, or the call expression when entering a function
+  pub expr: &'a Calcit,
+  pub scope: &'a CalcitScope,
+  pub call_stack: &'a CallStackList,
+}
+
+#[derive(Debug, Clone, Default)]
+enum Stepping {
+  #[default]
+  Off,
+  Into,
+  /// depth of stack, and the expression to skip
+  Over(usize, NodeLocation),
+  /// depth of stack of the function
+  Out(usize),
+}
+
+#[derive(Default)]
+struct DebugState {
+  breakpoints: Vec<Breakpoint>,
+  stepping: Stepping,
+  handler: Option<DebugHandler>,
+}
+
+/// turn on debugging, pauses at first expression when no breakpoints given
+pub fn start_debugging(breakpoints: Vec<Breakpoint>, handler: DebugHandler) {
+  let mut state = DEBUG_STATE.lock().expect("open debug state");
+  state.stepping = if breakpoints.is_empty() { Stepping::Into } else { Stepping::Off };
+  state.breakpoints = breakpoints;
+  state.handler = Some(handler);
+  DEBUGGING.store(true, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn is_debugging() -> bool {
+  DEBUGGING.load(Ordering::Relaxed)
+}
+
+pub fn add_breakpoint(breakpoint: Breakpoint) {
+  let mut state = DEBUG_STATE.lock().expect("open debug state");
+  if !state.breakpoints.contains(&breakpoint) {
+    state.breakpoints.push(breakpoint);
+  }
+}
+
+/// returns `false` if not found
+pub fn remove_breakpoint(breakpoint: &Breakpoint) -> bool {
+  let mut state = DEBUG_STATE.lock().expect("open debug state");
+  let size = state.breakpoints.len();
+  state.breakpoints.retain(|b| b != breakpoint);
+  state.breakpoints.len() < size
+}
+
+pub fn list_breakpoints() -> Vec<Breakpoint> {
+  DEBUG_STATE.lock().expect("open debug state").breakpoints.to_owned()
+}
+
+/// check before evaluating an expression
+pub fn on_expr(expr: &Calcit, scope: &CalcitScope, call_stack: &CallStackList) {
+  // generated code and core are not paused at
+  if let Some(location) = expr.expr_location() {
+    check(location, expr, scope, call_stack);
+  }
+}
+
+/// check after arguments are bound to a function, `call_stack` is expected to contain the function
+pub fn on_fn_enter(info: &CalcitFn, scope: &CalcitScope, call_stack: &CallStackList) {
+  if &*info.def_ns != CORE_NS {
+    let location = NodeLocation::new(info.def_ns.to_owned(), info.name.to_owned(), Arc::new(vec![]));
+    let expr = call_stack.0.first().map(|s| s.code.to_owned()).unwrap_or(Calcit::Nil);
+    check(location, &expr, scope, call_stack);
+  }
+}
+
+fn check(location: NodeLocation, expr: &Calcit, scope: &CalcitScope, call_stack: &CallStackList) {
+  let depth = call_stack.len();
+  let reason = {
+    let state = DEBUG_STATE.lock().expect("open debug state");
+    let stepped = match &state.stepping {
+      Stepping::Off => false,
+      Stepping::Into => true,
+      Stepping::Over(d, current) => depth < *d || (depth == *d && !is_inside(&location, current)),
+      Stepping::Out(d) => depth < *d,
+    };
+    if stepped {
+      PauseReason::Step
+    } else if state.breakpoints.iter().any(|b| b.matches(&location)) {
+      PauseReason::Breakpoint
+    } else {
+      return;
+    }
+  };
+  pause(DebugPause {
+    reason,
+    location,
+    expr,
+    scope,
+    call_stack,
+  });
+}
+
+fn pause(p: DebugPause) {
+  let handler = {
+    let mut state = DEBUG_STATE.lock().expect("open debug state");
+    state.stepping = Stepping::Off;
+    state.handler
+  };
+  // lock is released, so handler could modify breakpoints
+  let action = match handler {
+    Some(f) => f(&p),
+    None => StepAction::Continue,
+  };
+  let depth = p.call_stack.len();
+  let stepping = match action {
+    StepAction::Continue => Stepping::Off,
+    StepAction::StepInto => Stepping::Into,
+    StepAction::StepOver => Stepping::Over(depth, p.location.to_owned()),
+    StepAction::StepOut => {
+      // depth where the function was entered, syntax frames like `let` are skipped
+      let fn_depth = p.call_stack.0.iter().position(|s| s.kind == StackKind::Fn).map(|idx| depth - idx);
+      Stepping::Out(fn_depth.unwrap_or(depth))
+    }
+  };
+  DEBUG_STATE.lock().expect("open debug state").stepping = stepping;
+}
+
+fn is_inside(location: &NodeLocation, parent: &NodeLocation) -> bool {
+  location.ns == parent.ns && location.def == parent.def && location.coord.starts_with(&parent.coord)
+}
@@ -0,0 +1,196 @@
+//! event loop in main thread, callbacks of timers, promises and other threads run one at a time.
+//! loop exits when there are no jobs, no timers, and no pending tasks in `track`.
+//! `await` runs the loop nested inside a job, so jobs and timers are re-entrant,
+//! except for top-level jobs like reloading code, which wait until the outermost loop
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::calcit::{CalcitErr, CalcitFn};
+use crate::call_stack::{display_stack, CallStackList};
+use crate::runner;
+
+use super::track;
+
+pub type Job = Box<dyn FnOnce() + Send>;
+
+struct Timer {
+  id: usize,
+  due: Instant,
+  /// only for `set-interval`
+  interval: Option<Duration>,
+  callback: Arc<CalcitFn>,
+  call_stack: CallStackList,
+}
+
+#[derive(Default)]
+struct LoopState {
+  jobs: VecDeque<Job>,
+  /// only taken by the outermost loop
+  top_level_jobs: VecDeque<Job>,
+  timers: Vec<Timer>,
+  next_timer_id: usize,
+}
+
+static LOOP_STATE: LazyLock<Mutex<LoopState>> = LazyLock::new(|| Mutex::new(LoopState::default()));
+/// notified when jobs, timers or tasks are changed
+static LOOP_WAKER: Condvar = Condvar::new();
+/// number of `run_until` calls on the stack, more than 1 inside `await`
+static LOOP_DEPTH: AtomicUsize = AtomicUsize::new(0);
+
+/// add a job to the end of the queue, safe to call from other threads
+pub fn enqueue(job: Job) {
+  let mut state = LOOP_STATE.lock().expect("lock event loop");
+  state.jobs.push_back(job);
+  LOOP_WAKER.notify_all();
+}
+
+/// add a job that only runs in the outermost loop, not inside `await` of another job.
+/// for jobs replacing program states, such as reloading code
+pub fn enqueue_top_level(job: Job) {
+  let mut state = LOOP_STATE.lock().expect("lock event loop");
+  state.top_level_jobs.push_back(job);
+  LOOP_WAKER.notify_all();
+}
+
+/// wake the loop to check states again
+pub fn wake() {
+  let _state = LOOP_STATE.lock().expect("lock event loop");
+  LOOP_WAKER.notify_all();
+}
+
+/// returns id of the timer
+pub fn add_timer(delay: Duration, repeat: bool, callback: Arc<CalcitFn>, call_stack: &CallStackList) -> usize {
+  let mut state = LOOP_STATE.lock().expect("lock event loop");
+  state.next_timer_id += 1;
+  let id = state.next_timer_id;
+  state.timers.push(Timer {
+    id,
+    due: Instant::now() + delay,
+    interval: if repeat { Some(delay) } else { None },
+    callback,
+    call_stack: call_stack.to_owned(),
+  });
+  LOOP_WAKER.notify_all();
+  id
+}
+
+/// returns false if the timer is already finished or cleared
+pub fn clear_timer(id: usize) -> bool {
+  let mut state = LOOP_STATE.lock().expect("lock event loop");
+  let size = state.timers.len();
+  state.timers.retain(|t| t.id != id);
+  state.timers.len() < size
+}
+
+/// errors in callbacks are displayed, they do not stop the loop
+pub fn report_error(source: &str, e: &CalcitErr) {
+  let _ = display_stack(&format!("[Error] {source} failed: {}", e.msg), &e.stack, e.location.as_ref());
+}
+
+/// queued jobs come first, then top-level jobs when not nested, then the earliest timer that is due
+fn take_job(state: &mut LoopState, nested: bool) -> Option<Job> {
+  if let Some(job) = state.jobs.pop_front() {
+    return Some(job);
+  }
+  if !nested {
+    if let Some(job) = state.top_level_jobs.pop_front() {
+      return Some(job);
+    }
+  }
+  let now = Instant::now();
+  let idx = state
+    .timers
+    .iter()
+    .enumerate()
+    .filter(|(_, t)| t.due <= now)
+    .min_by_key(|(_, t)| (t.due, t.id))
+    .map(|(idx, _)| idx)?;
+  let (callback, call_stack) = match state.timers[idx].interval {
+    Some(interval) => {
+      // scheduled again before running, so it can be cleared inside callback
+      let timer = &mut state.timers[idx];
+      timer.due = now + interval;
+      (timer.callback.to_owned(), timer.call_stack.to_owned())
+    }
+    None => {
+      let timer = state.timers.remove(idx);
+      (timer.callback, timer.call_stack)
+    }
+  };
+  Some(Box::new(move || {
+    if let Err(e) = runner::run_fn(&[], &callback, &call_stack) {
+      report_error("timer callback", &e);
+    }
+  }))
+}
+
+/// decreases depth when `run_until` returns
+struct DepthGuard;
+
+impl Drop for DepthGuard {
+  fn drop(&mut self) {
+    LOOP_DEPTH.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+/// runs jobs until `done` returns true. returns false when nothing is left to wait for,
+/// by then `done` would never turn true. it may be called again inside a job, which blocks that job
+/// while other jobs and timers run, top-level jobs are held back until the outer loop
+pub fn run_until(done: impl Fn() -> bool) -> bool {
+  let nested = LOOP_DEPTH.fetch_add(1, Ordering::SeqCst) > 0;
+  let _guard = DepthGuard;
+  loop {
+    if done() {
+      return true;
+    }
+    let job = {
+      let mut state = LOOP_STATE.lock().expect("lock event loop");
+      loop {
+        if let Some(job) = take_job(&mut state, nested) {
+          break job;
+        }
+        if state.timers.is_empty() && track::count_pending_tasks() == 0 {
+          return false;
+        }
+        state = match state.timers.iter().map(|t| t.due).min() {
+          Some(due) => {
+            let wait = due.saturating_duration_since(Instant::now());
+            LOOP_WAKER.wait_timeout(state, wait).expect("wait for event loop").0
+          }
+          None => LOOP_WAKER.wait(state).expect("wait for event loop"),
+        };
+      }
+    };
+    job();
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  static LOG: Mutex<Vec<&str>> = Mutex::new(vec![]);
+
+  fn log(s: &'static str) {
+    LOG.lock().expect("lock log").push(s);
+  }
+
+  fn logged() -> Vec<&'static str> {
+    LOG.lock().expect("lock log").to_owned()
+  }
+
+  #[test]
+  fn top_level_jobs_wait_for_nested_loops() {
+    enqueue(Box::new(|| {
+      enqueue_top_level(Box::new(|| log("reload")));
+      enqueue(Box::new(|| log("inner")));
+      assert!(run_until(|| logged().contains(&"inner")));
+      log("outer");
+    }));
+    assert!(run_until(|| logged().len() == 3));
+    assert_eq!(logged(), vec!["inner", "outer", "reload"]);
+  }
+}
@@ -43,10 +43,7 @@ pub fn preprocess_ns_def(
           let resolved_code = preprocess_expr(&code, &HashSet::new(), ns, check_warnings, &next_stack)?;
           // println!("\n resolve code to run: {:?}", resolved_code);
           let v = if is_fn_or_macro(&resolved_code) {
-            match runner::evaluate_expr(&resolved_code, &CalcitScope::default(), ns, &next_stack) {
-              Ok(ret) => ret,
-              Err(e) => return Err(e),
-            }
+            runner::evaluate_expr(&resolved_code, &CalcitScope::default(), ns, &next_stack)?
           } else {
             Calcit::Thunk(CalcitThunk::Code {
               code: Arc::new(resolved_code),
@@ -272,9 +269,13 @@ pub fn preprocess_expr(
         preprocess_list_call(xs, scope_defs, file_ns, check_warnings, call_stack)
       }
     }
-    Calcit::Number(..) | Calcit::Str(..) | Calcit::Nil | Calcit::Bool(..) | Calcit::Tag(..) | Calcit::CirruQuote(..) => {
-      Ok(expr.to_owned())
-    }
+    Calcit::Number(..)
+    | Calcit::BigInt(..)
+    | Calcit::Str(..)
+    | Calcit::Nil
+    | Calcit::Bool(..)
+    | Calcit::Tag(..)
+    | Calcit::CirruQuote(..) => Ok(expr.to_owned()),
     Calcit::Method(..) => Ok(expr.to_owned()),
     Calcit::Proc(..) => Ok(expr.to_owned()),
     Calcit::Syntax(..) => Ok(expr.to_owned()),
@@ -340,6 +341,7 @@ fn preprocess_list_call(
 
       let code = Calcit::List(Arc::new(xs.to_owned()));
       let next_stack = call_stack.extend(&info.def_ns, &info.name, StackKind::Macro, &code, &args.to_vec());
+      let _profile = runner::profile::enter(&info.def_ns, &info.name);
 
       let mut body_scope = CalcitScope::default();
 
@@ -0,0 +1,223 @@
+//! instrumenting profiler, records time of calls at the places where call stack is extended
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::Instant;
+
+static PROFILING: AtomicBool = AtomicBool::new(false);
+static CALL_TREE: LazyLock<Mutex<CallTree>> = LazyLock::new(|| Mutex::new(CallTree::default()));
+
+/// collapsed stacks, consumed by flamegraph tools
+pub const PROFILE_FILE: &str = ".calcit-profile.folded";
+
+thread_local! {
+  static OPEN_FRAMES: RefCell<Vec<OpenFrame>> = const { RefCell::new(vec![]) };
+}
+
+struct OpenFrame {
+  node: usize,
+  started: Instant,
+  /// time spent in children, in microseconds
+  children_time: u128,
+}
+
+/// calls merged by path from root, so recording does not need to build paths
+struct CallTree {
+  nodes: Vec<CallNode>,
+}
+
+struct CallNode {
+  ns: String,
+  def: String,
+  parent: Option<usize>,
+  /// by ns and def
+  children: HashMap<String, HashMap<String, usize>>,
+  calls: usize,
+  /// in microseconds
+  total: u128,
+  /// in microseconds, time excluding children
+  own: u128,
+}
+
+impl CallNode {
+  fn new(ns: &str, def: &str, parent: Option<usize>) -> Self {
+    CallNode {
+      ns: ns.to_owned(),
+      def: def.to_owned(),
+      parent,
+      children: HashMap::new(),
+      calls: 0,
+      total: 0,
+      own: 0,
+    }
+  }
+}
+
+impl Default for CallTree {
+  fn default() -> Self {
+    CallTree {
+      nodes: vec![CallNode::new("", "", None)],
+    }
+  }
+}
+
+impl CallTree {
+  fn child(&mut self, parent: usize, ns: &str, def: &str) -> usize {
+    if let Some(idx) = self.nodes[parent].children.get(ns).and_then(|xs| xs.get(def)) {
+      return *idx;
+    }
+    let idx = self.nodes.len();
+    self.nodes.push(CallNode::new(ns, def, Some(parent)));
+    self.nodes[parent]
+      .children
+      .entry(ns.to_owned())
+      .or_default()
+      .insert(def.to_owned(), idx);
+    idx
+  }
+
+  fn path(&self, idx: usize) -> Vec<usize> {
+    let mut xs = vec![];
+    let mut current = Some(idx);
+    while let Some(i) = current {
+      if i == 0 {
+        break;
+      }
+      xs.push(i);
+      current = self.nodes[i].parent;
+    }
+    xs.reverse();
+    xs
+  }
+
+  fn name(&self, idx: usize) -> String {
+    format!("{}/{}", self.nodes[idx].ns, self.nodes[idx].def)
+  }
+}
+
+/// summary of a function from all of its call paths
+#[derive(Debug, Clone, Default)]
+pub struct ProfileEntry {
+  pub calls: usize,
+  /// in microseconds, time in recursive calls is only counted once
+  pub total: u128,
+  /// in microseconds, time excluding children
+  pub own: u128,
+}
+
+pub fn start_profiling() {
+  PROFILING.store(true, Ordering::Relaxed);
+}
+
+#[inline(always)]
+pub fn is_profiling() -> bool {
+  PROFILING.load(Ordering::Relaxed)
+}
+
+/// clear records, used before running program again in watch mode
+pub fn reset_profile() {
+  OPEN_FRAMES.with_borrow_mut(|frames| frames.clear());
+  *CALL_TREE.lock().expect("open call tree") = CallTree::default();
+}
+
+/// records until dropped
+pub struct ProfileGuard(());
+
+/// starts recording a frame when profiling, keep the guard until the call finishes
+#[inline(always)]
+pub fn enter(ns: &str, def: &str) -> Option<ProfileGuard> {
+  if !is_profiling() {
+    return None;
+  }
+  OPEN_FRAMES.with_borrow_mut(|frames| {
+    let parent = frames.last().map(|f| f.node).unwrap_or(0);
+    let node = CALL_TREE.lock().expect("open call tree").child(parent, ns, def);
+    frames.push(OpenFrame {
+      node,
+      started: Instant::now(),
+      children_time: 0,
+    });
+  });
+  Some(ProfileGuard(()))
+}
+
+impl Drop for ProfileGuard {
+  fn drop(&mut self) {
+    OPEN_FRAMES.with_borrow_mut(|frames| {
+      let frame = match frames.pop() {
+        Some(f) => f,
+        None => return,
+      };
+      let elapsed = frame.started.elapsed().as_micros();
+      if let Some(parent) = frames.last_mut() {
+        parent.children_time += elapsed;
+      }
+      let mut tree = CALL_TREE.lock().expect("open call tree");
+      // tree might be reset during the call
+      if let Some(node) = tree.nodes.get_mut(frame.node) {
+        node.calls += 1;
+        node.total += elapsed;
+        node.own += elapsed.saturating_sub(frame.children_time);
+      }
+    });
+  }
+}
+
+/// recorded functions, sorted by time excluding children
+pub fn list_entries() -> Vec<(String, ProfileEntry)> {
+  let tree = CALL_TREE.lock().expect("open call tree");
+  let mut entries: HashMap<String, ProfileEntry> = HashMap::new();
+  for idx in 1..tree.nodes.len() {
+    let node = &tree.nodes[idx];
+    let name = tree.name(idx);
+    // time of recursive calls is already included in the outer one
+    let recursive = tree
+      .path(idx)
+      .iter()
+      .rev()
+      .skip(1)
+      .any(|i| tree.nodes[*i].ns == node.ns && tree.nodes[*i].def == node.def);
+    let entry = entries.entry(name).or_default();
+    entry.calls += node.calls;
+    entry.own += node.own;
+    if !recursive {
+      entry.total += node.total;
+    }
+  }
+  let mut xs: Vec<(String, ProfileEntry)> = entries.into_iter().collect();
+  xs.sort_by(|a, b| b.1.own.cmp(&a.1.own).then_with(|| a.0.cmp(&b.0)));
+  xs
+}
+
+/// write collapsed stacks to `PROFILE_FILE`, and print a table of hottest functions
+pub fn report_profile(limit: usize) -> Result<(), String> {
+  let content = {
+    let tree = CALL_TREE.lock().expect("open call tree");
+    let mut lines: Vec<String> = vec![];
+    for idx in 1..tree.nodes.len() {
+      let own = tree.nodes[idx].own;
+      if own > 0 {
+        let path: Vec<String> = tree.path(idx).into_iter().map(|i| tree.name(i)).collect();
+        lines.push(format!("{} {own}", path.join(";")));
+      }
+    }
+    lines.sort();
+    lines.join("\n")
+  };
+  fs::write(PROFILE_FILE, content).map_err(|e| format!("failed to write {PROFILE_FILE}: {e}"))?;
+
+  println!("\n{:>10} {:>10} {:>8}  name", "self(ms)", "total(ms)", "calls");
+  for (name, entry) in list_entries().iter().take(limit) {
+    println!(
+      "{:>10.3} {:>10.3} {:>8}  {name}",
+      entry.own as f64 / 1000.0,
+      entry.total as f64 / 1000.0,
+      entry.calls
+    );
+  }
+  println!("\ncollapsed stacks wrote to {PROFILE_FILE}, render with flamegraph tools.");
+  Ok(())
+}
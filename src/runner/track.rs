@@ -1,20 +1,13 @@
 use std::sync::atomic;
 use std::sync::atomic::AtomicUsize;
-use std::{thread, time};
+
+use super::event_loop;
 
 static TASK_COUNT: AtomicUsize = AtomicUsize::new(0);
 
+/// runs the event loop until jobs, timers and pending tasks are all cleared
 pub fn exit_when_cleared() {
-  let delay = time::Duration::from_millis(40);
-
-  // keep looping until remaining task size 0
-  loop {
-    if TASK_COUNT.load(atomic::Ordering::Relaxed) == 0 {
-      break;
-    } else {
-      thread::sleep(delay);
-    }
-  }
+  event_loop::run_until(|| false);
 }
 
 /// by default, watcher adds 1 task
@@ -28,4 +21,5 @@ pub fn track_task_add() {
 
 pub fn track_task_release() {
   TASK_COUNT.fetch_sub(1, atomic::Ordering::SeqCst);
+  event_loop::wake();
 }
@@ -1,4 +1,4 @@
-use cirru_edn::{Edn, EdnMapView, EdnRecordView, EdnSetView, EdnTag};
+use cirru_edn::{Edn, EdnListView, EdnMapView, EdnRecordView, EdnSetView, EdnTag};
 use cirru_parser::Cirru;
 use std::collections::hash_map::HashMap;
 use std::collections::hash_set::HashSet;
@@ -10,7 +10,40 @@ pub struct SnapshotConfigs {
   pub init_fn: Arc<str>,
   pub reload_fn: Arc<str>,
   pub modules: Vec<Arc<str>>,
+  /// project-local modules folder, relative to the snapshot file
+  pub modules_dir: Option<Arc<str>>,
   pub version: Arc<str>,
+  /// rules of `cr lint` turned on or off, by name
+  pub lint: HashMap<Arc<str>, LintRuleConfig>,
+}
+
+/// a rule of `cr lint` turned on or off, a list of `ns/def` keeps it on except in those defs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LintRuleConfig {
+  Enabled(bool),
+  SkippedIn(HashSet<Arc<str>>),
+}
+
+impl TryFrom<Edn> for LintRuleConfig {
+  type Error = String;
+  fn try_from(data: Edn) -> Result<Self, String> {
+    match data {
+      Edn::Bool(b) => Ok(LintRuleConfig::Enabled(b)),
+      Edn::List(EdnListView(xs)) => {
+        let mut defs: HashSet<Arc<str>> = HashSet::with_capacity(xs.len());
+        for x in &xs {
+          match x {
+            Edn::Str(s) => {
+              defs.insert(s.to_owned());
+            }
+            _ => return Err(format!("expected ns/def string to skip lint rule, got: {x}")),
+          }
+        }
+        Ok(LintRuleConfig::SkippedIn(defs))
+      }
+      _ => Err(format!("expected bool or list of defs for lint rule, got: {data}")),
+    }
+  }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -113,6 +146,23 @@ impl TryFrom<Edn> for SnapshotConfigs {
         Edn::Nil => vec![],
         v => v.try_into()?,
       },
+      modules_dir: crate::read_modules_dir(&data)?,
+      lint: match data.get_or_nil("lint") {
+        Edn::Nil => HashMap::new(),
+        Edn::Map(EdnMapView(xs)) => {
+          let mut rules: HashMap<Arc<str>, LintRuleConfig> = HashMap::with_capacity(xs.len());
+          for (k, v) in xs {
+            let name: Arc<str> = match k {
+              Edn::Tag(t) => t.arc_str(),
+              Edn::Str(s) => s,
+              _ => return Err(format!("expected tag for lint rule, got: {k}")),
+            };
+            rules.insert(name, v.try_into()?);
+          }
+          rules
+        }
+        v => return Err(format!("expected map for lint configs, got: {v}")),
+      },
     };
     Ok(c)
   }
@@ -168,6 +218,8 @@ impl Default for Snapshot {
         reload_fn: "app.main/reload!".into(),
         version: "0.0.0".into(),
         modules: vec![],
+        modules_dir: None,
+        lint: HashMap::new(),
       },
       entries: HashMap::new(),
       files: HashMap::new(),
@@ -206,6 +258,7 @@ pub struct FileChangeInfo {
 }
 
 impl From<&FileChangeInfo> for Edn {
+  #[allow(clippy::mutable_key_type)]
   fn from(data: &FileChangeInfo) -> Edn {
     let mut map = EdnMapView::default();
     if let Some(ns) = &data.ns {
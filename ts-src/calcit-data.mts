@@ -168,6 +168,7 @@ export var refsRegistry = new Map<string, CalcitRef>();
 
 let defaultHash_nil = valueHash("nil:");
 let defaultHash_number = valueHash("number:");
+let defaultHash_bigint = valueHash("bigint:");
 let defaultHash_string = valueHash("string:");
 let defaultHash_tag = valueHash("tag:");
 let defaultHash_true = valueHash("bool:true");
@@ -194,6 +195,9 @@ export let hashFunction = (x: CalcitValue): Hash => {
   if (typeof x === "number") {
     return mergeValueHash(defaultHash_number, x);
   }
+  if (typeof x === "bigint") {
+    return mergeValueHash(defaultHash_bigint, x.toString());
+  }
   if (typeof x === "string") {
     return mergeValueHash(defaultHash_string, x);
   }
@@ -357,6 +361,9 @@ export let toString = (x: CalcitValue, escaped: boolean, disableJsDataWarning: b
   if (typeof x === "number") {
     return x.toString();
   }
+  if (typeof x === "bigint") {
+    return escaped ? `${x}n` : x.toString();
+  }
   if (typeof x === "boolean") {
     return x.toString();
   }
@@ -407,7 +414,7 @@ export let to_js_data = (x: CalcitValue, addColon: boolean = false): any => {
   if (typeof x === "string") {
     return x;
   }
-  if (typeof x === "number") {
+  if (typeof x === "number" || typeof x === "bigint") {
     return x;
   }
   if (typeof x === "function") {
@@ -515,6 +522,10 @@ export let _$n__$e_ = (x: CalcitValue, y: CalcitValue): boolean => {
     // already checked above
     return false;
   }
+  if (tx === "bigint") {
+    // already checked above
+    return false;
+  }
   if (tx === "function") {
     // comparing functions by reference
     return x === y;
@@ -9,7 +9,7 @@ import { writeCirruCode } from "@cirru/writer.ts";
 import { CalcitValue } from "./js-primes.mjs";
 import { CalcitSymbol, CalcitTag, CalcitFn, CalcitRecur, newTag, refsRegistry, toString, getStringName, _$n__$e_, hashFunction } from "./calcit-data.mjs";
 
-import { CalcitRef } from "./js-ref.mjs";
+import { CalcitRef, atom } from "./js-ref.mjs";
 import { fieldsEqual, CalcitRecord } from "./js-record.mjs";
 
 export * from "./calcit-data.mjs";
@@ -21,6 +21,8 @@ export * from "./js-primes.mjs";
 export * from "./js-tuple.mjs";
 export * from "./custom-formatter.mjs";
 export * from "./js-cirru.mjs";
+export * from "./js-json.mjs";
+export * from "./js-datetime.mjs";
 export { _$n_compare } from "./js-primes.mjs";
 
 import { CalcitList, CalcitSliceList, foldl } from "./js-list.mjs";
@@ -38,6 +40,9 @@ export let type_of = (x: any): CalcitTag => {
   if (typeof x === "number") {
     return newTag("number");
   }
+  if (typeof x === "bigint") {
+    return newTag("bigint");
+  }
   if (x instanceof CalcitTag) {
     return newTag("tag");
   }
@@ -68,6 +73,9 @@ export let type_of = (x: any): CalcitTag => {
   if (x instanceof CalcitCirruQuote) {
     return newTag("cirru-quote");
   }
+  if (x instanceof Promise) {
+    return newTag("promise");
+  }
   if (x === true || x === false) {
     return newTag("bool");
   }
@@ -413,15 +421,69 @@ export let _$n_map_$o_dissoc = function (xs: CalcitValue, ...args: CalcitValue[]
   throw new Error("`dissoc` expected a map");
 };
 
+/** validator runs before writing, listeners taking 3 arguments also receive a map of `:key`, `:path` and `:op` */
+/** same as Rust side, unchanged value skips validator and watchers, all watchers run before the first error is thrown */
+let modifyRef = (op: string, a: CalcitRef, v: CalcitValue): void => {
+  let prev = a.value;
+  if (_$n__$e_(prev, v)) {
+    return;
+  }
+  if (a.validator != null) {
+    let ok = a.validator(v, prev);
+    if (ok == null || ok === false) {
+      throw new Error(`${op}! rejected by validator of ${a.path}, got: ${toString(v, true)}`);
+    }
+  }
+  a.value = v;
+  let failed = false;
+  let failure: unknown = null;
+  a.listeners.forEach((f, k) => {
+    try {
+      if (f.length === 3) {
+        f(v, prev, new CalcitSliceMap([newTag("key"), k, newTag("path"), a.path, newTag("op"), newTag(op)]));
+      } else {
+        f(v, prev);
+      }
+    } catch (error) {
+      if (!failed) {
+        failed = true;
+        failure = error;
+      }
+    }
+  });
+  if (failed) {
+    throw failure;
+  }
+};
+
 export let reset_$x_ = (a: CalcitRef, v: CalcitValue): null => {
   if (!(a instanceof CalcitRef)) {
     throw new Error("Expected ref for reset!");
   }
-  let prev = a.value;
-  a.value = v;
-  a.listeners.forEach((f) => {
-    f(v, prev);
-  });
+  modifyRef("reset", a, v);
+  return null;
+};
+
+export let _$n_atom_$o_swap_$x_ = (a: CalcitRef, f: CalcitFn, ...args: CalcitValue[]): CalcitValue => {
+  if (!(a instanceof CalcitRef)) {
+    throw new Error("Expected ref for swap!");
+  }
+  if (typeof f !== "function") {
+    throw new Error("Expected function for swap!");
+  }
+  let v = f(a.value, ...args);
+  modifyRef("swap", a, v);
+  return v;
+};
+
+export let set_validator_$x_ = (a: CalcitRef, f: CalcitFn): null => {
+  if (!(a instanceof CalcitRef)) {
+    throw new Error("Expected ref for set-validator!");
+  }
+  if (!(f == null || typeof f === "function")) {
+    throw new Error("Expected validator function or nil");
+  }
+  a.validator = f;
   return null;
 };
 
@@ -807,8 +869,11 @@ export let sin = (n: number) => {
 export let cos = (n: number) => {
   return Math.cos(n);
 };
-export let pow = (n: number, m: number) => {
-  return Math.pow(n, m);
+export let pow = (n: number | bigint, m: number | bigint) => {
+  if (typeof n === "bigint" && typeof m === "bigint") {
+    return n ** m;
+  }
+  return Math.pow(n as number, m as number);
 };
 export let ceil = (n: number) => {
   return Math.ceil(n);
@@ -927,7 +992,11 @@ export let trim = (x: string, c: string): string => {
   return x.trim();
 };
 
-export let _$n_number_$o_format = (x: number, n: number): string => {
+export let _$n_number_$o_format = (x: number | bigint, n: number): string => {
+  if (typeof x === "bigint") {
+    // no fractional part in bigints, zeros are padded
+    return n > 0 ? `${x}.${"0".repeat(n)}` : x.toString();
+  }
   return x.toFixed(n);
 };
 
@@ -1058,7 +1127,7 @@ export let turn_string = (x: CalcitValue): string => {
   if (x instanceof CalcitSymbol) {
     return x.value;
   }
-  if (typeof x === "number") {
+  if (typeof x === "number" || typeof x === "bigint") {
     return x.toString();
   }
   if (typeof x === "boolean") {
@@ -1406,6 +1475,31 @@ export let _$n_map_$o_common_keys = (a: CalcitValue, b: CalcitValue): CalcitSet
   }
 };
 
+/** turns an integer number or a string of digits into bigint */
+export let bigint = (x: CalcitValue): bigint => {
+  if (typeof x === "bigint") {
+    return x;
+  }
+  if (typeof x === "number") {
+    if (!Number.isInteger(x)) {
+      throw new Error(`bigint expected an integer, got: ${x}`);
+    }
+    return BigInt(x);
+  }
+  if (typeof x === "string") {
+    return BigInt(x.trim());
+  }
+  throw new Error(`bigint expected a number or a string, got: ${x}`);
+};
+
+/** nearest number, precision is lost for large values */
+export let _$n_bigint_$o_to_number = (x: bigint): number => {
+  if (typeof x !== "bigint") {
+    throw new Error(`&bigint:to-number expected a bigint, got: ${x}`);
+  }
+  return Number(x);
+};
+
 export let bit_shr = (base: number, step: number): number => {
   return base >> step;
 };
@@ -1425,6 +1519,110 @@ export let bit_not = (a: number): number => {
   return ~a;
 };
 
+/** mulberry32 in 32-bit integer operations, same sequence as in Rust for the same seed.
+ * returns a number in `[0, 1)` along with next state */
+let mulberry32 = (state: number): [number, number] => {
+  let next = (state + 0x6d2b79f5) >>> 0;
+  let t = next;
+  t = Math.imul(t ^ (t >>> 15), t | 1);
+  t ^= t + Math.imul(t ^ (t >>> 7), t | 61);
+  return [((t ^ (t >>> 14)) >>> 0) / 4294967296, next];
+};
+
+let seedState = (procName: string, seed: number): number => {
+  if (!Number.isInteger(seed)) {
+    throw new Error(`${procName} expected an integer seed, got: ${seed}`);
+  }
+  return seed >>> 0;
+};
+
+var globalRandState = (Math.random() * 4294967296) >>> 0;
+
+/** generator from `rand-gen` is optional in the first argument */
+let nextFloat = (procName: string, generator: CalcitRef): number => {
+  if (generator == null) {
+    let [x, next] = mulberry32(globalRandState);
+    globalRandState = next;
+    return x;
+  }
+  if (typeof generator.value !== "number") {
+    throw new Error(`${procName} expected a generator from rand-gen, got: ${generator.value}`);
+  }
+  let [x, next] = mulberry32(seedState(procName, generator.value));
+  generator.value = next;
+  return x;
+};
+
+/** generators are tuples of `:: :rand-gen ref`, refs from `atom` are not taken as generators */
+let splitGenerator = (xs: CalcitValue[]): [CalcitRef, CalcitValue[]] => {
+  let x = xs[0];
+  if (
+    x instanceof CalcitTuple &&
+    x.tag instanceof CalcitTag &&
+    x.tag.value === "rand-gen" &&
+    x.extra.length === 1 &&
+    x.extra[0] instanceof CalcitRef
+  ) {
+    return [x.extra[0], xs.slice(1)];
+  }
+  return [null, xs];
+};
+
+export let rand_gen = (seed: number): CalcitTuple => {
+  if (typeof seed !== "number") {
+    throw new Error(`rand-gen expected a number as seed, got: ${seed}`);
+  }
+  return new CalcitTuple(newTag("rand-gen"), [atom(seedState("rand-gen", seed))], null);
+};
+
+export let rand = (...xs: CalcitValue[]): number => {
+  let [generator, args] = splitGenerator(xs);
+  if (args.length === 0) {
+    return nextFloat("rand", generator);
+  }
+  if (typeof args[0] !== "number") {
+    throw new Error(`rand expected a number, got: ${args[0]}`);
+  }
+  return nextFloat("rand", generator) * args[0];
+};
+
+export let rand_int = (...xs: CalcitValue[]): number => {
+  let [generator, args] = splitGenerator(xs);
+  if (typeof args[0] !== "number") {
+    throw new Error(`rand-int expected a number, got: ${args[0]}`);
+  }
+  return Math.floor(nextFloat("rand-int", generator) * args[0]);
+};
+
+export let rand_nth = (...xs: CalcitValue[]): CalcitValue => {
+  let [generator, args] = splitGenerator(xs);
+  let ys = args[0];
+  if (!(ys instanceof CalcitList || ys instanceof CalcitSliceList)) {
+    throw new Error(`rand-nth expected a list, got: ${ys}`);
+  }
+  if (ys.len() === 0) {
+    return null;
+  }
+  return ys.get(Math.floor(nextFloat("rand-nth", generator) * ys.len()));
+};
+
+/** Fisher-Yates shuffle, swapping from the end of the list */
+export let shuffle = (...xs: CalcitValue[]): CalcitSliceList => {
+  let [generator, args] = splitGenerator(xs);
+  let ys = args[0];
+  if (!(ys instanceof CalcitList || ys instanceof CalcitSliceList)) {
+    throw new Error(`shuffle expected a list, got: ${ys}`);
+  }
+  let data = ys.toArray().slice();
+  for (let i = data.length - 1; i > 0; i--) {
+    let j = Math.floor(nextFloat("shuffle", generator) * (i + 1));
+    let tmp = data[i];
+    data[i] = data[j];
+    data[j] = tmp;
+  }
+  return new CalcitSliceList(data);
+};
+
 export let _$n_list_$o_to_set = (xs: CalcitList): CalcitSet => {
   var result: CalcitValue[] = [];
   let data = xs.toArray();
@@ -1456,6 +1654,101 @@ export let _$n_str_$o_pad_right = (s: string, size: number, pattern: string): st
   return s.padEnd(size, pattern);
 };
 
+/** patterns are compiled once, `u` flag is used to handle unicode like Rust does */
+let regexCache = new Map<string, RegExp>();
+
+let compileRegex = (pattern: string, flags: string): RegExp => {
+  let key = `${flags}/${pattern}`;
+  let re = regexCache.get(key);
+  if (re == null) {
+    if (regexCache.size >= 256) {
+      regexCache.clear();
+    }
+    re = new RegExp(pattern, flags);
+    regexCache.set(key, re);
+  }
+  return re;
+};
+
+export let re_matches = (s: string, pattern: string): boolean => {
+  return compileRegex(`^(?:${pattern})$`, "u").test(s);
+};
+
+/** first match in a list of whole match and capture groups, unmatched groups are nil */
+export let re_find = (s: string, pattern: string): CalcitSliceList => {
+  let m = compileRegex(pattern, "u").exec(s);
+  if (m == null) {
+    return null;
+  }
+  return new CalcitSliceList(Array.from(m, (x) => (x === undefined ? null : x)));
+};
+
+export let re_find_all = (s: string, pattern: string): CalcitSliceList => {
+  return new CalcitSliceList(Array.from(s.matchAll(compileRegex(pattern, "gu")), (m) => m[0]));
+};
+
+/** replaces all matches, `$1` `$<name>` `$&` and `$$` in replacement are expanded */
+export let re_replace = (s: string, pattern: string, replacement: string): string => {
+  return s.replace(compileRegex(pattern, "gu"), replacement);
+};
+
+/** splits by matches, unlike `String.prototype.split`, capture groups are not included in result */
+export let re_split = (s: string, pattern: string): CalcitSliceList => {
+  let pieces: string[] = [];
+  let last = 0;
+  for (let m of s.matchAll(compileRegex(pattern, "gu"))) {
+    pieces.push(s.slice(last, m.index));
+    last = m.index + m[0].length;
+  }
+  pieces.push(s.slice(last));
+  return new CalcitSliceList(pieces);
+};
+
+export let set_timeout = (ms: number, f: () => void) => {
+  return setTimeout(f, ms);
+};
+export let set_interval = (ms: number, f: () => void) => {
+  return setInterval(f, ms);
+};
+/** `clearTimeout` also clears intervals */
+export let clear_timer = (id: any): void => {
+  clearTimeout(id);
+};
+
+/** a native promise, with resolvers attached for `&promise:resolve` and `&promise:reject` */
+export let promise = (): Promise<CalcitValue> => {
+  let resolvers: any = {};
+  let p: any = new Promise((resolve, reject) => {
+    resolvers.resolve = resolve;
+    resolvers.reject = reject;
+  });
+  p.__calcitResolvers = resolvers;
+  return p;
+};
+let promiseResolvers = (p: any, name: string) => {
+  if (p instanceof Promise && (p as any).__calcitResolvers != null) {
+    return (p as any).__calcitResolvers;
+  }
+  throw new Error(`${name} expected a promise created by \`promise\`, got: ${toString(p, true)}`);
+};
+export let _$n_promise_$o_resolve = (p: Promise<CalcitValue>, v: CalcitValue = null): void => {
+  promiseResolvers(p, "&promise:resolve").resolve(v);
+};
+export let _$n_promise_$o_reject = (p: Promise<CalcitValue>, v: CalcitValue = null): void => {
+  promiseResolvers(p, "&promise:reject").reject(_calcit_make_error(v));
+};
+/** rejected reason is passed to `on-rejected` like in Rust, with data from `raise` */
+export let _$n_promise_$o_then = (
+  p: Promise<CalcitValue>,
+  f: (x: CalcitValue) => CalcitValue,
+  onRejected?: (x: CalcitValue) => CalcitValue
+): Promise<CalcitValue> => {
+  if (onRejected == null) {
+    return p.then(f);
+  }
+  return p.then(f, (err: any) => onRejected(err instanceof Error ? (err as any).data ?? err.message : err));
+};
+
 export let _$n_get_os = (): CalcitTag => {
   return newTag("js-engine");
 };
@@ -1514,6 +1807,22 @@ export let gensym = unavailableProc;
 export let macroexpand = unavailableProc;
 export let macroexpand_all = unavailableProc;
 export let _$n_get_calcit_running_mode = unavailableProc;
+export let append_file = unavailableProc;
+export let read_file_buffer = unavailableProc;
+export let write_file_buffer = unavailableProc;
+export let read_dir = unavailableProc;
+export let walk_dir = unavailableProc;
+export let path_exists_$q_ = unavailableProc;
+export let path_metadata = unavailableProc;
+export let create_dir = unavailableProc;
+export let remove_path = unavailableProc;
+export let rename_path = unavailableProc;
+export let run_command = unavailableProc;
+export let spawn_command = unavailableProc;
+export let kill_command = unavailableProc;
+export let write_command_stdin = unavailableProc;
+export let close_command_stdin = unavailableProc;
+export let persist_atoms_$x_ = unavailableProc;
 
 export let _calcit_args_mismatch = (name: string, expected: number, got: number) => {
   return new Error(`\`${name}\` expected ${expected} params, got ${got}`);
@@ -1521,3 +1830,35 @@ export let _calcit_args_mismatch = (name: string, expected: number, got: number)
 
 // already handled in code emitter
 export let raise = unavailableProc;
+
+/** used by `raise`, a value that is not a string is also taken as data */
+export let _calcit_make_error = (message: CalcitValue, data?: CalcitValue): Error => {
+  let err = new Error(typeof message === "string" ? message : toString(message, false));
+  (err as any).data = data !== undefined ? data : typeof message === "string" ? null : message;
+  return err;
+};
+
+/** record passed to handler of `try`, same fields as in Rust runtime. location is not tracked in js */
+export let _calcit_error_value = (err: any): CalcitRecord => {
+  let message: string;
+  let data: CalcitValue = null;
+  let stack: CalcitValue[] = [];
+  if (err instanceof Error) {
+    message = err.message;
+    data = (err as any).data ?? null;
+    for (let line of (err.stack ?? "").split("\n")) {
+      let trimmed = line.trim();
+      if (trimmed.startsWith("at ")) {
+        stack.push(new CalcitSliceMap([newTag("def"), trimmed.slice(3), newTag("kind"), newTag("js")]));
+      }
+    }
+  } else {
+    message = toString(err, false);
+    data = err;
+  }
+  return new CalcitRecord(
+    newTag("calcit-error"),
+    [newTag("data"), newTag("location"), newTag("message"), newTag("stack")],
+    [data, null, message, new CalcitSliceList(stack)]
+  );
+};
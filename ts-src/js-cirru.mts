@@ -77,6 +77,10 @@ export let to_cirru_edn = (x: CalcitValue): CirruEdnFormat => {
   if (typeof x === "number") {
     return `${x}`;
   }
+  if (typeof x === "bigint") {
+    // EDN has no integers
+    return ["::", "'bigint", `|${x}`];
+  }
   if (typeof x === "boolean") {
     return `${x}`;
   }
@@ -331,6 +335,10 @@ export let extract_cirru_edn = (x: CirruEdnFormat, options: CalcitValue): Calcit
       if (x.length < 2) {
         throw new Error(`tuple expects at least 1 value, got: ${x}`);
       }
+      let digits = x[2];
+      if (x.length === 3 && x[1] === "'bigint" && typeof digits === "string" && /^[\|"]-?\d+$/.test(digits)) {
+        return BigInt(digits.slice(1));
+      }
       return new CalcitTuple(
         extract_cirru_edn(x[1], options),
         x
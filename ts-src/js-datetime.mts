@@ -0,0 +1,282 @@
+import { CalcitValue } from "./js-primes.mjs";
+import { CalcitMap, CalcitSliceMap } from "./js-map.mjs";
+import { CalcitTag, newTag, toString } from "./calcit-data.mjs";
+
+// calendar in integer arithmetic, same as `src/builtins/datetime.rs`, system time zones are not used
+
+const MS_PER_MINUTE = 60000;
+const MS_PER_DAY = 86400000;
+/** 100,000,000 days before or after Unix epoch, same range as `Date` */
+const MAX_TIME = 8.64e15;
+
+interface TimeFields {
+  year: number;
+  month: number;
+  day: number;
+  hour: number;
+  minute: number;
+  second: number;
+  ms: number;
+  offset: number;
+}
+
+let mod = (a: number, b: number): number => a - Math.floor(a / b) * b;
+
+/** days since 1970-01-01, from http://howardhinnant.github.io/date_algorithms.html */
+let daysFromCivil = (year: number, month: number, day: number): number => {
+  let y = month <= 2 ? year - 1 : year;
+  let era = Math.floor(y / 400);
+  let yoe = y - era * 400;
+  let doy = Math.floor((153 * (month > 2 ? month - 3 : month + 9) + 2) / 5) + day - 1;
+  let doe = yoe * 365 + Math.floor(yoe / 4) - Math.floor(yoe / 100) + doy;
+  return era * 146097 + doe - 719468;
+};
+
+let civilFromDays = (days: number): [number, number, number] => {
+  let z = days + 719468;
+  let era = Math.floor(z / 146097);
+  let doe = z - era * 146097;
+  let yoe = Math.floor((doe - Math.floor(doe / 1460) + Math.floor(doe / 36524) - Math.floor(doe / 146096)) / 365);
+  let doy = doe - (365 * yoe + Math.floor(yoe / 4) - Math.floor(yoe / 100));
+  let mp = Math.floor((5 * doy + 2) / 153);
+  let day = doy - Math.floor((153 * mp + 2) / 5) + 1;
+  let month = mp < 10 ? mp + 3 : mp - 9;
+  return [month <= 2 ? yoe + era * 400 + 1 : yoe + era * 400, month, day];
+};
+
+let daysInMonth = (year: number, month: number): number => {
+  if (month === 2) {
+    return year % 4 === 0 && (year % 100 !== 0 || year % 400 === 0) ? 29 : 28;
+  }
+  return month === 4 || month === 6 || month === 9 || month === 11 ? 30 : 31;
+};
+
+let toFields = (time: number, offset: number): TimeFields => {
+  let local = time + offset * MS_PER_MINUTE;
+  let [year, month, day] = civilFromDays(Math.floor(local / MS_PER_DAY));
+  let rest = mod(local, MS_PER_DAY);
+  return {
+    year,
+    month,
+    day,
+    hour: Math.floor(rest / 3600000),
+    minute: Math.floor(rest / 60000) % 60,
+    second: Math.floor(rest / 1000) % 60,
+    ms: rest % 1000,
+    offset,
+  };
+};
+
+let fromFields = (t: TimeFields): number => {
+  let days = daysFromCivil(t.year, t.month, t.day);
+  return days * MS_PER_DAY + ((t.hour * 60 + t.minute) * 60 + t.second) * 1000 + t.ms - t.offset * MS_PER_MINUTE;
+};
+
+/** computed time out of range fails, same as Rust side */
+let timeResult = (procName: string, time: number): number => {
+  if (Number.isFinite(time) && Math.abs(time) <= MAX_TIME) {
+    return time;
+  }
+  throw new Error(`${procName} got time out of range of ±8.64e15 ms`);
+};
+
+let readTime = (procName: string, x: CalcitValue): number => {
+  if (typeof x === "number" && Number.isFinite(x)) {
+    if (Math.abs(x) > MAX_TIME) {
+      throw new Error(`${procName} expected time within ±8.64e15 ms, got: ${x.toExponential()}`);
+    }
+    return Math.floor(x);
+  }
+  throw new Error(`${procName} expected time in milliseconds, got: ${toString(x, true)}`);
+};
+
+/** offset in minutes, defaults to UTC */
+let readOffset = (procName: string, x: CalcitValue): number => {
+  if (x == null) {
+    return 0;
+  }
+  if (typeof x === "number" && Number.isInteger(x) && Math.abs(x) < 1440) {
+    return x;
+  }
+  throw new Error(`${procName} expected offset in minutes, got: ${toString(x, true)}`);
+};
+
+export let now_ms = (): number => {
+  return Date.now();
+};
+
+/** microseconds at most, depending on precision of `performance.now()` */
+export let now_ns = (): bigint => {
+  return BigInt(Math.floor((performance.timeOrigin + performance.now()) * 1000)) * 1000n;
+};
+
+let pad = (n: number, width: number): string => {
+  return `${n}`.padStart(width, "0");
+};
+
+let formatOffset = (offset: number): string => {
+  if (offset === 0) {
+    return "Z";
+  }
+  let sign = offset < 0 ? "-" : "+";
+  return `${sign}${pad(Math.floor(Math.abs(offset) / 60), 2)}:${pad(Math.abs(offset) % 60, 2)}`;
+};
+
+let formatYear = (year: number): string => {
+  if (year >= 0 && year <= 9999) {
+    return pad(year, 4);
+  }
+  return year < 0 ? `-${pad(-year, 6)}` : `+${pad(year, 6)}`;
+};
+
+export let format_time = (time: number, offset: number = null): string => {
+  let t = toFields(readTime("format-time", time), readOffset("format-time", offset));
+  return `${formatYear(t.year)}-${pad(t.month, 2)}-${pad(t.day, 2)}T${pad(t.hour, 2)}:${pad(t.minute, 2)}:${pad(t.second, 2)}.${pad(
+    t.ms,
+    3
+  )}${formatOffset(t.offset)}`;
+};
+
+let rfc3339Pattern =
+  /^([+-]\d{6}|\d{4})-(\d{2})-(\d{2})(?:[Tt ](\d{2}):(\d{2})(?::(\d{2})(?:\.(\d+))?)?([Zz]|[+-]\d{2}:\d{2})?)?$/;
+
+let parseRfc3339 = (s: string): TimeFields => {
+  let m = rfc3339Pattern.exec(s.trim());
+  if (m == null) {
+    return null;
+  }
+  let t: TimeFields = {
+    year: parseInt(m[1], 10),
+    month: parseInt(m[2], 10),
+    day: parseInt(m[3], 10),
+    hour: m[4] != null ? parseInt(m[4], 10) : 0,
+    minute: m[5] != null ? parseInt(m[5], 10) : 0,
+    second: m[6] != null ? parseInt(m[6], 10) : 0,
+    // digits after milliseconds are dropped
+    ms: m[7] != null ? parseInt(m[7].slice(0, 3).padEnd(3, "0"), 10) : 0,
+    offset: 0,
+  };
+  if (t.month < 1 || t.month > 12 || t.day < 1 || t.day > daysInMonth(t.year, t.month)) {
+    return null;
+  }
+  if (t.hour > 23 || t.minute > 59 || t.second > 59) {
+    return null;
+  }
+  if (m[8] != null && m[8] !== "Z" && m[8] !== "z") {
+    let hours = parseInt(m[8].slice(1, 3), 10);
+    let minutes = parseInt(m[8].slice(4, 6), 10);
+    if (hours > 23 || minutes > 59) {
+      return null;
+    }
+    t.offset = (m[8][0] === "-" ? -1 : 1) * (hours * 60 + minutes);
+  }
+  return t;
+};
+
+export let parse_time = (s: string): number => {
+  if (typeof s !== "string") {
+    throw new Error(`parse-time expected a string, got: ${toString(s, true)}`);
+  }
+  let t = parseRfc3339(s);
+  if (t == null) {
+    throw new Error(`parse-time expected time in RFC 3339, got: ${s}`);
+  }
+  return timeResult("parse-time", fromFields(t));
+};
+
+export let time_fields = (time: number, offset: number = null): CalcitSliceMap => {
+  let ms = readTime("time-fields", time);
+  let t = toFields(ms, readOffset("time-fields", offset));
+  // 1970-01-01 is a Thursday
+  let weekday = mod(Math.floor((ms + t.offset * MS_PER_MINUTE) / MS_PER_DAY) + 3, 7) + 1;
+  return new CalcitSliceMap([
+    newTag("year"),
+    t.year,
+    newTag("month"),
+    t.month,
+    newTag("day"),
+    t.day,
+    newTag("hour"),
+    t.hour,
+    newTag("minute"),
+    t.minute,
+    newTag("second"),
+    t.second,
+    newTag("ms"),
+    t.ms,
+    newTag("weekday"),
+    weekday,
+    newTag("offset"),
+    t.offset,
+  ]);
+};
+
+export let time_from_fields = (fields: CalcitValue): number => {
+  if (!(fields instanceof CalcitMap || fields instanceof CalcitSliceMap)) {
+    throw new Error(`time-from-fields expected a map, got: ${toString(fields, true)}`);
+  }
+  let read = (name: string, defaultValue: number): number => {
+    let v = fields.get(newTag(name));
+    if (v == null) {
+      if (defaultValue == null) {
+        throw new Error(`time-from-fields expected :${name} in fields`);
+      }
+      return defaultValue;
+    }
+    if (typeof v === "number" && Number.isInteger(v)) {
+      return v;
+    }
+    throw new Error(`time-from-fields expected an integer for :${name}, got: ${toString(v, true)}`);
+  };
+  let t: TimeFields = {
+    year: read("year", null),
+    month: read("month", 1),
+    day: read("day", 1),
+    hour: read("hour", 0),
+    minute: read("minute", 0),
+    second: read("second", 0),
+    ms: read("ms", 0),
+    offset: read("offset", 0),
+  };
+  // months out of range turn into years, days are carried by day count
+  t.year += Math.floor((t.month - 1) / 12);
+  t.month = mod(t.month - 1, 12) + 1;
+  return timeResult("time-from-fields", fromFields(t));
+};
+
+let unitSteps = new Map<string, number>([
+  ["ms", 1],
+  ["second", 1000],
+  ["minute", MS_PER_MINUTE],
+  ["hour", 3600000],
+  ["day", MS_PER_DAY],
+  ["week", 7 * MS_PER_DAY],
+]);
+
+export let time_add = (time: number, n: number, unit: CalcitTag, offset: number = null): number => {
+  let ms = readTime("time-add", time);
+  if (typeof n !== "number") {
+    throw new Error(`time-add expected a number to add, got: ${toString(n, true)}`);
+  }
+  if (!(unit instanceof CalcitTag)) {
+    throw new Error(`time-add expected a unit in tag, got: ${toString(unit, true)}`);
+  }
+  let name = unit.value;
+  if (name === "month" || name === "year") {
+    if (!Number.isInteger(n)) {
+      throw new Error(`time-add expected an integer of ${name}s, got: ${n}`);
+    }
+    let months = name === "year" ? n * 12 : n;
+    let t = toFields(ms, readOffset("time-add", offset));
+    let total = t.year * 12 + t.month - 1 + months;
+    t.year = Math.floor(total / 12);
+    t.month = mod(total, 12) + 1;
+    t.day = Math.min(t.day, daysInMonth(t.year, t.month));
+    return timeResult("time-add", fromFields(t));
+  }
+  let step = unitSteps.get(name);
+  if (step == null) {
+    throw new Error(`time-add expected a unit of time, got: :${name}`);
+  }
+  return timeResult("time-add", Math.floor(ms + n * step));
+};
@@ -0,0 +1,155 @@
+import { CalcitValue, _$n_compare } from "./js-primes.mjs";
+import { CalcitList, CalcitSliceList } from "./js-list.mjs";
+import { CalcitRecord } from "./js-record.mjs";
+import { CalcitMap, CalcitSliceMap } from "./js-map.mjs";
+import { CalcitSet } from "./js-set.mjs";
+import { CalcitTag, newTag, toString } from "./calcit-data.mjs";
+
+/** reads a bool from options map, missing options are false */
+let readOption = (options: CalcitValue, name: string): boolean => {
+  if (options == null) {
+    return false;
+  }
+  if (options instanceof CalcitMap || options instanceof CalcitSliceMap) {
+    let v = options.get(newTag(name));
+    if (v == null) {
+      return false;
+    }
+    if (typeof v === "boolean") {
+      return v;
+    }
+    throw new Error(`expected a bool for option :${name}, got: ${toString(v, true)}`);
+  }
+  throw new Error(`expected options in a map, got: ${toString(options, true)}`);
+};
+
+let jsonToCalcit = (x: any, tagKeys: boolean): CalcitValue => {
+  if (x == null) {
+    return null;
+  }
+  if (typeof x === "boolean" || typeof x === "number" || typeof x === "string") {
+    return x;
+  }
+  if (Array.isArray(x)) {
+    return new CalcitSliceList(x.map((y) => jsonToCalcit(y, tagKeys)));
+  }
+  let chunk: CalcitValue[] = [];
+  for (let k of Object.keys(x)) {
+    chunk.push(tagKeys ? newTag(k) : k, jsonToCalcit(x[k], tagKeys));
+  }
+  return new CalcitSliceMap(chunk);
+};
+
+/** objects are turned into maps with string keys, or tag keys with `:tag-keys` option */
+export let parse_json = (s: string, options: CalcitValue = null): CalcitValue => {
+  if (typeof s !== "string") {
+    throw new Error(`parse-json expected a string, got: ${toString(s, true)}`);
+  }
+  let tagKeys = readOption(options, "tag-keys");
+  return jsonToCalcit(JSON.parse(s), tagKeys);
+};
+
+/** options `:pretty` and `:sort-keys` */
+export let format_json = (x: CalcitValue, options: CalcitValue = null): string => {
+  let pretty = readOption(options, "pretty");
+  let sortKeys = readOption(options, "sort-keys");
+  return writeJson(x, pretty, sortKeys, 0);
+};
+
+let writeIndent = (pretty: boolean, depth: number): string => {
+  return pretty ? "\n" + "  ".repeat(depth) : "";
+};
+
+let jsonKey = (x: CalcitValue): string => {
+  if (typeof x === "string") {
+    return x;
+  }
+  if (x instanceof CalcitTag) {
+    return x.value;
+  }
+  throw new Error(`JSON object keys should be strings or tags, got: ${toString(x, true)}`);
+};
+
+let writeArray = (xs: CalcitValue[], pretty: boolean, sortKeys: boolean, depth: number): string => {
+  if (xs.length === 0) {
+    return "[]";
+  }
+  let buf = "[";
+  for (let idx = 0; idx < xs.length; idx++) {
+    if (idx > 0) {
+      buf += ",";
+    }
+    buf += writeIndent(pretty, depth + 1) + writeJson(xs[idx], pretty, sortKeys, depth + 1);
+  }
+  return buf + writeIndent(pretty, depth) + "]";
+};
+
+let writeObject = (pairs: [string, CalcitValue][], pretty: boolean, sortKeys: boolean, depth: number): string => {
+  if (pairs.length === 0) {
+    return "{}";
+  }
+  if (sortKeys) {
+    pairs.sort((a, b) => (a[0] < b[0] ? -1 : a[0] > b[0] ? 1 : 0));
+  }
+  let buf = "{";
+  for (let idx = 0; idx < pairs.length; idx++) {
+    if (idx > 0) {
+      buf += ",";
+    }
+    buf += writeIndent(pretty, depth + 1) + JSON.stringify(pairs[idx][0]) + (pretty ? ": " : ":");
+    buf += writeJson(pairs[idx][1], pretty, sortKeys, depth + 1);
+  }
+  return buf + writeIndent(pretty, depth) + "}";
+};
+
+let writeJson = (x: CalcitValue, pretty: boolean, sortKeys: boolean, depth: number): string => {
+  if (x == null) {
+    return "null";
+  }
+  if (typeof x === "boolean") {
+    return x.toString();
+  }
+  if (typeof x === "number") {
+    if (!isFinite(x)) {
+      throw new Error(`format-json failed, JSON does not support number: ${x}`);
+    }
+    return JSON.stringify(x);
+  }
+  if (typeof x === "string") {
+    return JSON.stringify(x);
+  }
+  if (x instanceof CalcitTag) {
+    return JSON.stringify(x.value);
+  }
+  if (x instanceof CalcitList || x instanceof CalcitSliceList) {
+    return writeArray(x.toArray(), pretty, sortKeys, depth);
+  }
+  if (x instanceof CalcitSet) {
+    // sets are unordered, sorted for stable output
+    return writeArray(x.values().sort(_$n_compare), pretty, sortKeys, depth);
+  }
+  if (x instanceof CalcitMap || x instanceof CalcitSliceMap) {
+    let pairs: [string, CalcitValue][] = x.pairs().map(([k, v]) => [jsonKey(k), v]);
+    // a string key and a tag key of the same name would be written twice
+    let seen = new Set<string>();
+    for (let [k] of pairs) {
+      if (seen.has(k)) {
+        throw new Error(`format-json failed, JSON object has duplicated key from a string and a tag: ${JSON.stringify(k)}`);
+      }
+      seen.add(k);
+    }
+    return writeObject(pairs, pretty, sortKeys, depth);
+  }
+  if (x instanceof CalcitRecord) {
+    return writeObject(
+      x.fields.map((f, idx) => [f.value, x.values[idx]]),
+      pretty,
+      sortKeys,
+      depth
+    );
+  }
+  if (typeof x === "bigint") {
+    throw new Error(`format-json failed, JSON does not support bigint, turn it into a string first: ${x}n`);
+  }
+  throw new Error(`format-json failed, JSON does not support value: ${toString(x, true)}`);
+};
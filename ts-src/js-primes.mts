@@ -10,6 +10,7 @@ import { CalcitCirruQuote, cirru_deep_equal } from "./js-cirru.mjs";
 export type CalcitValue =
   | string
   | number
+  | bigint
   | boolean
   | CalcitMap
   | CalcitSliceMap
@@ -31,6 +32,7 @@ export let isLiteral = (x: CalcitValue): boolean => {
   if (typeof x == "string") return true;
   if (typeof x == "boolean") return true;
   if (typeof x == "number") return true;
+  if (typeof x == "bigint") return true;
   if (x instanceof CalcitTag) return true;
   if (x instanceof CalcitSymbol) return true;
   return false;
@@ -40,6 +42,7 @@ enum PseudoTypeIndex {
   nil,
   bool,
   number,
+  bigint,
   symbol,
   tag,
   string,
@@ -60,6 +63,7 @@ let typeAsInt = (x: CalcitValue): number => {
   let t = typeof x;
   if (t === "boolean") return PseudoTypeIndex.bool;
   if (t === "number") return PseudoTypeIndex.number;
+  if (t === "bigint") return PseudoTypeIndex.bigint;
   if (x instanceof CalcitSymbol) return PseudoTypeIndex.symbol;
   if (x instanceof CalcitTag) return PseudoTypeIndex.tag;
   if (t === "string") return PseudoTypeIndex.string;
@@ -90,6 +94,10 @@ export let _$n_compare = (a: CalcitValue, b: CalcitValue): number => {
   if (a === b) return 0;
   let ta = typeAsInt(a);
   let tb = typeAsInt(b);
+  if ((ta === PseudoTypeIndex.number && tb === PseudoTypeIndex.bigint) || (ta === PseudoTypeIndex.bigint && tb === PseudoTypeIndex.number)) {
+    // compared by values, number goes first when values are equal
+    return rawCompare(a, b) || rawCompare(ta, tb);
+  }
   if (ta === tb) {
     switch (ta) {
       case PseudoTypeIndex.nil:
@@ -98,6 +106,8 @@ export let _$n_compare = (a: CalcitValue, b: CalcitValue): number => {
         return rawCompare(a, b);
       case PseudoTypeIndex.number:
         return rawCompare(a, b);
+      case PseudoTypeIndex.bigint:
+        return rawCompare(a, b);
       case PseudoTypeIndex.tag:
         return rawCompare((a as CalcitTag).value, (b as CalcitTag).value);
       case PseudoTypeIndex.symbol:
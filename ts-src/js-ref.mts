@@ -6,11 +6,14 @@ export class CalcitRef {
   value: CalcitValue;
   path: string;
   listeners: Map<CalcitValue, CalcitFn>;
+  /** called with `new prev` before each change, a falsy result rejects it */
+  validator: CalcitFn;
   cachedHash: Hash;
   constructor(x: CalcitValue, path: string) {
     this.value = x;
     this.path = path;
     this.listeners = new Map();
+    this.validator = null;
     this.cachedHash = null;
   }
   toString(): string {